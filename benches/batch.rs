@@ -0,0 +1,61 @@
+//! Batched transforms and bounds against the scalar path of one vector at a time.
+
+use sinsha::linear_algebra::{ XYZ, Quat, Mat4, Aabb, Transform };
+use sinsha::linear_algebra::batch;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+const LENGTHS: [usize; 4] = [16, 1024, 65536, 1 << 20];
+/// Vectors processed by each measurement, over repeated runs.
+const TOTAL: usize = 1 << 24;
+
+fn main() {
+    let m = Transform::new(
+        XYZ::new(1.0, -2.0, 3.0),
+        Quat::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), 0.7),
+        XYZ::new(1.5, 1.5, 1.5),
+    ).to_matrix();
+
+    println!("{:>8} {:>22} {:>12} {:>12}", "length", "", "batch", "scalar");
+    LENGTHS.iter().for_each(|&len| {
+        let points = (0..len)
+            .map(|i| XYZ::new(i as f32, (i as f32 * 0.1).sin(), -(i as f32)))
+            .collect::<Vec<_>>();
+        let mut out = vec![XYZ::default(); len];
+
+        let batch = measure(len, || batch::transform_points(&m, black_box(&points), &mut out));
+        let scalar = measure(len, || scalar_transform_points(&m, black_box(&points), &mut out));
+        report(len, "transform_points", batch, scalar);
+
+        let batch = measure(len, || { black_box(batch::bounding_box(black_box(&points))); });
+        let scalar = measure(len, || { black_box(Aabb::from_points(black_box(&points).iter().cloned())); });
+        report(len, "bounding_box", batch, scalar);
+    });
+}
+
+fn scalar_transform_points(m: &Mat4, points: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+    points.iter().zip(out.iter_mut()).for_each(|(p, o)| *o = m.transform_point(*p));
+    black_box(out);
+}
+
+/// Mean nanoseconds per vector, running `f` over `len` vectors until `TOTAL` vectors.
+fn measure<F: FnMut()>(len: usize, mut f: F) -> f64 {
+    // Warm up caches and the detection of CPU features.
+    f();
+    let runs = (TOTAL / len).max(1);
+    let start = Instant::now();
+    (0..runs).for_each(|_| f());
+    start.elapsed().as_secs_f64() * 1e9 / (runs * len) as f64
+}
+
+fn report(len: usize, name: &str, batch: f64, scalar: f64) {
+    println!(
+        "{:>8} {:>22} {:>9.3} ns {:>9.3} ns  x{:.2}",
+        len,
+        name,
+        batch,
+        scalar,
+        scalar / batch,
+    );
+}
@@ -0,0 +1,83 @@
+//! CPU time of recording the G-Buffer subpass against the number of worker threads.
+//!
+//! Every frame draws the same cube `DRAWS` times in chunks of `DRAWS_PER_CHUNK`, and only
+//! `Render::execute_g_buffer` is timed, so the result is the time to record and gather the
+//! secondary command buffers.
+
+use ash::vk;
+
+use sinsha::linear_algebra::XYZ;
+use sinsha::vulkan::Vulkan;
+use sinsha::vulkan::render::{ Render, Projection };
+use sinsha::graphics::{ self, Graphics, Mesh, DrawCommand };
+
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+const DRAWS: usize = 16384;
+const DRAWS_PER_CHUNK: usize = 256;
+const WARM_UP_FRAMES: usize = 10;
+const FRAMES: usize = 100;
+const WORKER_COUNTS: [usize; 5] = [1, 2, 4, 8, 16];
+
+fn main() {
+    let (window, _events_loop) = sinsha::window::create_window();
+    let vulkan = Vulkan::new(window);
+    println!("{} draws in chunks of {}, mean of {} frames", DRAWS, DRAWS_PER_CHUNK, FRAMES);
+
+    WORKER_COUNTS.iter().for_each(|&worker_count| {
+        let mean = unsafe { bench(&vulkan, worker_count) };
+        println!("{:>2} workers: {:>8.3} ms", worker_count, mean.as_secs_f64() * 1000.0);
+    });
+}
+
+/// Mean time of `execute_g_buffer` with `worker_count` threads.
+unsafe fn bench(vulkan: &Vulkan, worker_count: usize) -> Duration {
+    let mut render = Render::with_worker_count(vulkan, worker_count);
+    let shader = render.load_mesh::<graphics::Vertex>(vulkan, Projection::Perspective).unwrap();
+    let white = image_crate::RgbaImage::from_pixel(1, 1, image_crate::Rgba([255; 4]));
+    let mut cube = None;
+
+    let mut total = Duration::default();
+    for frame in 0..WARM_UP_FRAMES + FRAMES {
+        let (framebuffer_index, mut command_buffer) = render.begin_frame(vulkan).expect("the window is minimized");
+        let cube = cube.get_or_insert_with(|| {
+            let mesh = Mesh::cube(XYZ::new(1.0, 1.0, 1.0));
+            let mut cube = Graphics::new(vulkan, &mut render, command_buffer.handle(), &shader, &mesh, &white);
+            cube.finish_upload(&mut render);
+            cube
+        });
+        let chunks = (0..DRAWS)
+            .step_by(DRAWS_PER_CHUNK)
+            .map(|start| start..DRAWS.min(start + DRAWS_PER_CHUNK))
+            .collect::<Vec<_>>();
+
+        render.begin_render_pass(&mut command_buffer, framebuffer_index, vk::SubpassContents::SECONDARY_COMMAND_BUFFERS);
+        let start = Instant::now();
+        render.execute_g_buffer(
+            vulkan,
+            &mut command_buffer,
+            framebuffer_index,
+            &shader,
+            chunks,
+            |draws, recorder| draws.for_each(|_| cube.draw(recorder)),
+        );
+        if WARM_UP_FRAMES <= frame {
+            total += start.elapsed();
+        }
+        // Nothing is drawn in the lighting and GUI subpasses.
+        command_buffer
+            .next_subpass(vk::SubpassContents::INLINE)
+            .next_subpass(vk::SubpassContents::INLINE)
+            .end_render_pass();
+        render.end_frame(vulkan, framebuffer_index, command_buffer);
+    }
+
+    if let Some(cube) = cube {
+        cube.retire(&mut render);
+    }
+    render.retire(Arc::new(shader));
+    render.destroy(vulkan);
+
+    total / FRAMES as u32
+}
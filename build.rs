@@ -1,51 +1,90 @@
-use std::process::Command;
-use std::path::{ PathBuf };
+#[path = "src/vulkan/render/compile.rs"]
+mod compile;
+
+use compile::CompileErr;
+
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+/// Macros defined in all shaders, e.g. `SHADER_DEFINES="DEBUG_NORMALS,CASCADES=4"`.
+const DEFINES_VAR: &str = "SHADER_DEFINES";
 
 fn main() {
     let shader_root = PathBuf::from("src/vulkan/render/");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let defines = compile::parse_defines(&env::var(DEFINES_VAR).unwrap_or_default());
 
-    let shaders = [
-        "dim3",
-        "lighting",
-        "gui_rect_2d",
-        "gui_3d",
-    ];
+    println!("cargo:rerun-if-env-changed={}", DEFINES_VAR);
+    println!("cargo:rerun-if-changed={}", shader_root.join(compile::INCLUDE_DIR).display());
+    println!("cargo:rerun-if-changed=src/vulkan/render/compile.rs");
 
-    shaders
+    compile::discover(&shader_root)
+        .unwrap()
         .iter()
-        .map(|shader| {
-            let mut glsl_vert = shader_root.clone();
-            glsl_vert.push(shader);
-            glsl_vert.push("glsl.vert");
-
-            let mut glsl_frag = shader_root.clone();
-            glsl_frag.push(shader);
-            glsl_frag.push("glsl.vert");
-
-            let mut vert_spv = shader_root.clone();
-            vert_spv.push(shader);
-            vert_spv.push("vert.spv");
-
-            let mut frag_spv = shader_root.clone();
-            frag_spv.push(shader);
-            frag_spv.push("frag.spv");
-
-            [glsl_vert, glsl_frag, vert_spv, frag_spv]
-        })
-        .for_each(|[glsl_vert, glsl_frag, vert_spv, frag_spv]| {
-            [[glsl_vert, vert_spv], [glsl_frag, frag_spv]].iter()
-                .for_each(|[glsl, spv]| {
-                    let output = Command::new("glslangValidator")
-                        .args(&["-V", glsl.to_str().unwrap(), "-o", spv.to_str().unwrap()])
-                        .output()
-                        .unwrap();
-
-                    println!(
-                        "\u{001b}[33;1mSPIR-V Compile:\u{001b}[m {}\n{}",
-                        output.status,
-                        String::from_utf8(output.stdout).unwrap()
-                    );
-                    assert!(output.status.success());
-                })
+        .for_each(|source| {
+            println!("cargo:rerun-if-changed={}", source.path.display());
+            compile::dependencies(&source.path, &shader_root)
+                .iter()
+                .for_each(|path| println!("cargo:rerun-if-changed={}", path.display()));
+
+            println!("cargo:rerun-if-changed={}", source.checked_in().display());
+            println!("cargo:rerun-if-changed={}", source.stamp().display());
+
+            let dir = out_dir.join(&source.shader);
+            fs::create_dir_all(&dir).unwrap();
+            let output = dir.join(source.spv_name);
+
+            let hash = compile::source_hash(source, &shader_root)
+                .unwrap_or_else(|err| panic!("{}: {:?}", source.path.display(), err));
+            let fresh = fs::read_to_string(source.stamp())
+                .ok()
+                .and_then(|stamp| u64::from_str_radix(stamp.trim(), 16).ok())
+                == Some(hash);
+
+            match compile::compile(source, &shader_root, &defines[..]) {
+                Ok(spirv) => {
+                    // Keep the checked-in SPIR-V up to date for builds without a compiler.
+                    if defines.is_empty() && !fresh {
+                        fs::write(source.checked_in(), &spirv).unwrap();
+                        fs::write(source.stamp(), format!("{:016x}\n", hash)).unwrap();
+                    }
+                    fs::write(&output, spirv).unwrap();
+                },
+                Err(CompileErr::NoCompiler) => fall_back(source, &output, fresh, &defines[..]),
+                Err(CompileErr::Failed(log)) => {
+                    panic!("failed to compile {}:\n{}", source.path.display(), log)
+                },
+                Err(CompileErr::Io(err)) => panic!("{}: {}", source.path.display(), err),
+            }
         });
-}
\ No newline at end of file
+}
+
+/// Use the SPIR-V checked in next to the source. It must have been compiled from the current
+/// sources, without defines.
+fn fall_back(source: &compile::Source, output: &Path, fresh: bool, defines: &[(String, String)]) {
+    let checked_in = source.checked_in();
+    if !defines.is_empty() {
+        panic!(
+            "no shader compiler found to compile {} with {}. Install glslangValidator or \
+             enable the `shaderc` feature.",
+            source.path.display(),
+            DEFINES_VAR,
+        );
+    }
+    if !fresh {
+        panic!(
+            "no shader compiler found and {} is older than {} or files it includes. Install \
+             glslangValidator or enable the `shaderc` feature, which also updates it.",
+            checked_in.display(),
+            source.path.display(),
+        );
+    }
+    fs::copy(&checked_in, output).unwrap_or_else(|_| {
+        panic!(
+            "no shader compiler found and {} does not exist. Install glslangValidator or \
+             enable the `shaderc` feature.",
+            checked_in.display(),
+        )
+    });
+}
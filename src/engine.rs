@@ -1,19 +1,314 @@
+use ash::vk;
 use winit::*;
 
+use crate::linear_algebra::*;
 use crate::vulkan::Vulkan;
+use crate::vulkan::command::{ CommandBuffer, Recording };
+use crate::vulkan::render::{ Render, Shader, Projection, CameraUniform, ViewFrustum, Light };
+use crate::graphics::{ self, Graphics, SkinnedGraphics, Mesh, DrawCommand };
+use crate::graphics::gltf::Primitive;
+use crate::graphics::animation::{ Skeleton, Joint, Clip, Channel, Property, Interpolation };
+use crate::input::{ InputDevices, OrbitController };
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Instant;
+
+mod overlay;
+
+use overlay::Overlay;
 
 pub fn run() {
-    let (mut window, mut events_loop) = crate::window::create_window();
+    let (window, mut events_loop) = crate::window::create_window();
+    let mut input = InputDevices::new(&window);
+    let vulkan = Vulkan::new(window);
+    let mut render = Render::new(&vulkan);
+    let mut scene: Option<Scene> = None;
 
     let mut loop_end = false;
+    while !loop_end {
+        events_loop.poll_events(|event| {
+            input.event_update(&event);
+            match event {
+                Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => loop_end = true,
+                Event::WindowEvent { event: WindowEvent::Resized(_), .. } => render.invalidate_swapchain(),
+                _ => (),
+            }
+        });
+
+        // Shaders are replaced between frames.
+        #[cfg(feature = "hot_reload")]
+        {
+            let changed = render.poll_shaders();
+            if let Some(scene) = &mut scene {
+                unsafe { scene.reload_shaders(&vulkan, &mut render, &changed[..]); }
+            }
+        }
+
+        unsafe {
+            // Nothing is drawn while the window is minimized.
+            if let Some((framebuffer_index, mut command_buffer)) = render.begin_frame(&vulkan) {
+                // Uploads are recorded into the first frame.
+                let scene = scene.get_or_insert_with(|| Scene::new(&vulkan, &mut render, &mut command_buffer));
+                scene.update(&input);
+                scene.record(&vulkan, &mut render, framebuffer_index, &mut command_buffer);
+                render.end_frame(&vulkan, framebuffer_index, command_buffer);
+            }
+        }
+        input.clear();
+    }
+
+    if let Some(scene) = scene {
+        scene.retire(&mut render);
+    }
+    // This also saves the pipeline cache for the next run.
+    unsafe { render.destroy(&vulkan); }
+}
 
-    let event_handler = |event| {
-        match event {
-            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => loop_end = true,
-            _ => ()
+/// Meshes lit by the sun, seen from a camera orbiting around them.
+struct Scene {
+    mesh_shader: Shader,
+    skinned_shader: Shader,
+    lighting_shader: Shader,
+    shadow_shader: Shader,
+    graphics: Vec<Graphics>,
+    /// Swayed by `sway` since `start`. It casts no shadow.
+    tail: SkinnedGraphics,
+    sway: Clip,
+    start: Instant,
+    overlay: Overlay,
+    /// Dragging with the right button rotates the camera, and the wheel zooms.
+    controller: OrbitController,
+    camera: Camera,
+    lens: Lens,
+}
+
+impl Scene {
+    /// Load pipelines and record uploads of the meshes into the command buffer of the frame.
+    unsafe fn new(vulkan: &Vulkan, render: &mut Render, command_buffer: &mut CommandBuffer<Recording>) -> Self {
+        let mesh_shader = render.load_mesh::<graphics::Vertex>(vulkan, Projection::Perspective).unwrap();
+        let skinned_shader = render.load_dim3_skinned(vulkan, Projection::Perspective).unwrap();
+        let lighting_shader = render.load_lighting(vulkan).unwrap();
+        let shadow_shader = render.load_shadow::<graphics::Vertex>(vulkan).unwrap();
+
+        let white = image_crate::RgbaImage::from_pixel(1, 1, image_crate::Rgba([255; 4]));
+        let placed = [
+            (Mesh::plane(XY::new(20.0, 20.0), XY::new(1, 1)), XYZ::new(0.0, 0.0, 0.0)),
+            (Mesh::icosphere(3), XYZ::new(0.0, 1.0, 0.0)),
+            (Mesh::cube(XYZ::new(1.0, 1.0, 1.0)), XYZ::new(2.5, 0.5, -1.0)),
+        ];
+        let graphics = placed
+            .iter()
+            .map(|(mesh, position)| {
+                let mut graphics = Graphics::new(
+                    vulkan,
+                    render,
+                    command_buffer.handle(),
+                    &mesh_shader,
+                    mesh,
+                    &white,
+                );
+                graphics.finish_upload(render);
+                graphics.transform = Transform::from_translation(*position);
+                graphics
+            })
+            .collect();
+        let (mut tail, sway) = Self::tail(vulkan, command_buffer.handle());
+        tail.finish_upload(render);
+        tail.transform = Transform::from_translation(XYZ::new(-2.5, 0.0, 1.0));
+        let overlay = Overlay::new(vulkan, render, command_buffer, &white);
+
+        let sun = Light::Directional {
+            direction: XYZ::new(-0.4, -1.0, -0.3),
+            color: XYZ::new(1.0, 0.95, 0.9),
+            intensity: 3.0,
         };
-    };
-    while !loop_end {
-        events_loop.poll_events(event_handler);
+        let sun = render.add_light(sun);
+        render.set_sun(sun);
+        render.set_ambient_light(XYZ::new(0.05, 0.05, 0.06));
+
+        let controller = OrbitController::new(XYZ::new(0.0, 1.0, 0.0), 8.0);
+        let camera = controller.camera();
+        let lens = Lens { fov_y: PI / 3.0, height: 10.0, near: 0.1, far: 100.0 };
+
+        Self {
+            mesh_shader,
+            skinned_shader,
+            lighting_shader,
+            shadow_shader,
+            graphics,
+            tail,
+            sway,
+            start: Instant::now(),
+            overlay,
+            controller,
+            camera,
+            lens,
+        }
+    }
+
+    /// Joints of the tail, from its base upward.
+    const TAIL_JOINTS: usize = 4;
+
+    /// A capsule standing on the origin, skinned to a chain of joints, and a clip which sways
+    /// it back and forth every 2 seconds.
+    unsafe fn tail(vulkan: &Vulkan, command_buffer: vk::CommandBuffer) -> (SkinnedGraphics, Clip) {
+        let (radius, height) = (0.3, 2.0);
+        let mut mesh = Mesh::capsule(radius, height, 16, 4);
+        mesh.vertices.iter_mut().for_each(|vertex| vertex.position.y += height * 0.5 + radius);
+        let spacing = (height + radius * 2.0) / (Self::TAIL_JOINTS - 1) as f32;
+
+        // Each vertex blends the two joints around its height.
+        let (joints, weights) = mesh.vertices
+            .iter()
+            .map(|vertex| {
+                let t = vertex.position.y / spacing;
+                let joint = (t.max(0.0) as usize).min(Self::TAIL_JOINTS - 2);
+                let weight = (t - joint as f32).clamp(0.0, 1.0);
+                ([joint as u16, joint as u16 + 1, 0, 0], XYZW::new(1.0 - weight, weight, 0.0, 0.0))
+            })
+            .unzip();
+        let primitive = Primitive { mesh, material: None, joints, weights };
+
+        let joints = (0..Self::TAIL_JOINTS)
+            .map(|joint| Joint {
+                name: None,
+                parent: joint.checked_sub(1),
+                rest: Transform::from_translation(XYZ::new(0.0, if joint == 0 { 0.0 } else { spacing }, 0.0)),
+                inverse_bind: Mat4::translation(XYZ::new(0.0, -spacing * joint as f32, 0.0)),
+            })
+            .collect();
+        let skeleton = Skeleton { joints, root: Mat4::IDENTITY };
+
+        let bend = |angle: f32| Quat::from_axis_angle(XYZ::new(0.0, 0.0, 1.0), angle).into();
+        let channels = (1..Self::TAIL_JOINTS)
+            .map(|joint| Channel {
+                joint,
+                property: Property::Rotation,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 1.0, 2.0],
+                values: vec![bend(-0.25), bend(0.25), bend(-0.25)],
+            })
+            .collect();
+        let sway = Clip::new(Some("sway".into()), channels).unwrap();
+
+        let white = XYZW::new(1.0, 1.0, 1.0, 1.0);
+        (SkinnedGraphics::new(vulkan, command_buffer, &primitive, skeleton, white), sway)
+    }
+
+    /// Reload the shaders made from any of `changed`, which are names of shaders with new
+    /// SPIR-V.
+    #[cfg(feature = "hot_reload")]
+    unsafe fn reload_shaders(&mut self, vulkan: &Vulkan, render: &mut Render, changed: &[String]) {
+        let uses = |names: &[&str]| changed.iter().any(|changed| names.contains(&changed.as_str()));
+        if uses(&["mesh"]) {
+            render.reload_shader(vulkan, &mut self.mesh_shader, |render| {
+                render.load_mesh::<graphics::Vertex>(vulkan, Projection::Perspective)
+            });
+        }
+        // The skinned shader shares the fragment stage of dim3.
+        if uses(&["dim3_skinned", "dim3"]) {
+            render.reload_shader(vulkan, &mut self.skinned_shader, |render| {
+                render.load_dim3_skinned(vulkan, Projection::Perspective)
+            });
+        }
+        if uses(&["lighting"]) {
+            render.reload_shader(vulkan, &mut self.lighting_shader, |render| render.load_lighting(vulkan));
+        }
+        if uses(&["shadow"]) {
+            render.reload_shader(vulkan, &mut self.shadow_shader, |render| {
+                render.load_shadow::<graphics::Vertex>(vulkan)
+            });
+        }
+        if uses(&["gui_rect_2d"]) {
+            self.overlay.reload_shader(vulkan, render);
+        }
+    }
+
+    /// Graphics drawn by a secondary command buffer of the G-Buffer subpass.
+    const GRAPHICS_PER_CHUNK: usize = 64;
+
+    /// Move the camera by the input since the last frame.
+    fn update(&mut self, input: &InputDevices) {
+        self.controller.update(input);
+        self.camera = self.controller.camera();
+        self.sway.sample_looped(self.start.elapsed().as_secs_f32(), &mut self.tail.pose);
+    }
+
+    /// Record the shadow pass and the main render pass of the frame. The G-Buffer and GUI
+    /// subpasses are recorded by the worker threads of the render.
+    unsafe fn record(
+        &self,
+        vulkan: &Vulkan,
+        render: &mut Render,
+        framebuffer_index: usize,
+        command_buffer: &mut CommandBuffer<Recording>,
+    ) {
+        let aspect = render.aspect();
+        let frustum = ViewFrustum {
+            position: self.camera.position(),
+            direction: self.camera.direction(),
+            up: self.camera.up(),
+            fov_y: self.lens.fov_y,
+            aspect,
+            near: self.lens.near,
+            far: self.lens.far,
+        };
+        render.upload_camera(framebuffer_index, &CameraUniform::new(&self.camera, &self.lens, aspect));
+        render.upload_lights(
+            framebuffer_index,
+            &CameraUniform::view_matrices(&self.camera, &self.lens, aspect, Projection::Perspective),
+        );
+        render.update_shadow(framebuffer_index, &frustum);
+        render.clear_joints(framebuffer_index);
+        let tail = self.tail.upload_pose(render, framebuffer_index);
+
+        render.record_shadow(command_buffer, &self.shadow_shader, |command_buffer, _cascade, light_space| {
+            self.graphics
+                .iter()
+                .for_each(|graphics| graphics.draw_shadow(command_buffer, &self.shadow_shader, light_space));
+        });
+
+        // Shadows of graphics out of the view may still be seen, so only the G-Buffer is culled.
+        let view = self.camera.frustum(&self.lens, aspect);
+        let visible = self.graphics
+            .iter()
+            .filter(|graphics| view.intersects_sphere(&graphics.bounding_sphere()))
+            .collect::<Vec<_>>();
+
+        render.begin_render_pass(command_buffer, framebuffer_index, vk::SubpassContents::SECONDARY_COMMAND_BUFFERS);
+        render.execute_g_buffer(
+            vulkan,
+            command_buffer,
+            framebuffer_index,
+            &self.mesh_shader,
+            visible.chunks(Self::GRAPHICS_PER_CHUNK).collect(),
+            |chunk, recorder| chunk.iter().for_each(|graphics| graphics.draw(recorder)),
+        );
+        // The tail is skipped if its joints do not fit in the frame.
+        if let Some(object) = tail {
+            render.execute_skinned(
+                vulkan,
+                command_buffer,
+                framebuffer_index,
+                &self.skinned_shader,
+                vec![&self.tail],
+                |tail, recorder| tail.draw(recorder, &object),
+            );
+        }
+        render.record_lighting(command_buffer, framebuffer_index, &self.lighting_shader);
+        let times = render.frame_times();
+        self.overlay.record(vulkan, render, framebuffer_index, command_buffer, &times);
+    }
+
+    /// Everything is destroyed after the frames in flight complete.
+    fn retire(self, render: &mut Render) {
+        self.graphics.into_iter().for_each(|graphics| graphics.retire(render));
+        self.tail.retire(render);
+        self.overlay.retire(render);
+        render.retire(Arc::new(self.mesh_shader));
+        render.retire(Arc::new(self.skinned_shader));
+        render.retire(Arc::new(self.lighting_shader));
+        render.retire(Arc::new(self.shadow_shader));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,229 @@
+//! Frame times drawn over the scene in the GUI subpass.
+
+use ash::vk;
+use image_crate::GenericImageView;
+
+use crate::linear_algebra::*;
+use crate::vulkan::Vulkan;
+use crate::vulkan::command::{ CommandBuffer, Recording };
+use crate::vulkan::render::{ Render, Shader, Texture, DescriptorWriter, GuiRect, FrameTimes };
+
+use std::sync::Arc;
+
+const FONT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/font/friz_quadrata.png");
+
+/// A line of text on a translucent panel at the top left.
+pub(super) struct Overlay {
+    shader: Shader,
+    /// A white pixel for the panel.
+    white: Arc<Texture>,
+    /// Set 0 of the shader, which binds the white texture.
+    white_set: vk::DescriptorSet,
+    font: Font,
+}
+
+/// Printable ASCII glyphs of an atlas in a grid of 16 x 16 square cells, where the character
+/// `c` is in the column `c % 16` and the row `c / 16`. Only the rows of printable characters
+/// are uploaded.
+struct Font {
+    texture: Arc<Texture>,
+    /// Set 0 of the shader, which binds the texture.
+    texture_set: vk::DescriptorSet,
+    /// Left and right of the glyphs in their cells, in fractions of the cell width.
+    /// `None` if the glyph is blank, e.g. the space.
+    extents: Vec<Option<(f32, f32)>>,
+}
+
+/// Rectangles drawn by a secondary command buffer with a texture.
+struct Batch<'a> {
+    texture: &'a Arc<Texture>,
+    texture_set: vk::DescriptorSet,
+    rects: &'a [GuiRect],
+}
+
+impl Overlay {
+    /// Height of the text in pixels.
+    const TEXT_HEIGHT: f32 = 24.0;
+    /// Between the panel and the text in pixels.
+    const PADDING: f32 = 6.0;
+    /// Glyphs drawn by a secondary command buffer of the GUI subpass.
+    const GLYPHS_PER_CHUNK: usize = 64;
+
+    /// Load the pipeline and record uploads of the textures into the command buffer of the
+    /// frame.
+    pub(super) unsafe fn new(
+        vulkan: &Vulkan,
+        render: &mut Render,
+        command_buffer: &mut CommandBuffer<Recording>,
+        white: &image_crate::RgbaImage,
+    ) -> Self {
+        let shader = render.load_gui_rect(vulkan).unwrap();
+        let (white, white_set) = upload_texture(vulkan, render, command_buffer, &shader, white);
+        let font = Font::load(vulkan, render, command_buffer, &shader);
+
+        Self { shader, white, white_set, font }
+    }
+
+    /// Replace the pipeline by the one of the latest SPIR-V. The descriptor sets are kept,
+    /// because the set layouts stay the same unless the bindings of the shader change.
+    #[cfg(feature = "hot_reload")]
+    pub(super) unsafe fn reload_shader(&mut self, vulkan: &Vulkan, render: &mut Render) {
+        render.reload_shader(vulkan, &mut self.shader, |render| render.load_gui_rect(vulkan));
+    }
+
+    /// Record the GUI subpass with `times`, which ends the render pass.
+    pub(super) fn record(
+        &self,
+        vulkan: &Vulkan,
+        render: &mut Render,
+        framebuffer_index: usize,
+        command_buffer: &mut CommandBuffer<Recording>,
+        times: &FrameTimes,
+    ) {
+        let origin = XY::new(8.0, 8.0);
+        let text_position = origin + XY::new(Self::PADDING, Self::PADDING);
+        let color = XYZW::new(1.0, 1.0, 1.0, 1.0);
+        let (glyphs, width) = self.font.layout(&times.to_string(), text_position, Self::TEXT_HEIGHT, color);
+
+        let panel_size = XY::new(width, Self::TEXT_HEIGHT) + XY::new(Self::PADDING, Self::PADDING) * 2.0;
+        let panel = [GuiRect::new(origin, panel_size, XYZW::new(0.0, 0.0, 0.0, 0.5))];
+
+        // Secondary command buffers are executed in the order of chunks, so the panel is
+        // drawn first.
+        let batches = std::iter::once(Batch { texture: &self.white, texture_set: self.white_set, rects: &panel })
+            .chain(glyphs.chunks(Self::GLYPHS_PER_CHUNK).map(|rects| Batch {
+                texture: &self.font.texture,
+                texture_set: self.font.texture_set,
+                rects,
+            }))
+            .collect();
+
+        render.execute_gui(
+            vulkan,
+            command_buffer,
+            framebuffer_index,
+            &self.shader,
+            batches,
+            |batch, recorder| {
+                recorder.bind_texture(batch.texture_set);
+                // The descriptor set refers to the texture.
+                recorder.command_buffer().keep(batch.texture);
+                batch.rects.iter().for_each(|rect| recorder.draw(rect));
+            },
+        );
+    }
+
+    pub(super) fn retire(self, render: &mut Render) {
+        render.retire(self.white);
+        render.retire(self.font.texture);
+        unsafe {
+            render.free_descriptor_set(self.white_set);
+            render.free_descriptor_set(self.font.texture_set);
+        }
+        render.retire(Arc::new(self.shader));
+    }
+}
+
+impl Font {
+    const COLUMNS: u32 = 16;
+    /// Rows from the space to the delete.
+    const ROWS: u32 = 6;
+    const FIRST: u8 = b' ';
+    /// Advance of blank glyphs in the text height.
+    const SPACE: f32 = 0.3;
+    /// Between glyphs in the text height.
+    const SPACING: f32 = 0.05;
+
+    unsafe fn load(
+        vulkan: &Vulkan,
+        render: &mut Render,
+        command_buffer: &mut CommandBuffer<Recording>,
+        shader: &Shader,
+    ) -> Self {
+        let mut atlas = image_crate::open(FONT_PATH).unwrap();
+        let cell = atlas.width() / Self::COLUMNS;
+        let first_row = u32::from(Self::FIRST) / Self::COLUMNS;
+        let image = atlas
+            .crop(0, first_row * cell, Self::COLUMNS * cell, Self::ROWS * cell)
+            .to_rgba();
+
+        let extents = (0..Self::COLUMNS * Self::ROWS)
+            .map(|index| {
+                let (left, top) = (index % Self::COLUMNS * cell, index / Self::COLUMNS * cell);
+                let inked = |x: u32| (top..top + cell).any(|y| image.get_pixel(left + x, y)[3] != 0);
+                let min = (0..cell).find(|x| inked(*x))?;
+                let max = (0..cell).rev().find(|x| inked(*x))?;
+                Some((min as f32 / cell as f32, (max + 1) as f32 / cell as f32))
+            })
+            .collect();
+
+        let (texture, texture_set) = upload_texture(vulkan, render, command_buffer, shader, &image);
+        Self { texture, texture_set, extents }
+    }
+
+    /// Glyphs of `text` from the top left `position`, with its width. Characters out of the
+    /// atlas are skipped.
+    fn layout(&self, text: &str, position: XY<f32>, height: f32, color: XYZW<f32>) -> (Vec<GuiRect>, f32) {
+        let columns = Self::COLUMNS as f32;
+        let rows = Self::ROWS as f32;
+        let mut x = position.x;
+
+        let glyphs = text
+            .bytes()
+            .filter_map(|c| c.checked_sub(Self::FIRST))
+            .filter_map(|index| {
+                let index = u32::from(index);
+                let extent = self.extents.get(index as usize)?;
+                let (left, right) = match extent {
+                    Some(extent) => *extent,
+                    None => {
+                        x += height * Self::SPACE;
+                        return None;
+                    }
+                };
+
+                let (column, row) = ((index % Self::COLUMNS) as f32, (index / Self::COLUMNS) as f32);
+                let width = (right - left) * height;
+                let glyph = GuiRect {
+                    position: XY::new(x, position.y),
+                    size: XY::new(width, height),
+                    texture: (
+                        XY::new((column + left) / columns, row / rows),
+                        XY::new((column + right) / columns, (row + 1.0) / rows),
+                    ),
+                    color,
+                };
+                x += width + height * Self::SPACING;
+                Some(glyph)
+            })
+            .collect();
+
+        (glyphs, x - position.x)
+    }
+}
+
+/// Record the upload of the image and bind it to a descriptor set of the shader.
+unsafe fn upload_texture(
+    vulkan: &Vulkan,
+    render: &mut Render,
+    command_buffer: &mut CommandBuffer<Recording>,
+    shader: &Shader,
+    image: &image_crate::RgbaImage,
+) -> (Arc<Texture>, vk::DescriptorSet) {
+    let (width, height) = image.dimensions();
+    let mut texture = Texture::new(
+        vulkan,
+        command_buffer.handle(),
+        vk::Extent2D { width, height },
+        Texture::COLOR_FORMAT,
+        image,
+    );
+    texture.finish_upload(render);
+
+    let texture_set = render.allocate_descriptor_set(vulkan, shader, 0);
+    DescriptorWriter::new(texture_set)
+        .combined_image_sampler(0, &texture, texture.sampler())
+        .write(vulkan);
+
+    (Arc::new(texture), texture_set)
+}
@@ -1,5 +1,12 @@
-use crate::graphics::Graphics;
+use crate::graphics::{ Graphics, DrawCommand };
+use crate::vulkan::render::Recorder;
 
 pub struct Field {
     graphics: Graphics,
-}
\ No newline at end of file
+}
+
+impl DrawCommand for Field {
+    fn draw(&self, recorder: &mut Recorder) {
+        self.graphics.draw(recorder);
+    }
+}
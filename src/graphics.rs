@@ -1,39 +1,235 @@
+use ash::vk;
+
 use crate::linear_algebra::*;
+use crate::vulkan::Vulkan;
+use crate::vulkan::command::{ CommandBuffer, Recording };
+use crate::vulkan::render::{ Render, Shader, MeshBuffer, Texture, Recorder, Object, ShadowObject, DescriptorWriter };
+use crate::vulkan::render::{ SkinnedVertex, SkinnedObject, SkinnedRecorder, MAX_JOINTS };
 
 use std::default::Default;
+use std::sync::Arc;
+
+mod mesh;
+pub mod gltf;
+pub mod animation;
+
+pub use mesh::Mesh;
 
+use animation::{ Skeleton, Pose };
 
+
+/// Objects which draw themselves in the G-Buffer subpass.
+/// The command buffer keeps their resources until it is reset.
 pub trait DrawCommand {
-    fn draw(&self, command: &mut ());
+    fn draw(&self, recorder: &mut Recorder);
 }
 
+/// A mesh with an albedo texture in device memory, placed by its transform.
 pub struct Graphics {
-    vertices: Vec<f32>,
-    texture: Vec<u8>,
+    mesh: Arc<MeshBuffer>,
+    texture: Arc<Texture>,
+    /// Set 1 of the mesh shader, which binds the texture.
+    material: vk::DescriptorSet,
+    /// Bounds of the mesh before the transform.
+    bounds: Sphere,
+    pub transform: Transform,
+}
+
+impl Graphics {
+    /// Record uploads of the mesh and the texture into the command buffer, and bind the
+    /// texture to a descriptor set of `shader`, which is `Render::load_mesh::<Vertex>`.
+    /// # Safety
+    /// Call `finish_upload` after the command buffer completes.
+    pub unsafe fn new(
+        vulkan: &Vulkan,
+        render: &mut Render,
+        command_buffer: vk::CommandBuffer,
+        shader: &Shader,
+        mesh: &Mesh,
+        texture: &image_crate::RgbaImage,
+    ) -> Self {
+        let bounds = Aabb::from_points(mesh.vertices.iter().map(|vertex| vertex.position)).bounding_sphere();
+        let mesh = MeshBuffer::new(vulkan, command_buffer, &mesh.vertices[..], &mesh.indices[..]);
+        let (width, height) = texture.dimensions();
+        let texture = Texture::new(
+            vulkan,
+            command_buffer,
+            vk::Extent2D { width, height },
+            Texture::COLOR_FORMAT,
+            texture,
+        );
+
+        let material = render.allocate_descriptor_set(vulkan, shader, 1);
+        DescriptorWriter::new(material)
+            .combined_image_sampler(0, &texture, texture.sampler())
+            .write(vulkan);
+
+        Self {
+            mesh: Arc::new(mesh),
+            texture: Arc::new(texture),
+            material,
+            bounds,
+            transform: Transform::IDENTITY,
+        }
+    }
+
+    /// Bounding sphere in world coordinates, e.g. for frustum culling.
+    pub fn bounding_sphere(&self) -> Sphere {
+        let scale = self.transform.scale;
+        let max_scale = scale.x.abs().max(scale.y.abs()).max(scale.z.abs());
+        Sphere::new(self.transform.transform_point(self.bounds.center), self.bounds.radius * max_scale)
+    }
+
+    /// Retire staging buffers.
+    /// # Safety
+    /// The command buffer passed to `new` must be the one of the frame being recorded, or have
+    /// completed. Nothing is drawn yet.
+    pub unsafe fn finish_upload(&mut self, render: &mut Render) {
+        Self::unique(&mut self.mesh).finish_upload(render);
+        Self::unique(&mut self.texture).finish_upload(render);
+    }
+
+    /// Record drawing the mesh into a shadow cascade with the pipeline of
+    /// `Render::load_shadow::<Vertex>`. See `Render::record_shadow`.
+    pub fn draw_shadow(&self, command_buffer: &mut CommandBuffer<Recording>, shader: &Shader, light_space: &Mat4) {
+        ShadowObject::new(light_space, &Mat4::from(&self.transform)).push(command_buffer, shader);
+        self.mesh.bind(command_buffer);
+        self.mesh.draw(command_buffer);
+    }
+
+    /// Retire the mesh, the texture and the descriptor set, which are destroyed after frames
+    /// using them complete.
+    pub fn retire(self, render: &mut Render) {
+        render.retire(self.mesh);
+        render.retire(self.texture);
+        // Nothing records the set after this, since it is moved out with `self`.
+        unsafe { render.free_descriptor_set(self.material) };
+    }
+
+    fn unique<T>(resource: &mut Arc<T>) -> &mut T {
+        Arc::get_mut(resource).expect("a command buffer still keeps the resource")
+    }
 }
 
 impl DrawCommand for Graphics {
-    fn draw(&self, command: &mut ()) {
-        unimplemented!()
+    fn draw(&self, recorder: &mut Recorder) {
+        recorder.bind_material(self.material);
+        // The descriptor set refers to the texture.
+        recorder.command_buffer().keep(&self.texture);
+        recorder.draw(&self.mesh, &Object::from(&self.transform));
     }
 }
 
+/// A mesh deformed by the pose of its skeleton in device memory, placed by its transform.
+pub struct SkinnedGraphics {
+    mesh: Arc<MeshBuffer>,
+    pub skeleton: Skeleton,
+    /// Sampled by clips of the skeleton, e.g. of `gltf::Model::skeleton`.
+    pub pose: Pose,
+    pub transform: Transform,
+}
+
+impl SkinnedGraphics {
+    /// Record the upload of the primitive in the color into the command buffer. Joints of
+    /// its vertices are joints of the skeleton, e.g. the one of its skin. Vertices of
+    /// primitives without joints follow the first joint. The pose starts at rest.
+    /// # Safety
+    /// Call `finish_upload` after the command buffer completes.
+    pub unsafe fn new(
+        vulkan: &Vulkan,
+        command_buffer: vk::CommandBuffer,
+        primitive: &gltf::Primitive,
+        skeleton: Skeleton,
+        color: XYZW<f32>,
+    ) -> Self {
+        let joint_count = skeleton.joints.len();
+        assert!(0 < joint_count && joint_count <= MAX_JOINTS, "Skeletons must have 1 to MAX_JOINTS joints.");
+        assert!(
+            primitive.joints.iter().flatten().all(|joint| (*joint as usize) < joint_count),
+            "Joints of vertices must be in the skeleton.",
+        );
+
+        let vertices = primitive.mesh.vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                let joints = primitive.joints.get(i).map_or([0; 4], |joints| *joints);
+                SkinnedVertex {
+                    position: vertex.position,
+                    normal: vertex.normal,
+                    color,
+                    joints: XYZW::new(joints[0] as u32, joints[1] as u32, joints[2] as u32, joints[3] as u32),
+                    weights: primitive.weights.get(i).map_or(XYZW::new(1.0, 0.0, 0.0, 0.0), |weights| *weights),
+                }
+            })
+            .collect::<Vec<_>>();
+        let mesh = MeshBuffer::new(vulkan, command_buffer, &vertices[..], &primitive.mesh.indices[..]);
+
+        Self {
+            mesh: Arc::new(mesh),
+            pose: skeleton.rest_pose(),
+            skeleton,
+            transform: Transform::IDENTITY,
+        }
+    }
+
+    /// Retire the staging buffer.
+    /// # Safety
+    /// The command buffer passed to `new` must be the one of the frame being recorded, or have
+    /// completed. Nothing is drawn yet.
+    pub unsafe fn finish_upload(&mut self, render: &mut Render) {
+        Graphics::unique(&mut self.mesh).finish_upload(render);
+    }
+
+    /// Upload the joint matrices of the pose for the frame rendered into the framebuffer, and
+    /// return the push constants for `draw`, or `None` if the joints of the frame are full.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub unsafe fn upload_pose(&self, render: &mut Render, framebuffer_index: usize) -> Option<SkinnedObject> {
+        let matrices = self.skeleton.joint_matrices(&self.pose);
+        render
+            .upload_joints(framebuffer_index, &matrices[..])
+            .map(|joint_offset| SkinnedObject::new(Object::from(&self.transform), joint_offset))
+    }
+
+    /// Record drawing the mesh with the pipeline of `Render::load_dim3_skinned`. `object` is
+    /// of `upload_pose` in the same frame. See `Render::execute_skinned`.
+    pub fn draw(&self, recorder: &mut SkinnedRecorder, object: &SkinnedObject) {
+        recorder.draw(&self.mesh, object);
+    }
+
+    /// Retire the mesh, which is destroyed after frames using it complete.
+    pub fn retire(self, render: &mut Render) {
+        render.retire(self.mesh);
+    }
+}
+
+#[repr(C)]
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Vertex {
     pub position: XYZ<f32>,
     pub normal: XYZ<f32>,
+    /// xyz: unit tangent along +u. w: 1.0 or -1.0, the sign of bitangent = cross(normal, tangent)
+    /// along +v.
+    pub tangent: XYZW<f32>,
+    /// (0, 0) is the top left of the texture.
+    pub uv: XY<f32>,
 }
 
+vertex_input!(Vertex { position, normal, tangent, uv });
+
 impl Vertex {
-    fn new(position: XYZ<f32>, normal: XYZ<f32>) -> Self {
-        Self { position, normal }
+    fn new(position: XYZ<f32>, normal: XYZ<f32>, uv: XY<f32>) -> Self {
+        Self { position, normal, tangent: XYZW::default(), uv }
     }
 }
 
+/// Unit icosahedron. Triangles are counterclockwise seen from the outside, and normals
+/// point outward from the center. UVs and tangents are left zero.
 pub fn regular_icosahedron() -> ([Vertex; 12], [u16; 60]) {
-    const PHI: f32 = 1.6180339887498948482;
+    const PHI: f32 = 1.618_034;
 
-    let norm = 1.0 + PHI * PHI;
+    let norm = (1.0 + PHI * PHI).sqrt();
     let regular_positions = [
         XYZ::new( 1.0,  PHI,  0.0) / norm,
         XYZ::new( 1.0, -PHI,  0.0) / norm,
@@ -53,12 +249,18 @@ pub fn regular_icosahedron() -> ([Vertex; 12], [u16; 60]) {
 
     vertices.iter_mut()
         .zip(regular_positions.iter())
-        .for_each(|(Vertex { position, normal }, xyz)| {
+        .for_each(|(Vertex { position, normal, .. }, xyz)| {
             *position = *xyz;
             *normal = *xyz;
         });
 
-    let indices = unimplemented!();
+    // 20 triangles.
+    let indices = [
+        0, 2, 4,    0, 5, 2,    0, 4, 8,    0, 10, 5,   0, 8, 10,
+        1, 6, 3,    1, 3, 7,    1, 8, 6,    1, 7, 10,   1, 10, 8,
+        2, 9, 4,    2, 5, 11,   2, 11, 9,   3, 6, 9,    3, 11, 7,
+        3, 9, 11,   4, 6, 8,    4, 9, 6,    5, 10, 7,   5, 7, 11,
+    ];
 
     (vertices, indices)
-}
\ No newline at end of file
+}
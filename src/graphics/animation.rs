@@ -0,0 +1,454 @@
+//! Skeletal animation on the CPU.
+//!
+//! A `Clip` is sampled into a `Pose` of local joint transforms, poses are crossfaded by
+//! `Pose::blend` and layered by `Pose::add`, and `Skeleton::joint_matrices` turns the
+//! result into matrices for `Render::upload_joints`.
+//!
+//! Keyframes follow glTF: rotations are unit quaternions (x, y, z, w), and cubic splines
+//! are Hermite splines with an in-tangent, a value and an out-tangent for each key.
+
+use crate::linear_algebra::*;
+
+/// Joints form a forest, in any order, e.g. the order of glTF skins which joint indices of
+/// vertices refer to.
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    /// Transform of the parent of root joints in model coordinates.
+    pub root: Mat4,
+}
+
+pub struct Joint {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    /// Local transform without animation.
+    pub rest: Transform,
+    /// From model coordinates of the mesh into the coordinates of the joint.
+    pub inverse_bind: Mat4,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+/// Keyframes of one property of one joint.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    /// Seconds in increasing order. At least one.
+    pub times: Vec<f32>,
+    /// A value for each time, or (in-tangent, value, out-tangent) for each time of cubic
+    /// splines. Translations and scales leave w zero.
+    pub values: Vec<XYZW<f32>>,
+}
+
+/// Build it by `new`, which validates the channels.
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub name: Option<String>,
+    /// Seconds until the last keyframe of all channels.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+/// Invalid channel of `Clip::new`, at the index of `channels`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ClipErr {
+    NoKeyframes { channel: usize },
+    /// Times are not finite and increasing.
+    UnorderedTimes { channel: usize },
+    /// The number of values doesn't match the times and the interpolation.
+    ValueCount { channel: usize },
+}
+
+/// Local transforms of joints, indexed like `Skeleton::joints`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub locals: Vec<Transform>,
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Pose {
+        Pose { locals: self.joints.iter().map(|joint| joint.rest).collect() }
+    }
+
+    /// Model coordinates of each joint in the pose.
+    pub fn global_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut globals: Vec<Option<Mat4>> = vec![None; self.joints.len()];
+        (0..self.joints.len()).for_each(|joint| {
+            // The joint and its ancestors without matrices yet, from the joint upward.
+            let mut chain = Vec::new();
+            let mut next = Some(joint);
+            while let Some(ancestor) = next.filter(|ancestor| globals[*ancestor].is_none()) {
+                assert!(chain.len() < self.joints.len(), "Joints must not form a cycle.");
+                chain.push(ancestor);
+                next = self.joints[ancestor].parent;
+            }
+
+            chain.iter().rev().for_each(|joint| {
+                let parent = self.joints[*joint].parent
+                    .map_or(self.root, |parent| globals[parent].unwrap());
+                globals[*joint] = Some(parent * pose.locals[*joint].to_matrix());
+            });
+        });
+        globals.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// Matrices which move vertices from the bind pose into the pose, in the layout of
+    /// `joints` of `dim3_skinned/glsl.vert`.
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        self.global_matrices(pose)
+            .iter()
+            .zip(self.joints.iter())
+            .map(|(global, joint)| *global * joint.inverse_bind)
+            .collect()
+    }
+}
+
+impl Channel {
+    /// Values for each key.
+    #[inline]
+    fn stride(&self) -> usize {
+        if self.interpolation == Interpolation::CubicSpline { 3 } else { 1 }
+    }
+
+    /// Value at the time, which is clamped into the keyframes.
+    /// `None` if there are no keyframes or the number of values doesn't match them.
+    pub fn sample(&self, time: f32) -> Option<XYZW<f32>> {
+        let stride = self.stride();
+        if self.times.is_empty() || self.values.len() != self.times.len() * stride {
+            return None;
+        }
+        // The value of key `i`.
+        let value = |i: usize| self.values[i * stride + stride / 2];
+
+        let last = self.times.len() - 1;
+        // The first key after the time.
+        let next = self.times.iter().position(|t| *t > time).unwrap_or(last + 1);
+        if next == 0 {
+            return Some(value(0));
+        }
+        if next > last {
+            return Some(value(last));
+        }
+
+        let previous = next - 1;
+        let delta = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / delta;
+
+        let value = match self.interpolation {
+            Interpolation::Step => value(previous),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                Quat::from(value(previous)).slerp(Quat::from(value(next)), t).into()
+            },
+            Interpolation::Linear => value(previous).lerp(value(next), t),
+            Interpolation::CubicSpline => {
+                let (t2, t3) = (t * t, t * t * t);
+                let out_tangent = self.values[previous * 3 + 2] * delta;
+                let in_tangent = self.values[next * 3] * delta;
+                let v = value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + value(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2);
+                if self.property == Property::Rotation {
+                    Quat::from(v).normalize().into()
+                } else {
+                    v
+                }
+            },
+        };
+        Some(value)
+    }
+
+    fn validate(&self, index: usize) -> Result<(), ClipErr> {
+        if self.times.is_empty() {
+            return Err(ClipErr::NoKeyframes { channel: index });
+        }
+        let increasing = self.times.iter().all(|t| t.is_finite())
+            && self.times.windows(2).all(|pair| pair[0] < pair[1]);
+        if !increasing {
+            return Err(ClipErr::UnorderedTimes { channel: index });
+        }
+        if self.values.len() != self.times.len() * self.stride() {
+            return Err(ClipErr::ValueCount { channel: index });
+        }
+        Ok(())
+    }
+}
+
+impl Clip {
+    /// The duration is the last keyframe of all channels.
+    pub fn new(name: Option<String>, channels: Vec<Channel>) -> Result<Self, ClipErr> {
+        channels
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, channel)| channel.validate(index))?;
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration: f32, time| duration.max(*time));
+        Ok(Self { name, duration, channels })
+    }
+
+    /// Overwrite the animated properties of the pose with their values at the time.
+    /// Properties without channels keep their values, so start from `Skeleton::rest_pose`.
+    /// Channels of joints which the pose doesn't have are skipped, and so are invalid ones.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        self.channels
+            .iter()
+            .for_each(|channel| {
+                let (value, local) = match (channel.sample(time), pose.locals.get_mut(channel.joint)) {
+                    (Some(value), Some(local)) => (value, local),
+                    _ => return,
+                };
+                match channel.property {
+                    Property::Translation => local.translation = value.truncate(),
+                    Property::Rotation => local.rotation = Quat::from(value),
+                    Property::Scale => local.scale = value.truncate(),
+                }
+            });
+    }
+
+    /// `sample` at the time wrapped into the duration.
+    pub fn sample_looped(&self, time: f32, pose: &mut Pose) {
+        let time = if self.duration > 0.0 { time.rem_euclid(self.duration) } else { 0.0 };
+        self.sample(time, pose);
+    }
+
+    /// Map channels onto other joints, e.g. from glTF nodes onto joints of a skeleton.
+    /// Channels whose joints map to `None` are dropped.
+    pub fn retarget<F: Fn(usize) -> Option<usize>>(&self, joint_of: F) -> Self {
+        Self {
+            name: self.name.clone(),
+            duration: self.duration,
+            channels: self.channels
+                .iter()
+                .filter_map(|channel| {
+                    joint_of(channel.joint).map(|joint| Channel { joint, ..channel.clone() })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Pose {
+    /// Crossfade from `self` at `t = 0.0` to `rhs` at `t = 1.0`.
+    pub fn blend(&self, rhs: &Self, t: f32) -> Self {
+        Self {
+            locals: self.locals
+                .iter()
+                .zip(rhs.locals.iter())
+                .map(|(a, b)| a.lerp(b, t))
+                .collect(),
+        }
+    }
+
+    /// Additive layer: apply the difference of `layer` from `reference` by the weight,
+    /// e.g. a breathing clip sampled into `layer` over the rest pose as `reference`.
+    pub fn add(&mut self, layer: &Self, reference: &Self, weight: f32) {
+        self.locals
+            .iter_mut()
+            .zip(layer.locals.iter().zip(reference.locals.iter()))
+            .for_each(|(local, (layer, reference))| {
+                let rotation = reference.rotation.conjugate() * layer.rotation;
+                local.translation += (layer.translation - reference.translation) * weight;
+                local.rotation = (local.rotation * Quat::IDENTITY.slerp(rotation, weight)).normalize();
+                local.scale *= XYZ::<f32>::splat(1.0).lerp(layer.scale / reference.scale, weight);
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn near(a: XYZW<f32>, b: XYZW<f32>) -> bool { (a - b).length() < EPSILON }
+
+    fn x(value: f32) -> XYZW<f32> { XYZW::new(value, 0.0, 0.0, 0.0) }
+
+    fn channel(interpolation: Interpolation, times: Vec<f32>, values: Vec<XYZW<f32>>) -> Channel {
+        Channel { joint: 0, property: Property::Translation, interpolation, times, values }
+    }
+
+    fn joint(parent: Option<usize>, translation: XYZ<f32>) -> Joint {
+        Joint { name: None, parent, rest: Transform::from_translation(translation), inverse_bind: Mat4::IDENTITY }
+    }
+
+    #[test]
+    fn step() {
+        let step = channel(Interpolation::Step, vec![0.0, 1.0, 2.0], vec![x(0.0), x(1.0), x(2.0)]);
+        assert_eq!(step.sample(0.5), Some(x(0.0)));
+        assert_eq!(step.sample(1.0), Some(x(1.0)));
+        assert_eq!(step.sample(1.99), Some(x(1.0)));
+    }
+
+    #[test]
+    fn linear() {
+        let linear = channel(Interpolation::Linear, vec![1.0, 3.0], vec![x(2.0), x(6.0)]);
+        assert!(near(linear.sample(1.5).unwrap(), x(3.0)));
+        assert!(near(linear.sample(3.0).unwrap(), x(6.0)));
+    }
+
+    #[test]
+    fn linear_rotation() {
+        let axis = XYZ::new(0.0, 0.0, 1.0);
+        let rotation = Channel {
+            property: Property::Rotation,
+            ..channel(
+                Interpolation::Linear,
+                vec![0.0, 1.0],
+                vec![Quat::IDENTITY.into(), Quat::from_axis_angle(axis, FRAC_PI_2).into()],
+            )
+        };
+        // Slerp, so the angle is proportional to the time.
+        let sampled = Quat::from(rotation.sample(0.25).unwrap());
+        assert!(sampled.dot(Quat::from_axis_angle(axis, FRAC_PI_2 * 0.25)) > 1.0 - EPSILON);
+    }
+
+    #[test]
+    fn cubic_spline() {
+        // (in-tangent, value, out-tangent) for each key.
+        let values = vec![x(0.0), x(1.0), x(4.0), x(-2.0), x(3.0), x(0.0)];
+        let cubic = channel(Interpolation::CubicSpline, vec![0.0, 2.0], values);
+        assert!(near(cubic.sample(0.0).unwrap(), x(1.0)));
+        assert!(near(cubic.sample(2.0).unwrap(), x(3.0)));
+        // Hermite basis at the middle: (p0 + p1) / 2 + (m0 - m1) / 8, tangents scaled by the delta.
+        let expected = (1.0 + 3.0) / 2.0 + (4.0 * 2.0 - -2.0 * 2.0) / 8.0;
+        assert!(near(cubic.sample(1.0).unwrap(), x(expected)));
+    }
+
+    #[test]
+    fn clamped() {
+        let linear = channel(Interpolation::Linear, vec![1.0, 2.0], vec![x(5.0), x(7.0)]);
+        assert_eq!(linear.sample(-1.0), Some(x(5.0)));
+        assert_eq!(linear.sample(10.0), Some(x(7.0)));
+        let single = channel(Interpolation::CubicSpline, vec![1.0], vec![x(0.0), x(9.0), x(0.0)]);
+        assert_eq!(single.sample(0.0), Some(x(9.0)));
+        assert_eq!(single.sample(2.0), Some(x(9.0)));
+    }
+
+    #[test]
+    fn invalid_channels() {
+        assert_eq!(channel(Interpolation::Linear, vec![], vec![]).sample(0.0), None);
+        assert_eq!(channel(Interpolation::CubicSpline, vec![0.0], vec![x(1.0)]).sample(0.0), None);
+
+        let clip = |channels| Clip::new(None, channels).map(|clip| clip.duration);
+        assert_eq!(clip(vec![channel(Interpolation::Step, vec![], vec![])]), Err(ClipErr::NoKeyframes { channel: 0 }));
+        let valid = channel(Interpolation::Step, vec![0.0, 1.5], vec![x(0.0), x(1.0)]);
+        let equal = channel(Interpolation::Step, vec![1.0, 1.0], vec![x(0.0), x(1.0)]);
+        assert_eq!(clip(vec![valid.clone(), equal]), Err(ClipErr::UnorderedTimes { channel: 1 }));
+        let nan = channel(Interpolation::Step, vec![f32::NAN], vec![x(0.0)]);
+        assert_eq!(clip(vec![nan]), Err(ClipErr::UnorderedTimes { channel: 0 }));
+        let short = channel(Interpolation::CubicSpline, vec![0.0, 1.0], vec![x(0.0); 5]);
+        assert_eq!(clip(vec![short]), Err(ClipErr::ValueCount { channel: 0 }));
+
+        let later = channel(Interpolation::Step, vec![0.5, 3.0], vec![x(0.0), x(1.0)]);
+        assert_eq!(clip(vec![valid, later]), Ok(3.0));
+    }
+
+    #[test]
+    fn clip_sample() {
+        let translation = channel(Interpolation::Linear, vec![0.0, 1.0], vec![x(0.0), x(2.0)]);
+        // A joint which the pose doesn't have.
+        let other = Channel { joint: 5, ..translation.clone() };
+        let scale = Channel { joint: 1, property: Property::Scale, ..translation.clone() };
+        let clip = Clip::new(None, vec![translation, other, scale]).unwrap();
+
+        let mut pose = Pose { locals: vec![Transform::IDENTITY; 2] };
+        clip.sample(0.5, &mut pose);
+        assert_eq!(pose.locals[0].translation, XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(pose.locals[0].scale, XYZ::splat(1.0));
+        assert_eq!(pose.locals[1].scale, XYZ::new(1.0, 0.0, 0.0));
+
+        // Wrapped into the duration.
+        clip.sample_looped(1.25, &mut pose);
+        assert_eq!(pose.locals[0].translation, XYZ::new(0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn blend() {
+        let a = Pose { locals: vec![Transform::from_translation(XYZ::new(0.0, 2.0, 0.0))] };
+        let b = Pose {
+            locals: vec![Transform::new(
+                XYZ::new(4.0, 2.0, 0.0),
+                Quat::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), FRAC_PI_2),
+                XYZ::splat(3.0),
+            )],
+        };
+        let half = a.blend(&b, 0.5).locals[0];
+        assert_eq!(half.translation, XYZ::new(2.0, 2.0, 0.0));
+        assert_eq!(half.scale, XYZ::splat(2.0));
+        let expected = Quat::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), FRAC_PI_2 * 0.5);
+        assert!(half.rotation.dot(expected) > 1.0 - EPSILON);
+        assert_eq!(a.blend(&b, 0.0), a);
+    }
+
+    #[test]
+    fn add() {
+        let reference = Pose { locals: vec![Transform::from_translation(XYZ::new(0.0, 1.0, 0.0))] };
+        let axis = XYZ::new(0.0, 0.0, 1.0);
+        let layer = Pose {
+            locals: vec![Transform::new(
+                XYZ::new(0.0, 1.5, 0.0),
+                Quat::from_axis_angle(axis, FRAC_PI_2),
+                XYZ::splat(2.0),
+            )],
+        };
+        let base = Transform::new(XYZ::new(3.0, 0.0, 0.0), Quat::IDENTITY, XYZ::splat(1.5));
+
+        // The reference itself adds nothing.
+        let mut pose = Pose { locals: vec![base] };
+        pose.add(&reference, &reference, 1.0);
+        assert_eq!(pose.locals[0], base);
+
+        let mut pose = Pose { locals: vec![base] };
+        pose.add(&layer, &reference, 0.5);
+        let local = pose.locals[0];
+        assert_eq!(local.translation, XYZ::new(3.0, 0.25, 0.0));
+        assert!(local.rotation.dot(Quat::from_axis_angle(axis, FRAC_PI_2 * 0.5)) > 1.0 - EPSILON);
+        assert_eq!(local.scale, XYZ::splat(1.5 * 1.5));
+    }
+
+    #[test]
+    fn global_matrices_out_of_order() {
+        // 1 is the root, 2 its child and 0 the child of 2.
+        let skeleton = Skeleton {
+            joints: vec![
+                joint(Some(2), XYZ::new(0.0, 0.0, 1.0)),
+                joint(None, XYZ::new(1.0, 0.0, 0.0)),
+                joint(Some(1), XYZ::new(0.0, 1.0, 0.0)),
+            ],
+            root: Mat4::translation(XYZ::new(10.0, 0.0, 0.0)),
+        };
+        let origins = skeleton.global_matrices(&skeleton.rest_pose())
+            .iter()
+            .map(|global| global.transform_point(XYZ::ZERO))
+            .collect::<Vec<_>>();
+        assert_eq!(origins, vec![XYZ::new(11.0, 1.0, 1.0), XYZ::new(11.0, 0.0, 0.0), XYZ::new(11.0, 1.0, 0.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn cyclic_joints() {
+        let skeleton = Skeleton {
+            joints: vec![joint(Some(1), XYZ::ZERO), joint(Some(0), XYZ::ZERO)],
+            root: Mat4::IDENTITY,
+        };
+        skeleton.global_matrices(&skeleton.rest_pose());
+    }
+}
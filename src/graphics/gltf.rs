@@ -0,0 +1,937 @@
+//! glTF 2.0 import of `.gltf` and `.glb` files.
+//!
+//! Each primitive of triangles becomes a `Mesh`. Missing normals are computed from the
+//! triangles and missing tangents by `Mesh::compute_tangents`. glTF is right-handed with
+//! counterclockwise front faces and UV (0, 0) at the top left, the same as `Mesh`.
+//!
+//! Not supported: sparse accessors, primitives other than triangle lists, texture
+//! coordinate sets other than 0, samplers, morph targets, cameras and extensions.
+
+use image_crate::RgbaImage;
+use serde_json::Value;
+
+use crate::linear_algebra::*;
+
+use super::{ Mesh, Vertex };
+use super::animation::{ Skeleton, Joint, Clip, Channel, Property, Interpolation };
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+const TRIANGLES: u64 = 4;
+
+#[derive(Debug)]
+pub enum GltfErr {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Image(image_crate::ImageError),
+    /// The file breaks the spec, e.g. an index out of bounds.
+    Invalid(String),
+    /// A valid feature which is not implemented, e.g. sparse accessors.
+    Unsupported(String),
+}
+
+/// Everything in a glTF file which is imported. Indices refer to the vectors of this.
+pub struct Model {
+    pub nodes: Vec<Node>,
+    /// Root nodes of the default scene.
+    pub roots: Vec<usize>,
+    pub meshes: Vec<ModelMesh>,
+    pub materials: Vec<Material>,
+    /// Images of textures in RGBA8. Color spaces are up to the material.
+    pub images: Vec<RgbaImage>,
+    pub skins: Vec<Skin>,
+    /// Channels target nodes. `skeleton` retargets them onto joints of a skin.
+    pub animations: Vec<Clip>,
+}
+
+pub struct Node {
+    pub name: Option<String>,
+    /// Relative to the parent.
+    pub transform: Transform,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub skin: Option<usize>,
+}
+
+pub struct ModelMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<Primitive>,
+}
+
+pub struct Primitive {
+    pub mesh: Mesh,
+    pub material: Option<usize>,
+    /// Indices into `Skin::joints` for each vertex. Empty unless the primitive is skinned.
+    pub joints: Vec<[u16; 4]>,
+    /// Weights of `joints`, which sum to 1.
+    pub weights: Vec<XYZW<f32>>,
+}
+
+/// Metallic-roughness material. Textures are indices into `Model::images`.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: Option<String>,
+    /// Linear RGBA, multiplied with the base color texture.
+    pub base_color_factor: XYZW<f32>,
+    /// sRGB.
+    pub base_color_texture: Option<usize>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Linear. Roughness is in green and metallic in blue.
+    pub metallic_roughness_texture: Option<usize>,
+    /// Linear tangent space normals.
+    pub normal_texture: Option<usize>,
+    /// Scale of x and y of the normal map.
+    pub normal_scale: f32,
+}
+
+pub struct Skin {
+    /// Nodes of the joints.
+    pub joints: Vec<usize>,
+    /// From model coordinates of the mesh into the coordinates of each joint.
+    pub inverse_bind_matrices: Vec<Mat4>,
+    /// The common root of the joints, if the file tells.
+    pub skeleton: Option<usize>,
+}
+
+/// A typed view of an accessor into a buffer.
+struct Accessor<'a> {
+    bytes: &'a [u8],
+    component_type: u64,
+    components: usize,
+    count: usize,
+    stride: usize,
+    normalized: bool,
+}
+
+impl Model {
+    /// Load a `.gltf` or `.glb` file. External buffers and images are relative to it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, GltfErr> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(GltfErr::Io)?;
+        Self::parse(&bytes[..], path.parent())
+    }
+
+    /// Load a `.gltf` or `.glb` file in memory, whose buffers and images are embedded.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, GltfErr> {
+        Self::parse(bytes, None)
+    }
+
+    fn parse(bytes: &[u8], base: Option<&Path>) -> Result<Self, GltfErr> {
+        let (json, bin) = if bytes.starts_with(GLB_MAGIC) {
+            parse_glb(bytes)?
+        } else {
+            (bytes, None)
+        };
+        let json: Value = serde_json::from_slice(json).map_err(GltfErr::Json)?;
+
+        let version = json["asset"]["version"].as_str().unwrap_or("");
+        if !version.starts_with("2.") {
+            return Err(GltfErr::Unsupported(format!("glTF version {:?}", version)));
+        }
+
+        let buffers = array(&json, "buffers")
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| match buffer["uri"].as_str() {
+                Some(uri) => read_uri(uri, base),
+                // Only the first buffer of a GLB may omit the URI.
+                None if index == 0 => bin.map(<[u8]>::to_vec).ok_or_else(|| invalid("buffer 0 has no data")),
+                None => Err(invalid("buffer without URI")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let images = array(&json, "images")
+            .iter()
+            .map(|image| {
+                let bytes = match (image["uri"].as_str(), index_of(image, "bufferView")) {
+                    (Some(uri), _) => read_uri(uri, base)?,
+                    (None, Some(view)) => buffer_view(&json, &buffers, view)?.to_vec(),
+                    (None, None) => return Err(invalid("image without data")),
+                };
+                image_crate::load_from_memory(&bytes[..])
+                    .map(|image| image.to_rgba())
+                    .map_err(GltfErr::Image)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let meshes = array(&json, "meshes")
+            .iter()
+            .map(|mesh| {
+                let primitives = array(mesh, "primitives")
+                    .iter()
+                    .map(|primitive| parse_primitive(&json, &buffers, primitive))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(ModelMesh { name: name(mesh), primitives })
+            })
+            .collect::<Result<Vec<_>, GltfErr>>()?;
+
+        let materials = array(&json, "materials")
+            .iter()
+            .map(|material| parse_material(&json, material))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let nodes = array(&json, "nodes")
+            .iter()
+            .map(parse_node)
+            .collect::<Vec<_>>();
+
+        let skins = array(&json, "skins")
+            .iter()
+            .map(|skin| {
+                let joints = indices(skin, "joints");
+                let inverse_bind_matrices = match index_of(skin, "inverseBindMatrices") {
+                    Some(accessor) => {
+                        let floats = Accessor::new(&json, &buffers, accessor)?.floats();
+                        floats
+                            .chunks_exact(16)
+                            .map(|m| Mat4::from_columns(
+                                XYZW::new(m[0], m[1], m[2], m[3]),
+                                XYZW::new(m[4], m[5], m[6], m[7]),
+                                XYZW::new(m[8], m[9], m[10], m[11]),
+                                XYZW::new(m[12], m[13], m[14], m[15]),
+                            ))
+                            .collect::<Vec<_>>()
+                    },
+                    None => vec![Mat4::IDENTITY; joints.len()],
+                };
+                if inverse_bind_matrices.len() != joints.len() {
+                    return Err(invalid("inverse bind matrices don't match joints"));
+                }
+                Ok(Skin { joints, inverse_bind_matrices, skeleton: index_of(skin, "skeleton") })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let roots = match json["scenes"].get(index_of(&json, "scene").unwrap_or(0)) {
+            Some(scene) => indices(scene, "nodes"),
+            // Without scenes, roots are nodes which are not children.
+            None => (0..nodes.len())
+                .filter(|node| nodes.iter().all(|parent| !parent.children.contains(node)))
+                .collect(),
+        };
+
+        let animations = array(&json, "animations")
+            .iter()
+            .map(|animation| parse_animation(&json, &buffers, animation))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let model = Self { nodes, roots, meshes, materials, images, skins, animations };
+        model.validate()?;
+        Ok(model)
+    }
+
+    /// Indices between objects are in bounds, and nodes form a forest whose roots are the
+    /// roots of the scene.
+    fn validate(&self) -> Result<(), GltfErr> {
+        let nodes = self.nodes.len();
+        let in_bounds = |index: &usize, len: usize| *index < len;
+
+        let valid = self.roots.iter().all(|root| in_bounds(root, nodes))
+            && self.nodes.iter().all(|node| {
+                node.children.iter().all(|child| in_bounds(child, nodes))
+                    && node.mesh.is_none_or(|mesh| in_bounds(&mesh, self.meshes.len()))
+                    && node.skin.is_none_or(|skin| in_bounds(&skin, self.skins.len()))
+            })
+            && self.meshes.iter().flat_map(|mesh| mesh.primitives.iter()).all(|primitive| {
+                primitive.material.is_none_or(|material| in_bounds(&material, self.materials.len()))
+            })
+            && self.materials.iter().all(|material| {
+                [material.base_color_texture, material.metallic_roughness_texture, material.normal_texture]
+                    .iter()
+                    .all(|texture| texture.is_none_or(|image| in_bounds(&image, self.images.len())))
+            })
+            && self.skins.iter().all(|skin| {
+                skin.joints.iter().chain(skin.skeleton.iter()).all(|joint| in_bounds(joint, nodes))
+            })
+            && self.animations.iter().flat_map(|clip| clip.channels.iter()).all(|channel| {
+                in_bounds(&channel.joint, nodes)
+            });
+
+        if !valid {
+            return Err(invalid("index out of bounds"));
+        }
+
+        let mut parents = vec![None; nodes];
+        for (parent, node) in self.nodes.iter().enumerate() {
+            for child in &node.children {
+                if parents[*child].replace(parent).is_some() {
+                    return Err(invalid("node with multiple parents"));
+                }
+            }
+        }
+        if self.roots.iter().any(|root| parents[*root].is_some()) {
+            return Err(invalid("root node with a parent"));
+        }
+
+        // With a parent each, nodes on a cycle are not reachable from nodes without parents.
+        let mut reached = 0;
+        let mut stack = (0..nodes).filter(|node| parents[*node].is_none()).collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            reached += 1;
+            stack.extend_from_slice(&self.nodes[node].children);
+        }
+        if reached != nodes {
+            return Err(invalid("cycle of nodes"));
+        }
+        Ok(())
+    }
+
+    /// Transforms of all nodes relative to the scene, indexed like `nodes`.
+    /// Nodes which are not in the scene keep their own transforms.
+    pub fn global_transforms(&self) -> Vec<Mat4> {
+        let mut globals: Vec<Mat4> = self.nodes.iter().map(|node| node.transform.to_matrix()).collect();
+        let mut stack: Vec<usize> = self.roots.clone();
+        // Parents come before their children, and `validate` ensures the hierarchy is a forest.
+        while let Some(parent) = stack.pop() {
+            let parent_matrix = globals[parent];
+            self.nodes[parent].children.iter().for_each(|child| {
+                globals[*child] = parent_matrix * self.nodes[*child].transform.to_matrix();
+                stack.push(*child);
+            });
+        }
+        globals
+    }
+
+    /// The skeleton of the skin with joints in the order of `Skin::joints`, which joints of
+    /// vertices refer to, and all animations retargeted onto it. Joint matrices of the
+    /// skeleton put vertices into the scene, so the node of the mesh is ignored by the spec.
+    pub fn skeleton(&self, skin: usize) -> (Skeleton, Vec<Clip>) {
+        let skin = &self.skins[skin];
+        let mut parents = vec![None; self.nodes.len()];
+        self.nodes.iter().enumerate().for_each(|(parent, node)| {
+            node.children.iter().for_each(|child| parents[*child] = Some(parent));
+        });
+        let joint_of = |node: usize| skin.joints.iter().position(|joint| *joint == node);
+
+        let joints: Vec<Joint> = skin.joints
+            .iter()
+            .zip(skin.inverse_bind_matrices.iter())
+            .map(|(node, inverse_bind)| Joint {
+                name: self.nodes[*node].name.clone(),
+                parent: parents[*node].and_then(joint_of),
+                rest: self.nodes[*node].transform,
+                inverse_bind: *inverse_bind,
+            })
+            .collect();
+
+        // Nodes above the root joints, which are not animated as a part of the skeleton.
+        let root = skin.joints
+            .iter()
+            .filter_map(|node| parents[*node])
+            .find(|parent| joint_of(*parent).is_none())
+            .map_or(Mat4::IDENTITY, |parent| self.global_transforms()[parent]);
+
+        let clips = self.animations.iter().map(|clip| clip.retarget(joint_of)).collect();
+        (Skeleton { joints, root }, clips)
+    }
+}
+
+impl<'a> Accessor<'a> {
+    fn new(json: &Value, buffers: &'a [Vec<u8>], index: usize) -> Result<Self, GltfErr> {
+        let accessor = json["accessors"].get(index).ok_or_else(|| invalid("accessor out of bounds"))?;
+        if !accessor["sparse"].is_null() {
+            return Err(GltfErr::Unsupported("sparse accessors".into()));
+        }
+
+        let component_type = accessor["componentType"].as_u64().unwrap_or(0);
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(invalid("unknown component type")),
+        };
+        let accessor_type = accessor["type"].as_str().unwrap_or("");
+        let components = match accessor_type {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            "MAT2" => 4,
+            "MAT3" => 9,
+            "MAT4" => 16,
+            _ => return Err(invalid("unknown accessor type")),
+        };
+        if accessor_type == "MAT2" && component_size == 1 || accessor_type == "MAT3" && component_size < 4 {
+            // Columns of small matrices are padded to 4 bytes.
+            return Err(GltfErr::Unsupported("matrices of small components".into()));
+        }
+
+        let count = accessor["count"].as_u64().unwrap_or(0) as usize;
+        let element_size = component_size * components;
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+        let view = match index_of(accessor, "bufferView") {
+            Some(view) => view,
+            // Without a view, all components are zero.
+            None => {
+                return Ok(Self {
+                    bytes: &[],
+                    component_type,
+                    components,
+                    count,
+                    stride: 0,
+                    normalized,
+                });
+            },
+        };
+
+        let bytes = buffer_view(json, buffers, view)?;
+        let stride = json["bufferViews"][view]["byteStride"].as_u64().unwrap_or(element_size as u64) as usize;
+        let offset = accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let end = match count {
+            0 => Some(offset),
+            _ => stride
+                .checked_mul(count - 1)
+                .and_then(|end| end.checked_add(offset))
+                .and_then(|end| end.checked_add(element_size)),
+        };
+        let end = end
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| invalid("accessor out of its buffer view"))?;
+
+        Ok(Self { bytes: &bytes[offset..end], component_type, components, count, stride, normalized })
+    }
+
+    /// Raw value of a component, and its maximum if normalized.
+    fn component(&self, element: usize, component: usize) -> (f64, f64) {
+        if self.bytes.is_empty() {
+            return (0.0, 1.0);
+        }
+
+        let size = match self.component_type { 5120 | 5121 => 1, 5122 | 5123 => 2, _ => 4 };
+        let at = element * self.stride + component * size;
+        let b = &self.bytes[at..at + size];
+        match self.component_type {
+            5120 => (b[0] as i8 as f64, 127.0),
+            5121 => (b[0] as f64, 255.0),
+            5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.0),
+            5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.0),
+            5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+            _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+        }
+    }
+
+    /// All components in order. Integers are mapped into [0, 1] or [-1, 1] if normalized.
+    fn floats(&self) -> Vec<f32> {
+        (0..self.count)
+            .flat_map(|element| (0..self.components).map(move |component| (element, component)))
+            .map(|(element, component)| {
+                let (value, max) = self.component(element, component);
+                if self.normalized { (value / max).max(-1.0) as f32 } else { value as f32 }
+            })
+            .collect()
+    }
+
+    /// All components in order, which must be unsigned integers.
+    fn uints(&self) -> Result<Vec<u32>, GltfErr> {
+        if ![5121, 5123, 5125].contains(&self.component_type) {
+            return Err(invalid("expected unsigned integers"));
+        }
+        Ok(
+            (0..self.count)
+                .flat_map(|element| (0..self.components).map(move |component| (element, component)))
+                .map(|(element, component)| self.component(element, component).0 as u32)
+                .collect()
+        )
+    }
+
+    /// `components` components of each element.
+    fn expect(self, components: usize, what: &str) -> Result<Self, GltfErr> {
+        if self.components == components {
+            Ok(self)
+        } else {
+            Err(invalid(&format!("{} with {} components", what, self.components)))
+        }
+    }
+}
+
+fn parse_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfErr> {
+    let word = |at: usize| -> Result<u32, GltfErr> {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid("truncated GLB"))
+    };
+
+    if word(4)? != 2 {
+        return Err(GltfErr::Unsupported(format!("GLB version {}", word(4)?)));
+    }
+    let length = (word(8)? as usize).min(bytes.len());
+
+    let mut json = None;
+    let mut bin = None;
+    let mut at = 12;
+    while at + 8 <= length {
+        let chunk_length = word(at)? as usize;
+        let chunk_type = word(at + 4)?;
+        let chunk = bytes.get(at + 8..at + 8 + chunk_length).ok_or_else(|| invalid("truncated GLB chunk"))?;
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+            // Unknown chunks are skipped by the spec.
+            _ => (),
+        }
+        // Chunks are padded to 4 bytes.
+        at += 8 + chunk_length.div_ceil(4) * 4;
+    }
+
+    Ok((json.ok_or_else(|| invalid("GLB without JSON"))?, bin))
+}
+
+fn parse_primitive(json: &Value, buffers: &[Vec<u8>], primitive: &Value) -> Result<Primitive, GltfErr> {
+    let mode = primitive["mode"].as_u64().unwrap_or(TRIANGLES);
+    if mode != TRIANGLES {
+        return Err(GltfErr::Unsupported(format!("primitive mode {}", mode)));
+    }
+
+    let attributes = &primitive["attributes"];
+    let attribute = |name: &str, components: usize| -> Result<Option<Accessor>, GltfErr> {
+        index_of(attributes, name)
+            .map(|accessor| Accessor::new(json, buffers, accessor)?.expect(components, name))
+            .transpose()
+    };
+
+    let positions = attribute("POSITION", 3)?
+        .ok_or_else(|| invalid("primitive without POSITION"))?
+        .floats();
+    let count = positions.len() / 3;
+    let normals = attribute("NORMAL", 3)?.map(|accessor| accessor.floats());
+    let tangents = attribute("TANGENT", 4)?.map(|accessor| accessor.floats());
+    let uvs = attribute("TEXCOORD_0", 2)?.map(|accessor| accessor.floats());
+
+    let lengths_match = [
+        normals.as_ref().map(|n| n.len() / 3),
+        tangents.as_ref().map(|t| t.len() / 4),
+        uvs.as_ref().map(|uv| uv.len() / 2),
+    ]
+        .iter()
+        .all(|len| len.is_none_or(|len| len == count));
+    if !lengths_match {
+        return Err(invalid("attributes of different counts"));
+    }
+
+    let vertices = (0..count)
+        .map(|i| {
+            let mut vertex = Vertex::new(
+                XYZ::new(positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]),
+                normals.as_ref().map_or(XYZ::<f32>::ZERO, |n| XYZ::new(n[3 * i], n[3 * i + 1], n[3 * i + 2])),
+                uvs.as_ref().map_or(XY::new(0.0, 0.0), |uv| XY::new(uv[2 * i], uv[2 * i + 1])),
+            );
+            if let Some(t) = &tangents {
+                vertex.tangent = XYZW::new(t[4 * i], t[4 * i + 1], t[4 * i + 2], t[4 * i + 3]);
+            }
+            vertex
+        })
+        .collect();
+
+    let indices = match index_of(primitive, "indices") {
+        Some(accessor) => Accessor::new(json, buffers, accessor)?.expect(1, "indices")?.uints()?,
+        None => (0..count as u32).collect(),
+    };
+    if indices.len() % 3 != 0 || indices.iter().any(|index| *index as usize >= count) {
+        return Err(invalid("indices are not triangles of the vertices"));
+    }
+
+    let mut mesh = Mesh { vertices, indices };
+    if normals.is_none() {
+        compute_normals(&mut mesh);
+    }
+    if tangents.is_none() {
+        mesh.compute_tangents();
+    }
+
+    let (joints, weights) = match (attribute("JOINTS_0", 4)?, attribute("WEIGHTS_0", 4)?) {
+        (Some(joints), Some(weights)) => {
+            let joints = joints.uints()?;
+            let weights = weights.floats();
+            if joints.len() != count * 4 || weights.len() != count * 4 {
+                return Err(invalid("attributes of different counts"));
+            }
+            let joints = joints
+                .chunks_exact(4)
+                .map(|j| [j[0] as u16, j[1] as u16, j[2] as u16, j[3] as u16])
+                .collect();
+            let weights = weights
+                .chunks_exact(4)
+                .map(|w| {
+                    let weights = XYZW::new(w[0], w[1], w[2], w[3]);
+                    let sum = w[0] + w[1] + w[2] + w[3];
+                    if sum > 0.0 { weights * (1.0 / sum) } else { XYZW::new(1.0, 0.0, 0.0, 0.0) }
+                })
+                .collect();
+            (joints, weights)
+        },
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    Ok(Primitive { mesh, material: index_of(primitive, "material"), joints, weights })
+}
+
+fn parse_material(json: &Value, material: &Value) -> Result<Material, GltfErr> {
+    let pbr = &material["pbrMetallicRoughness"];
+    // Textures refer to images through texture objects.
+    let texture = |info: &Value| -> Result<Option<usize>, GltfErr> {
+        match index_of(info, "index") {
+            Some(texture) => json["textures"]
+                .get(texture)
+                .map(|texture| index_of(texture, "source"))
+                .ok_or_else(|| invalid("texture out of bounds")),
+            None => Ok(None),
+        }
+    };
+
+    let factor = floats(&pbr["baseColorFactor"]);
+    Ok(Material {
+        name: name(material),
+        base_color_factor: match &factor[..] {
+            [r, g, b, a] => XYZW::new(*r, *g, *b, *a),
+            _ => XYZW::new(1.0, 1.0, 1.0, 1.0),
+        },
+        base_color_texture: texture(&pbr["baseColorTexture"])?,
+        metallic_factor: pbr["metallicFactor"].as_f64().unwrap_or(1.0) as f32,
+        roughness_factor: pbr["roughnessFactor"].as_f64().unwrap_or(1.0) as f32,
+        metallic_roughness_texture: texture(&pbr["metallicRoughnessTexture"])?,
+        normal_texture: texture(&material["normalTexture"])?,
+        normal_scale: material["normalTexture"]["scale"].as_f64().unwrap_or(1.0) as f32,
+    })
+}
+
+fn parse_animation(json: &Value, buffers: &[Vec<u8>], animation: &Value) -> Result<Clip, GltfErr> {
+    let samplers = array(animation, "samplers");
+    let channels = array(animation, "channels")
+        .iter()
+        // Morph target weights are not supported, and channels without nodes are ignored.
+        .filter_map(|channel| {
+            let target = &channel["target"];
+            let property = match target["path"].as_str() {
+                Some("translation") => Property::Translation,
+                Some("rotation") => Property::Rotation,
+                Some("scale") => Property::Scale,
+                _ => return None,
+            };
+            index_of(target, "node").map(|node| (channel, node, property))
+        })
+        .map(|(channel, node, property)| {
+            let sampler = index_of(channel, "sampler")
+                .and_then(|sampler| samplers.get(sampler))
+                .ok_or_else(|| invalid("animation sampler out of bounds"))?;
+            let interpolation = match sampler["interpolation"].as_str().unwrap_or("LINEAR") {
+                "STEP" => Interpolation::Step,
+                "LINEAR" => Interpolation::Linear,
+                "CUBICSPLINE" => Interpolation::CubicSpline,
+                other => return Err(invalid(&format!("interpolation {:?}", other))),
+            };
+
+            let accessor = |key: &str| {
+                index_of(sampler, key)
+                    .ok_or_else(|| invalid("animation sampler without data"))
+                    .and_then(|accessor| Accessor::new(json, buffers, accessor))
+            };
+            let times = accessor("input")?.expect(1, "keyframe times")?.floats();
+            let components = if property == Property::Rotation { 4 } else { 3 };
+            let values = accessor("output")?
+                .expect(components, "keyframe values")?
+                .floats()
+                .chunks_exact(components)
+                .map(|v| XYZW::new(v[0], v[1], v[2], if components == 4 { v[3] } else { 0.0 }))
+                .collect::<Vec<_>>();
+            Ok(Channel { joint: node, property, interpolation, times, values })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Clip::new(name(animation), channels).map_err(|err| invalid(&format!("animation: {:?}", err)))
+}
+
+fn parse_node(node: &Value) -> Node {
+    let matrix = floats(&node["matrix"]);
+    let transform = if matrix.len() == 16 {
+        let mut columns = [[0.0; 4]; 4];
+        columns.iter_mut().flatten().zip(matrix.iter()).for_each(|(c, m)| *c = *m);
+        Transform::from_matrix(&Mat4::from(columns))
+    } else {
+        let vector = |key: &str, default: XYZ<f32>| match &floats(&node[key])[..] {
+            [x, y, z] => XYZ::new(*x, *y, *z),
+            _ => default,
+        };
+        let rotation = match &floats(&node["rotation"])[..] {
+            [x, y, z, w] => Quat::new(*x, *y, *z, *w).normalize(),
+            _ => Quat::IDENTITY,
+        };
+        Transform::new(
+            vector("translation", XYZ::<f32>::ZERO),
+            rotation,
+            vector("scale", XYZ::splat(1.0)),
+        )
+    };
+
+    Node {
+        name: name(node),
+        transform,
+        children: indices(node, "children"),
+        mesh: index_of(node, "mesh"),
+        skin: index_of(node, "skin"),
+    }
+}
+
+/// Area weighted normals of the triangles around each vertex.
+fn compute_normals(mesh: &mut Mesh) {
+    let mut normals = vec![XYZ::<f32>::ZERO; mesh.vertices.len()];
+    mesh.indices.chunks_exact(3).for_each(|triangle| {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let (pa, pb, pc) = (mesh.vertices[a].position, mesh.vertices[b].position, mesh.vertices[c].position);
+        let normal = (pb - pa).cross(pc - pa);
+        [a, b, c].iter().for_each(|i| normals[*i] += normal);
+    });
+    mesh.vertices
+        .iter_mut()
+        .zip(normals.iter())
+        .for_each(|(vertex, normal)| vertex.normal = normal.normalize());
+}
+
+/// Bytes of a buffer view.
+fn buffer_view<'a>(json: &Value, buffers: &'a [Vec<u8>], view: usize) -> Result<&'a [u8], GltfErr> {
+    let view = json["bufferViews"].get(view).ok_or_else(|| invalid("buffer view out of bounds"))?;
+    let buffer = index_of(view, "buffer")
+        .and_then(|buffer| buffers.get(buffer))
+        .ok_or_else(|| invalid("buffer out of bounds"))?;
+    let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+    let length = view["byteLength"].as_u64().unwrap_or(0) as usize;
+    offset
+        .checked_add(length)
+        .and_then(|end| buffer.get(offset..end))
+        .ok_or_else(|| invalid("buffer view out of its buffer"))
+}
+
+/// Data URIs of base64 or files relative to `base`.
+fn read_uri(uri: &str, base: Option<&Path>) -> Result<Vec<u8>, GltfErr> {
+    if uri.starts_with("data:") {
+        let comma = uri.find(',').ok_or_else(|| invalid("data URI without data"))?;
+        if !uri[..comma].ends_with(";base64") {
+            return Err(GltfErr::Unsupported("data URIs which are not base64".into()));
+        }
+        return decode_base64(&uri[comma + 1..]).ok_or_else(|| invalid("invalid base64"));
+    }
+
+    match base {
+        Some(base) => fs::read(base.join(uri)).map_err(GltfErr::Io),
+        None => Err(GltfErr::Unsupported(format!("external URI {:?} without a base path", uri))),
+    }
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let sextet = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0_u32;
+    let mut bit_count = 0;
+    for c in text.bytes().take_while(|c| *c != b'=') {
+        bits = bits << 6 | sextet(c)? as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[inline]
+fn invalid(message: &str) -> GltfErr { GltfErr::Invalid(message.into()) }
+
+/// An empty slice if there is no array of the key.
+fn array<'a>(json: &'a Value, key: &str) -> &'a [Value] {
+    json[key].as_array().map_or(&[], |array| &array[..])
+}
+
+fn index_of(json: &Value, key: &str) -> Option<usize> {
+    json[key].as_u64().map(|index| index as usize)
+}
+
+fn indices(json: &Value, key: &str) -> Vec<usize> {
+    array(json, key).iter().filter_map(|index| index.as_u64()).map(|index| index as usize).collect()
+}
+
+fn floats(json: &Value) -> Vec<f32> {
+    json.as_array()
+        .map(|array| array.iter().filter_map(|value| value.as_f64()).map(|value| value as f32).collect())
+        .unwrap_or_default()
+}
+
+fn name(json: &Value) -> Option<String> {
+    json["name"].as_str().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    const TRIANGLE: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/triangle.gltf"));
+    /// Joints `hip` and its child `knee` skinning a triangle, with a clip `bend` which
+    /// rotates the knee by 90 degrees around z linearly and steps the hip up, over a second.
+    const SKINNED: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/skinned.glb"));
+
+    /// `TRIANGLE` changed by `edit`.
+    fn triangle_with<F: FnOnce(&mut Value)>(edit: F) -> Result<Model, GltfErr> {
+        let mut json: Value = serde_json::from_str(TRIANGLE).unwrap();
+        edit(&mut json);
+        Model::from_slice(&serde_json::to_vec(&json).unwrap())
+    }
+
+    fn assert_invalid(result: Result<Model, GltfErr>, expected: &str) {
+        match result {
+            Err(GltfErr::Invalid(message)) => assert_eq!(message, expected),
+            Err(err) => panic!("expected {:?}, got {:?}", expected, err),
+            Ok(_) => panic!("expected {:?}, got a model", expected),
+        }
+    }
+
+    fn assert_close(actual: XYZ<f32>, expected: XYZ<f32>) {
+        assert!((actual - expected).length() < 1e-5, "{:?} is not {:?}", actual, expected);
+    }
+
+    fn assert_identity(m: &Mat4) {
+        m.columns.iter().zip(Mat4::IDENTITY.columns.iter()).for_each(|(actual, expected)| {
+            assert!((*actual - *expected).length() < 1e-5, "{:?} is not the identity", m);
+        });
+    }
+
+    #[test]
+    fn data_uri() {
+        let model = Model::from_slice(TRIANGLE.as_bytes()).unwrap();
+        assert_eq!(model.roots, vec![0]);
+        assert_eq!(model.nodes[0].mesh, Some(0));
+        assert_eq!(model.nodes[0].transform.translation, XYZ::new(1.0, 2.0, 3.0));
+
+        let mesh = &model.meshes[0].primitives[0].mesh;
+        let positions = mesh.vertices.iter().map(|vertex| vertex.position).collect::<Vec<_>>();
+        assert_eq!(positions, vec![XYZ::new(0.0, 0.0, 0.0), XYZ::new(1.0, 0.0, 0.0), XYZ::new(0.0, 1.0, 0.0)]);
+        // Without indices, vertices are triangles in order, and normals are computed.
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        mesh.vertices.iter().for_each(|vertex| assert_close(vertex.normal, XYZ::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn skinned_glb() {
+        let model = Model::from_slice(SKINNED).unwrap();
+        let primitive = &model.meshes[0].primitives[0];
+        assert_eq!(primitive.mesh.indices, vec![0, 1, 2]);
+        assert_eq!(primitive.joints, vec![[0, 0, 0, 0], [0, 1, 0, 0], [1, 0, 0, 0]]);
+        // Weights are normalized.
+        assert_eq!(primitive.weights[1], XYZW::new(0.5, 0.5, 0.0, 0.0));
+        assert_eq!(primitive.weights[2], XYZW::new(1.0, 0.0, 0.0, 0.0));
+
+        let skin = &model.skins[0];
+        assert_eq!(skin.joints, vec![1, 2]);
+        assert_eq!(skin.skeleton, Some(1));
+        assert_eq!(skin.inverse_bind_matrices[1], Mat4::translation(XYZ::new(0.0, -2.0, 0.0)));
+
+        let (skeleton, clips) = model.skeleton(0);
+        let parents = skeleton.joints.iter().map(|joint| joint.parent).collect::<Vec<_>>();
+        assert_eq!(parents, vec![None, Some(0)]);
+        let names = skeleton.joints.iter().map(|joint| joint.name.as_deref()).collect::<Vec<_>>();
+        assert_eq!(names, vec![Some("hip"), Some("knee")]);
+        // Inverse bind matrices undo the rest pose.
+        skeleton.joint_matrices(&skeleton.rest_pose()).iter().for_each(assert_identity);
+
+        assert_eq!(clips.len(), 1);
+        let bend = &clips[0];
+        assert_eq!(bend.name.as_deref(), Some("bend"));
+        assert_eq!(bend.duration, 1.0);
+        // Channels are retargeted from nodes onto joints.
+        let targets = bend.channels.iter().map(|channel| (channel.joint, channel.property)).collect::<Vec<_>>();
+        assert_eq!(targets, vec![(1, Property::Rotation), (0, Property::Translation)]);
+
+        // Halfway, the knee is rotated by 45 degrees and the hip has not stepped yet.
+        let mut pose = skeleton.rest_pose();
+        bend.sample(0.5, &mut pose);
+        assert_close(pose.locals[0].translation, XYZ::new(0.0, 1.0, 0.0));
+        let joints = skeleton.joint_matrices(&pose);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(joints[1].transform_point(XYZ::new(1.0, 2.0, 0.0)), XYZ::new(half, 2.0 + half, 0.0));
+
+        bend.sample(1.0, &mut pose);
+        assert_close(pose.locals[0].translation, XYZ::new(0.0, 3.0, 0.0));
+        let joints = skeleton.joint_matrices(&pose);
+        assert_close(joints[1].transform_point(XYZ::new(1.0, 2.0, 0.0)), XYZ::new(0.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn truncated_glb() {
+        assert_invalid(Model::from_slice(&SKINNED[..10]), "truncated GLB");
+        assert_invalid(Model::from_slice(&SKINNED[..SKINNED.len() - 4]), "truncated GLB chunk");
+    }
+
+    #[test]
+    fn indices_out_of_bounds() {
+        // u16 indices 0, 1, 3 with 3 vertices.
+        let indices = triangle_with(|json| {
+            json["buffers"].as_array_mut().unwrap().push(json!({
+                "byteLength": 8,
+                "uri": "data:application/octet-stream;base64,AAABAAMAAAA=",
+            }));
+            json["bufferViews"].as_array_mut().unwrap().push(json!({ "buffer": 1, "byteLength": 6 }));
+            json["accessors"].as_array_mut().unwrap().push(json!({
+                "bufferView": 1,
+                "componentType": 5123,
+                "count": 3,
+                "type": "SCALAR",
+            }));
+            json["meshes"][0]["primitives"][0]["indices"] = json!(1);
+        });
+        assert_invalid(indices, "indices are not triangles of the vertices");
+
+        assert_invalid(triangle_with(|json| json["nodes"][0]["children"] = json!([1])), "index out of bounds");
+        assert_invalid(triangle_with(|json| json["nodes"][0]["mesh"] = json!(1)), "index out of bounds");
+        assert_invalid(
+            triangle_with(|json| json["meshes"][0]["primitives"][0]["attributes"]["POSITION"] = json!(1)),
+            "accessor out of bounds",
+        );
+    }
+
+    #[test]
+    fn sizes_overflow() {
+        let count = triangle_with(|json| json["accessors"][0]["count"] = json!(u64::MAX / 2));
+        assert_invalid(count, "accessor out of its buffer view");
+
+        let offset = triangle_with(|json| json["bufferViews"][0]["byteOffset"] = json!(u64::MAX - 1));
+        assert_invalid(offset, "buffer view out of its buffer");
+    }
+
+    #[test]
+    fn hierarchy_is_a_forest() {
+        let cycle = triangle_with(|json| {
+            json["nodes"] = json!([{ "mesh": 0 }, { "children": [2] }, { "children": [1] }]);
+        });
+        assert_invalid(cycle, "cycle of nodes");
+
+        let own_child = triangle_with(|json| json["nodes"][0]["children"] = json!([0]));
+        assert_invalid(own_child, "root node with a parent");
+
+        let parents = triangle_with(|json| {
+            json["nodes"] = json!([{ "children": [2] }, { "children": [2] }, { "mesh": 0 }]);
+            json["scenes"][0]["nodes"] = json!([0, 1]);
+        });
+        assert_invalid(parents, "node with multiple parents");
+
+        let root = triangle_with(|json| {
+            json["nodes"] = json!([{ "children": [1] }, { "mesh": 0 }]);
+            json["scenes"][0]["nodes"] = json!([0, 1]);
+        });
+        assert_invalid(root, "root node with a parent");
+    }
+}
@@ -0,0 +1,381 @@
+//! Procedural indexed meshes.
+//!
+//! Meshes are centered at the origin with y up. Triangles are counterclockwise seen from the
+//! outside, which `dim3` draws as front faces, and normals point outward. Vertices are split
+//! where UVs or normals are discontinuous, e.g. at the seam of a cylinder or the edges of a
+//! cube, so positions are shared only across smooth surfaces.
+
+use crate::linear_algebra::*;
+
+use super::{ Vertex, regular_icosahedron };
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    /// 3 for each triangle.
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Regular icosahedron with radius 1 and smooth normals.
+    pub fn icosahedron() -> Self {
+        Self::icosphere(0)
+    }
+
+    /// Sphere with radius 1 made by splitting each triangle of the icosahedron into 4,
+    /// `subdivisions` times. There are 20 * 4^subdivisions triangles.
+    pub fn icosphere(subdivisions: u32) -> Self {
+        let (vertices, indices) = regular_icosahedron();
+        let mut positions = vertices.iter().map(|vertex| vertex.position).collect::<Vec<_>>();
+        let mut triangles = indices.chunks(3).map(|t| [t[0] as u32, t[1] as u32, t[2] as u32]).collect::<Vec<_>>();
+
+        (0..subdivisions).for_each(|_| {
+            // Both triangles sharing an edge use the same midpoint.
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    positions.push(((positions[a as usize] + positions[b as usize]) * 0.5).normalize());
+                    positions.len() as u32 - 1
+                })
+            };
+
+            triangles = triangles
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        });
+
+        let mut mesh = Self::default();
+        // Vertices of triangles across the seam at u = 0 have copies with u + 1.
+        let mut unique = HashMap::new();
+        triangles.iter().for_each(|triangle| {
+            let positions = [
+                positions[triangle[0] as usize],
+                positions[triangle[1] as usize],
+                positions[triangle[2] as usize],
+            ];
+            // u is undefined at the poles, so they take the middle of the other two.
+            let is_pole = |i: usize| positions[i].y.abs() > 0.999_99;
+            let mut uvs = [sphere_uv(positions[0]), sphere_uv(positions[1]), sphere_uv(positions[2])];
+
+            // Front faces are clockwise in UVs as v is down, so a flipped triangle crosses the
+            // seam. With a pole, its other two vertices are more than half a turn apart.
+            let crosses_seam = match (0..3).find(|i| is_pole(*i)) {
+                Some(pole) => (uvs[(pole + 1) % 3].x - uvs[(pole + 2) % 3].x).abs() > 0.5,
+                None => {
+                    let (e1, e2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+                    e1.x * e2.y - e2.x * e1.y > 0.0
+                },
+            };
+            if crosses_seam {
+                uvs.iter_mut().filter(|uv| uv.x < 0.5).for_each(|uv| uv.x += 1.0);
+            }
+            (0..3).filter(|i| is_pole(*i)).for_each(|i| {
+                uvs[i].x = (uvs[(i + 1) % 3].x + uvs[(i + 2) % 3].x) * 0.5;
+            });
+
+            (0..3).for_each(|i| {
+                // Poles are split for each triangle, and other vertices on the seam once.
+                let split = if is_pole(i) { mesh.indices.len() as u32 + 1 } else { 0 };
+                let key = (triangle[i], split, uvs[i].x >= 1.0);
+                let index = *unique.entry(key).or_insert_with(|| {
+                    mesh.vertices.push(Vertex::new(positions[i], positions[i], uvs[i]));
+                    mesh.vertices.len() as u32 - 1
+                });
+                mesh.indices.push(index);
+            });
+        });
+
+        mesh.compute_tangents();
+        mesh
+    }
+
+    /// Box with the size, with a full texture on each face.
+    pub fn cube(size: XYZ<f32>) -> Self {
+        // (normal, right, up) with right × up = normal, so faces are counterclockwise.
+        let faces = [
+            (XYZ::new( 1.0, 0.0, 0.0), XYZ::new(0.0, 0.0, -1.0), XYZ::new(0.0, 1.0,  0.0)),
+            (XYZ::new(-1.0, 0.0, 0.0), XYZ::new(0.0, 0.0,  1.0), XYZ::new(0.0, 1.0,  0.0)),
+            (XYZ::new(0.0,  1.0, 0.0), XYZ::new(1.0, 0.0,  0.0), XYZ::new(0.0, 0.0, -1.0)),
+            (XYZ::new(0.0, -1.0, 0.0), XYZ::new(1.0, 0.0,  0.0), XYZ::new(0.0, 0.0,  1.0)),
+            (XYZ::new(0.0, 0.0,  1.0), XYZ::new(1.0, 0.0,  0.0), XYZ::new(0.0, 1.0,  0.0)),
+            (XYZ::new(0.0, 0.0, -1.0), XYZ::new(-1.0, 0.0, 0.0), XYZ::new(0.0, 1.0,  0.0)),
+        ];
+        let half = size * 0.5;
+
+        let mut mesh = Self::default();
+        faces.iter().for_each(|(normal, right, up)| {
+            let first = mesh.vertices.len() as u32;
+            // Top left, top right, bottom left and bottom right.
+            [(-1.0, 1.0), (1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)].iter().for_each(|(x, y)| {
+                let position = (*normal + *right * *x + *up * *y) * half;
+                let uv = XY::new((x + 1.0) * 0.5, (1.0 - y) * 0.5);
+                mesh.vertices.push(Vertex::new(position, *normal, uv));
+            });
+            mesh.indices.extend([0, 2, 3, 0, 3, 1].iter().map(|i| first + i));
+        });
+
+        mesh.compute_tangents();
+        mesh
+    }
+
+    /// Grid on the xz plane which faces +y. UVs span the whole plane with u along +x and
+    /// v along +z.
+    pub fn plane(size: XY<f32>, segments: XY<u32>) -> Self {
+        let segments = segments.max(XY::new(1, 1));
+        let mut mesh = Self::default();
+
+        (0..=segments.y).for_each(|j| {
+            (0..=segments.x).for_each(|i| {
+                let uv = XY::new(i as f32 / segments.x as f32, j as f32 / segments.y as f32);
+                let position = XYZ::new((uv.x - 0.5) * size.x, 0.0, (uv.y - 0.5) * size.y);
+                mesh.vertices.push(Vertex::new(position, XYZ::new(0.0, 1.0, 0.0), uv));
+            });
+        });
+
+        let row = segments.x + 1;
+        (0..segments.y).for_each(|j| {
+            (0..segments.x).for_each(|i| {
+                let a = j * row + i;
+                let (b, c, d) = (a + 1, a + row, a + row + 1);
+                mesh.indices.extend_from_slice(&[a, c, b, b, c, d]);
+            });
+        });
+
+        mesh.compute_tangents();
+        mesh
+    }
+
+    /// Cylinder around y with caps.
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let half = height * 0.5;
+        let profile = [
+            (radius, half, XY::new(1.0, 0.0), 0.0),
+            (radius, -half, XY::new(1.0, 0.0), 1.0),
+        ];
+
+        let mut mesh = Self::default();
+        mesh.lathe(&profile, segments);
+        mesh.cap(radius, half, 1.0, segments);
+        mesh.cap(radius, -half, -1.0, segments);
+
+        mesh.compute_tangents();
+        mesh
+    }
+
+    /// Cylinder around y with hemispheres at both ends. `height` is of the cylinder part,
+    /// and `rings` is the number of rings of each hemisphere.
+    pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        let segments = segments.max(3);
+        let rings = rings.max(1);
+        let half = height * 0.5;
+        // v is by arc length, so the texture is not stretched between the parts.
+        let total = PI * radius + height;
+
+        let hemisphere = |top: bool| {
+            (0..=rings).map(move |ring| {
+                let angle = ring as f32 / rings as f32 * PI * 0.5;
+                let (sin, cos) = if top { angle.sin_cos() } else { (angle.cos(), -angle.sin()) };
+                let y = if top { half } else { -half };
+                let arc = if top { angle * radius } else { PI * radius * 0.5 + height + angle * radius };
+                (radius * sin, y + radius * cos, XY::new(sin, cos), arc / total)
+            })
+        };
+        let profile = hemisphere(true).chain(hemisphere(false)).collect::<Vec<_>>();
+
+        let mut mesh = Self::default();
+        mesh.lathe(&profile, segments);
+
+        mesh.compute_tangents();
+        mesh
+    }
+
+    /// Revolve the profile, from top to bottom, around y. Each point is
+    /// (radius, y, (radial, y) of the normal, v).
+    fn lathe(&mut self, profile: &[(f32, f32, XY<f32>, f32)], segments: u32) {
+        let first = self.vertices.len() as u32;
+        // The seam has two columns of vertices with u = 0 and u = 1.
+        let row = segments + 1;
+
+        profile.iter().for_each(|(radius, y, normal, v)| {
+            (0..=segments).for_each(|segment| {
+                let u = segment as f32 / segments as f32;
+                let (sin, cos) = (u * 2.0 * PI).sin_cos();
+                let position = XYZ::new(radius * sin, *y, radius * cos);
+                let normal = XYZ::new(normal.x * sin, normal.y, normal.x * cos).normalize();
+                self.vertices.push(Vertex::new(position, normal, XY::new(u, *v)));
+            });
+        });
+
+        profile.windows(2).enumerate().for_each(|(ring, pair)| {
+            (0..segments).for_each(|segment| {
+                let a = first + ring as u32 * row + segment;
+                let (b, c, d) = (a + 1, a + row, a + row + 1);
+                // Triangles at a pole would be degenerate.
+                if pair[1].0.abs() > 1e-6 { self.indices.extend_from_slice(&[a, c, d]); }
+                if pair[0].0.abs() > 1e-6 { self.indices.extend_from_slice(&[a, d, b]); }
+            });
+        });
+    }
+
+    /// Disk at y which faces `sign` * y.
+    fn cap(&mut self, radius: f32, y: f32, sign: f32, segments: u32) {
+        let normal = XYZ::new(0.0, sign, 0.0);
+        let center = self.vertices.len() as u32;
+        self.vertices.push(Vertex::new(XYZ::new(0.0, y, 0.0), normal, XY::new(0.5, 0.5)));
+
+        (0..segments).for_each(|segment| {
+            let (sin, cos) = (segment as f32 / segments as f32 * 2.0 * PI).sin_cos();
+            let position = XYZ::new(radius * sin, y, radius * cos);
+            let uv = XY::new(0.5 + 0.5 * sin, 0.5 - 0.5 * sign * cos);
+            self.vertices.push(Vertex::new(position, normal, uv));
+        });
+
+        (0..segments).for_each(|segment| {
+            let a = center + 1 + segment;
+            let b = center + 1 + (segment + 1) % segments;
+            let triangle = if sign > 0.0 { [center, a, b] } else { [center, b, a] };
+            self.indices.extend_from_slice(&triangle);
+        });
+    }
+
+    /// Tangents from UVs and normals, averaged over the triangles of each vertex.
+    /// Vertices without UV gradients get any tangent perpendicular to the normal.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![XYZ::<f32>::ZERO; self.vertices.len()];
+        let mut bitangents = vec![XYZ::<f32>::ZERO; self.vertices.len()];
+
+        self.indices.chunks_exact(3).for_each(|triangle| {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (va, vb, vc) = (&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+            let (e1, e2) = (vb.position - va.position, vc.position - va.position);
+            let (d1, d2) = (vb.uv - va.uv, vc.uv - va.uv);
+
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det.abs() < 1e-12 { return; }
+            let r = 1.0 / det;
+            let tangent = (e1 * d2.y - e2 * d1.y) * r;
+            let bitangent = (e2 * d1.x - e1 * d2.x) * r;
+
+            [a, b, c].iter().for_each(|i| {
+                tangents[*i] += tangent;
+                bitangents[*i] += bitangent;
+            });
+        });
+
+        self.vertices
+            .iter_mut()
+            .zip(tangents.iter().zip(bitangents.iter()))
+            .for_each(|(vertex, (tangent, bitangent))| {
+                let n = vertex.normal;
+                // Gram-Schmidt.
+                let t = (*tangent - n * n.dot(*tangent)).try_normalize().unwrap_or_else(|| {
+                    let axis = if n.x.abs() < 0.9 { XYZ::new(1.0, 0.0, 0.0) } else { XYZ::new(0.0, 1.0, 0.0) };
+                    (axis - n * n.dot(axis)).normalize()
+                });
+                let w = if n.cross(t).dot(*bitangent) < 0.0 { -1.0 } else { 1.0 };
+                vertex.tangent = t.extend(w);
+            });
+    }
+}
+
+/// Equirectangular UV of a point on the unit sphere, with u = 0 at +z going toward +x and
+/// v = 0 at the top.
+fn sphere_uv(p: XYZ<f32>) -> XY<f32> {
+    let u = p.x.atan2(p.z) / (2.0 * PI);
+    XY::new(if u < 0.0 { u + 1.0 } else { u }, p.y.clamp(-1.0, 1.0).acos() / PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Closed meshes, which are all convex and centered at the origin.
+    fn closed_meshes() -> Vec<(&'static str, Mesh)> {
+        vec![
+            ("icosahedron", Mesh::icosahedron()),
+            ("icosphere", Mesh::icosphere(3)),
+            ("cube", Mesh::cube(XYZ::new(1.0, 2.0, 3.0))),
+            ("cylinder", Mesh::cylinder(0.5, 2.0, 16)),
+            ("capsule", Mesh::capsule(0.5, 1.0, 16, 4)),
+        ]
+    }
+
+    /// Triangles with indices of vertices welded by position, so that vertices split at
+    /// seams are the same.
+    fn welded_triangles(mesh: &Mesh) -> Vec<[u32; 3]> {
+        let mut welded = HashMap::new();
+        let indices = mesh.vertices
+            .iter()
+            .map(|vertex| {
+                let p = vertex.position * 1e4;
+                let key = (p.x.round() as i32, p.y.round() as i32, p.z.round() as i32);
+                let next = welded.len() as u32;
+                *welded.entry(key).or_insert(next)
+            })
+            .collect::<Vec<_>>();
+
+        mesh.indices
+            .chunks_exact(3)
+            .map(|t| [indices[t[0] as usize], indices[t[1] as usize], indices[t[2] as usize]])
+            .collect()
+    }
+
+    fn face_normal(mesh: &Mesh, triangle: &[u32]) -> XYZ<f32> {
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position);
+        (b - a).cross(c - a).normalize()
+    }
+
+    #[test]
+    fn closed_and_consistently_wound() {
+        closed_meshes().iter().for_each(|(name, mesh)| {
+            let mut edges = HashMap::new();
+            welded_triangles(mesh).iter().for_each(|&[a, b, c]| {
+                assert!(a != b && b != c && c != a, "{}: degenerate triangle", name);
+                [(a, b), (b, c), (c, a)].iter().for_each(|edge| *edges.entry(*edge).or_insert(0) += 1);
+            });
+
+            // Each edge is in one triangle each way, so exactly two triangles share it with
+            // opposite winding.
+            edges.iter().for_each(|(&(a, b), &count)| {
+                assert_eq!(count, 1, "{}: edge {:?} is repeated in the same direction", name, (a, b));
+                assert_eq!(edges.get(&(b, a)), Some(&1), "{}: edge {:?} is open", name, (a, b));
+            });
+        });
+    }
+
+    #[test]
+    fn faces_point_outward() {
+        closed_meshes().iter().for_each(|(name, mesh)| {
+            mesh.indices.chunks_exact(3).for_each(|triangle| {
+                let centroid = triangle
+                    .iter()
+                    .fold(XYZ::ZERO, |sum, i| sum + mesh.vertices[*i as usize].position) * (1.0 / 3.0);
+                let normal = face_normal(mesh, triangle);
+                assert!(normal.dot(centroid) > 0.0, "{}: triangle {:?} faces inward", name, triangle);
+            });
+        });
+    }
+
+    #[test]
+    fn vertex_normals_agree_with_faces() {
+        closed_meshes().iter().for_each(|(name, mesh)| {
+            mesh.indices.chunks_exact(3).for_each(|triangle| {
+                let normal = face_normal(mesh, triangle);
+                triangle.iter().for_each(|i| {
+                    let vertex = mesh.vertices[*i as usize].normal;
+                    assert!((vertex.length() - 1.0).abs() < 1e-5, "{}: vertex {} is not unit", name, i);
+                    // The icosahedron is the coarsest, with 37 degrees between them.
+                    assert!(vertex.dot(normal) > 0.75, "{}: vertex {} disagrees with its triangle", name, i);
+                });
+            });
+        });
+    }
+}
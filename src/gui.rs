@@ -1,24 +1,32 @@
+use crate::linear_algebra::XY;
+
 use std::ops::Range;
 
 pub struct Texture;
 
 
 pub struct Button {
-    hit_box: HitBox,
-    texture_index: usize,
+    pub hit_box: HitBox,
+    pub texture_index: usize,
 }
 
 pub struct HitBox {
-    range_of_x: Range<f32>,
-    range_of_y: Range<f32>,
+    pub range_of_x: Range<f32>,
+    pub range_of_y: Range<f32>,
+}
+
+impl HitBox {
+    #[inline]
+    pub fn contains(&self, point: XY<f32>) -> bool {
+        self.range_of_x.contains(&point.x) && self.range_of_y.contains(&point.y)
+    }
 }
 
 impl Button {
     pub fn builder() {}
 
-    pub fn hit_and_then<F: FnMut()>(input: (), mut behavior: F) {
-        let hit = unimplemented!();
-        if hit { behavior() }
+    /// Call `behavior` if the point, e.g. the cursor of `Mouse::position`, is on the button.
+    pub fn hit_and_then<F: FnMut()>(&self, point: XY<f32>, mut behavior: F) {
+        if self.hit_box.contains(point) { behavior() }
     }
 }
-
@@ -4,6 +4,10 @@ use crate::linear_algebra::*;
 
 use std::collections::HashMap;
 
+mod camera;
+
+pub use camera::{ OrbitController, FlyController };
+
 #[derive(Debug)]
 pub enum KeyState {
     Pressed,
@@ -12,12 +16,6 @@ pub enum KeyState {
     JustReleased,
 }
 
-#[derive(Clone, Debug)]
-pub struct Key {
-    previous: ElementState,
-    current: ElementState,
-}
-
 #[derive(Debug)]
 pub enum WheelState {
     ScrollUp,
@@ -32,6 +30,7 @@ pub struct Mouse {
     pub wheel: WheelState,
     /// -1.0 <= x, y <= 1.0
     pub position: XY<f32>,
+    /// Sum of raw motions since the last `clear`.
     pub raw_move: XY<f32>,
 }
 
@@ -47,14 +46,6 @@ pub struct InputDevices {
     logical_size: XY<f32>,
 }
 
-impl Key {
-    // TODO: (?_?)
-    fn update(&mut self, next: ElementState) {
-        self.previous = self.current;
-        self.current = next;
-    }
-}
-
 impl KeyState {
     fn update_by_event(&mut self, next_key_element_state: &ElementState) {
         match next_key_element_state {
@@ -73,6 +64,14 @@ impl KeyState {
         }
     }
 
+    /// `Pressed` or `JustPressed`.
+    pub fn is_down(&self) -> bool {
+        match self {
+            KeyState::Pressed | KeyState::JustPressed => true,
+            KeyState::Released | KeyState::JustReleased => false,
+        }
+    }
+
     fn clear(&mut self) {
         match &self {
             KeyState::JustPressed => *self = KeyState::Pressed,
@@ -98,6 +97,8 @@ impl Mouse {
     fn clear(&mut self) {
         self.left.clear();
         self.right.clear();
+        self.wheel = WheelState::None;
+        self.raw_move = XY::default();
     }
 }
 
@@ -150,10 +151,21 @@ impl InputDevices {
             }
             Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. } => {
                 let position = XY::new(position.x as f32, position.y as f32);
-                self.mouse.position = position.normalize(&self.logical_size);
+                self.mouse.position = self.normalize(position);
+            },
+            Event::WindowEvent { event: WindowEvent::MouseWheel { delta, .. }, .. } => {
+                let y = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                };
+                if y > 0.0 {
+                    self.mouse.wheel = WheelState::ScrollUp;
+                } else if y < 0.0 {
+                    self.mouse.wheel = WheelState::ScrollDown;
+                }
             },
             Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta: (x, y) }, ..} => {
-                self.mouse.raw_move = XY::new(*x as f32, *y as f32);
+                self.mouse.raw_move += XY::new(*x as f32, *y as f32);
             },
 
             // Keyboard update
@@ -179,6 +191,12 @@ impl InputDevices {
         self.mouse.clear();
         self.keyboard.clear();
     }
+
+    /// Map a position in the window to `-1.0 <= x, y <= 1.0`, the normalized device
+    /// coordinates of Vulkan.
+    fn normalize(&self, position: XY<f32>) -> XY<f32> {
+        position / self.logical_size * 2.0 - XY::splat(1.0)
+    }
 }
 
 
@@ -0,0 +1,125 @@
+//! Controllers which move a `Camera` by the input.
+//!
+//! Angles are in radians. Yaw turns around y and zero looks toward -z, and a positive pitch
+//! looks up.
+
+use winit::VirtualKeyCode;
+
+use crate::linear_algebra::{ XY, XYZ, Mat2, Quat, Camera };
+
+use super::{ InputDevices, WheelState };
+
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+/// Pitch is kept off the poles, where the view matrix is undefined.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const UP: XYZ<f32> = XYZ { x: 0.0, y: 1.0, z: 0.0 };
+
+/// Third-person camera which orbits around the target, e.g. the player.
+/// Dragging with the right button rotates and the wheel zooms.
+pub struct OrbitController {
+    pub target: XYZ<f32>,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    /// Keeps the camera out of the target, so it never needs a collision test.
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Radians per unit of the raw mouse motion.
+    pub sensitivity: f32,
+    /// Ratio of the distance to zoom by for each wheel step.
+    pub zoom_step: f32,
+}
+
+/// Free-fly debug camera. WASD moves horizontally in the view, space and left shift move
+/// up and down, and the raw mouse motion looks around.
+pub struct FlyController {
+    pub position: XYZ<f32>,
+    yaw: f32,
+    pitch: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per unit of the raw mouse motion.
+    pub sensitivity: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: XYZ<f32>, distance: f32) -> Self {
+        Self {
+            target,
+            yaw: 0.0,
+            pitch: -0.4,
+            distance,
+            min_distance: 1.5,
+            max_distance: 50.0,
+            sensitivity: 0.005,
+            zoom_step: 0.1,
+        }
+    }
+
+    #[inline]
+    pub fn distance(&self) -> f32 { self.distance }
+
+    pub fn update(&mut self, input: &InputDevices) {
+        if input.mouse.right.is_down() {
+            self.yaw -= input.mouse.raw_move.x * self.sensitivity;
+            self.pitch = (self.pitch - input.mouse.raw_move.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        match input.mouse.wheel {
+            WheelState::ScrollUp => self.distance *= 1.0 - self.zoom_step,
+            WheelState::ScrollDown => self.distance *= 1.0 + self.zoom_step,
+            WheelState::None => (),
+        }
+        self.distance = self.distance.max(self.min_distance).min(self.max_distance);
+    }
+
+    pub fn camera(&self) -> Camera {
+        let forward = Quat::from_euler(self.yaw, self.pitch, 0.0).rotate(XYZ::new(0.0, 0.0, -1.0));
+        Camera::new(self.target - forward * self.distance, forward, UP)
+    }
+}
+
+impl FlyController {
+    pub fn new(position: XYZ<f32>) -> Self {
+        Self {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 10.0,
+            sensitivity: 0.003,
+        }
+    }
+
+    /// `delta` is the time since the last update.
+    pub fn update(&mut self, input: &InputDevices, delta: Duration) {
+        self.yaw -= input.mouse.raw_move.x * self.sensitivity;
+        self.pitch = (self.pitch - input.mouse.raw_move.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+
+        // Horizontal directions are (x, z) in the view, turned by the yaw. Turning toward +x
+        // around y is clockwise in (x, z).
+        let keys = [
+            (VirtualKeyCode::W, XY::new(0.0, -1.0)),
+            (VirtualKeyCode::S, XY::new(0.0, 1.0)),
+            (VirtualKeyCode::D, XY::new(1.0, 0.0)),
+            (VirtualKeyCode::A, XY::new(-1.0, 0.0)),
+        ];
+        let horizontal = keys
+            .iter()
+            .filter(|(key, _)| input.keyboard.get(key).is_down())
+            .fold(XY::<f32>::ZERO, |sum, (_, direction)| sum + *direction);
+        let horizontal = Mat2::rotation(-self.yaw) * horizontal;
+
+        let is_down = |key| input.keyboard.get(&key).is_down() as i32 as f32;
+        let vertical = is_down(VirtualKeyCode::Space) - is_down(VirtualKeyCode::LShift);
+        let direction = XYZ::new(horizontal.x, vertical, horizontal.y);
+
+        self.position += direction.normalize() * (self.speed * delta.as_secs_f32());
+    }
+
+    pub fn camera(&self) -> Camera {
+        let forward = Quat::from_euler(self.yaw, self.pitch, 0.0).rotate(XYZ::new(0.0, 0.0, -1.0));
+        Camera::new(self.position, forward, UP)
+    }
+}
@@ -0,0 +1,14 @@
+//! The engine is a library, so that benches can link it. The binary runs `engine::run`.
+
+pub mod utility;
+pub mod linear_algebra;
+// Macros must be defined before the modules using them.
+#[macro_use]
+pub mod vulkan;
+pub mod window;
+pub mod engine;
+pub mod graphics;
+//pub mod player;
+pub mod field;
+pub mod gui;
+pub mod input;
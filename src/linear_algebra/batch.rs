@@ -0,0 +1,362 @@
+//! Transforms and bounds of many vectors at once.
+//!
+//! On x86 and x86_64 these use AVX or SSE, which is detected at run time, and fall back to
+//! scalar code elsewhere. The results are the same as `Mat4::transform_point` and friends
+//! up to rounding.
+
+use super::{ XYZ, Mat3, Mat4, Aabb };
+
+/// Transform positions, with w = 1. `out` must have the same length as `points`.
+pub fn transform_points(m: &Mat4, points: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+    transform(m, 1.0, points, out);
+}
+
+/// Transform directions, with w = 0, so translation is ignored.
+/// `out` must have the same length as `vectors`.
+pub fn transform_vectors(m: &Mat4, vectors: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+    transform(m, 0.0, vectors, out);
+}
+
+/// Transform normals by the inverse transpose of the upper left 3x3 part of a model matrix.
+/// They are not normalized, so normalize them if the matrix scales.
+/// Returns `false` if the matrix is singular, and then `out` is filled with zero vectors.
+pub fn transform_normals(m: &Mat4, normals: &[XYZ<f32>], out: &mut [XYZ<f32>]) -> bool {
+    match m.normal_matrix() {
+        Some(normal_matrix) => {
+            transform(&Mat4::from_mat3(&normal_matrix), 0.0, normals, out);
+            true
+        }
+        None => {
+            assert_eq!(normals.len(), out.len(), "The output must have the same length as the input.");
+            out.iter_mut().for_each(|o| *o = XYZ::ZERO);
+            false
+        }
+    }
+}
+
+/// `Aabb::EMPTY` if there are no points.
+pub fn bounding_box(points: &[XYZ<f32>]) -> Aabb {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { x86::bounding_box_avx(points) };
+        }
+        if is_x86_feature_detected!("sse") {
+            return unsafe { x86::bounding_box_sse(points) };
+        }
+    }
+
+    scalar::bounding_box(points)
+}
+
+fn transform(m: &Mat4, w: f32, input: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+    assert_eq!(input.len(), out.len(), "The output must have the same length as the input.");
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { x86::transform_avx(m, w, input, out) };
+        }
+        if is_x86_feature_detected!("sse") {
+            return unsafe { x86::transform_sse(m, w, input, out) };
+        }
+    }
+
+    scalar::transform(m, w, input, out)
+}
+
+mod scalar {
+    use super::{ XYZ, Mat4, Mat3, Aabb };
+
+    pub(super) fn transform(m: &Mat4, w: f32, input: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+        let rotation = Mat3::from_mat4(m);
+        let translation = m[3].truncate() * w;
+        input
+            .iter()
+            .zip(out.iter_mut())
+            .for_each(|(v, o)| *o = rotation * *v + translation);
+    }
+
+    pub(super) fn bounding_box(points: &[XYZ<f32>]) -> Aabb {
+        Aabb::from_points(points.iter().cloned())
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{ XYZ, Mat4, Aabb };
+
+    use std::slice;
+
+    /// Columns of the matrix, with the translation scaled by w.
+    #[inline]
+    #[target_feature(enable = "sse")]
+    unsafe fn load_columns(m: &Mat4, w: f32) -> [__m128; 4] {
+        [
+            _mm_loadu_ps(&m.columns[0].x),
+            _mm_loadu_ps(&m.columns[1].x),
+            _mm_loadu_ps(&m.columns[2].x),
+            _mm_mul_ps(_mm_loadu_ps(&m.columns[3].x), _mm_set1_ps(w)),
+        ]
+    }
+
+    /// Store xyz of the vector. It writes 4 floats when `overrun` is true, so the float after
+    /// `out` must be writable and overwritten later.
+    #[inline]
+    #[target_feature(enable = "sse")]
+    unsafe fn store(out: *mut XYZ<f32>, v: __m128, overrun: bool) {
+        if overrun {
+            _mm_storeu_ps(out as *mut f32, v);
+        } else {
+            let mut xyzw = [0.0_f32; 4];
+            _mm_storeu_ps(xyzw.as_mut_ptr(), v);
+            *out = XYZ::new(xyzw[0], xyzw[1], xyzw[2]);
+        }
+    }
+
+    /// One vector at a time in a register of (x, y, z, w).
+    #[target_feature(enable = "sse")]
+    pub(super) unsafe fn transform_sse(m: &Mat4, w: f32, input: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+        let [c0, c1, c2, c3] = load_columns(m, w);
+        let last = input.len().wrapping_sub(1);
+        let out = out.as_mut_ptr();
+
+        // Loops instead of closures, which don't inherit the target feature.
+        for (i, v) in input.iter().enumerate() {
+            let xy = _mm_add_ps(_mm_mul_ps(c0, _mm_set1_ps(v.x)), _mm_mul_ps(c1, _mm_set1_ps(v.y)));
+            let zw = _mm_add_ps(_mm_mul_ps(c2, _mm_set1_ps(v.z)), c3);
+            // The extra float lands on the next vector, which is written after this.
+            store(out.add(i), _mm_add_ps(xy, zw), i != last);
+        }
+    }
+
+    /// Two vectors at a time in the lower and upper halves of a register.
+    #[target_feature(enable = "avx")]
+    pub(super) unsafe fn transform_avx(m: &Mat4, w: f32, input: &[XYZ<f32>], out: &mut [XYZ<f32>]) {
+        let columns = load_columns(m, w);
+        let c0 = _mm256_set_m128(columns[0], columns[0]);
+        let c1 = _mm256_set_m128(columns[1], columns[1]);
+        let c2 = _mm256_set_m128(columns[2], columns[2]);
+        let c3 = _mm256_set_m128(columns[3], columns[3]);
+        let len = input.len();
+        let out = out.as_mut_ptr();
+        let pairs = input.chunks_exact(2);
+        let rest = pairs.remainder();
+
+        for (pair, vs) in pairs.enumerate() {
+            let (a, b) = (&vs[0], &vs[1]);
+            let x = _mm256_set_m128(_mm_set1_ps(b.x), _mm_set1_ps(a.x));
+            let y = _mm256_set_m128(_mm_set1_ps(b.y), _mm_set1_ps(a.y));
+            let z = _mm256_set_m128(_mm_set1_ps(b.z), _mm_set1_ps(a.z));
+            let xy = _mm256_add_ps(_mm256_mul_ps(c0, x), _mm256_mul_ps(c1, y));
+            let zw = _mm256_add_ps(_mm256_mul_ps(c2, z), c3);
+            let r = _mm256_add_ps(xy, zw);
+
+            let i = pair * 2;
+            store(out.add(i), _mm256_castps256_ps128(r), true);
+            store(out.add(i + 1), _mm256_extractf128_ps(r, 1), i + 1 != len - 1);
+        }
+
+        if let [v] = rest {
+            let [c0, c1, c2, c3] = columns;
+            let xy = _mm_add_ps(_mm_mul_ps(c0, _mm_set1_ps(v.x)), _mm_mul_ps(c1, _mm_set1_ps(v.y)));
+            let zw = _mm_add_ps(_mm_mul_ps(c2, _mm_set1_ps(v.z)), c3);
+            store(out.add(len - 1), _mm_add_ps(xy, zw), false);
+        }
+    }
+
+    /// Min and max over the floats of the points as one array. A chunk of 4 points is 12
+    /// floats in 3 registers, so lane `j` of register `k` always holds component
+    /// `(4 * k + j) % 3`.
+    #[target_feature(enable = "sse")]
+    pub(super) unsafe fn bounding_box_sse(points: &[XYZ<f32>]) -> Aabb {
+        let floats = slice::from_raw_parts(points.as_ptr() as *const f32, points.len() * 3);
+        let chunks = floats.chunks_exact(12);
+        let rest = chunks.remainder();
+
+        let mut min = [_mm_set1_ps(f32::INFINITY); 3];
+        let mut max = [_mm_set1_ps(f32::NEG_INFINITY); 3];
+        for chunk in chunks {
+            for k in 0..3 {
+                let v = _mm_loadu_ps(chunk.as_ptr().add(4 * k));
+                min[k] = _mm_min_ps(min[k], v);
+                max[k] = _mm_max_ps(max[k], v);
+            }
+        }
+
+        let mut lanes_min = [0.0_f32; 12];
+        let mut lanes_max = [0.0_f32; 12];
+        for k in 0..3 {
+            _mm_storeu_ps(lanes_min.as_mut_ptr().add(4 * k), min[k]);
+            _mm_storeu_ps(lanes_max.as_mut_ptr().add(4 * k), max[k]);
+        }
+
+        reduce(&lanes_min, &lanes_max, rest)
+    }
+
+    /// Like `bounding_box_sse` with chunks of 8 points in 3 registers of 8 lanes.
+    #[target_feature(enable = "avx")]
+    pub(super) unsafe fn bounding_box_avx(points: &[XYZ<f32>]) -> Aabb {
+        let floats = slice::from_raw_parts(points.as_ptr() as *const f32, points.len() * 3);
+        let chunks = floats.chunks_exact(24);
+        let rest = chunks.remainder();
+
+        let mut min = [_mm256_set1_ps(f32::INFINITY); 3];
+        let mut max = [_mm256_set1_ps(f32::NEG_INFINITY); 3];
+        for chunk in chunks {
+            for k in 0..3 {
+                let v = _mm256_loadu_ps(chunk.as_ptr().add(8 * k));
+                min[k] = _mm256_min_ps(min[k], v);
+                max[k] = _mm256_max_ps(max[k], v);
+            }
+        }
+
+        let mut lanes_min = [0.0_f32; 24];
+        let mut lanes_max = [0.0_f32; 24];
+        for k in 0..3 {
+            _mm256_storeu_ps(lanes_min.as_mut_ptr().add(8 * k), min[k]);
+            _mm256_storeu_ps(lanes_max.as_mut_ptr().add(8 * k), max[k]);
+        }
+
+        reduce(&lanes_min, &lanes_max, rest)
+    }
+
+    /// Fold lanes, in which float `i` is component `i % 3`, and the remaining floats.
+    fn reduce(lanes_min: &[f32], lanes_max: &[f32], rest: &[f32]) -> Aabb {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        lanes_min.iter().zip(lanes_max.iter()).chain(rest.iter().zip(rest.iter()))
+            .enumerate()
+            .for_each(|(i, (lane_min, lane_max))| {
+                min[i % 3] = min[i % 3].min(*lane_min);
+                max[i % 3] = max[i % 3].max(*lane_max);
+            });
+
+        Aabb::new(XYZ::from(min), XYZ::from(max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_algebra::XYZW;
+
+    type TransformFn = unsafe fn(&Mat4, f32, &[XYZ<f32>], &mut [XYZ<f32>]);
+    type BoundingBoxFn = unsafe fn(&[XYZ<f32>]) -> Aabb;
+
+    /// Lengths around the widths of registers and chunks.
+    const LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 7, 8, 9, 11, 12, 13, 23, 24, 25, 101];
+
+    fn points(len: usize) -> Vec<XYZ<f32>> {
+        (0..len)
+            .map(|i| {
+                let i = i as f32;
+                XYZ::new((i * 1.7).sin() * 10.0, (i * 0.3).cos() * 5.0 - i, i * 0.25 - 3.0)
+            })
+            .collect()
+    }
+
+    fn matrix() -> Mat4 {
+        Mat4::from_columns(
+            XYZW::new(0.8, 0.6, 0.0, 0.0),
+            XYZW::new(-1.2, 1.6, 0.5, 0.0),
+            XYZW::new(0.0, -0.3, 2.0, 0.0),
+            XYZW::new(4.0, -5.0, 6.0, 1.0),
+        )
+    }
+
+    fn assert_close(actual: &[XYZ<f32>], expected: &[XYZ<f32>]) {
+        assert_eq!(actual.len(), expected.len());
+        actual.iter().zip(expected.iter()).for_each(|(a, e)| {
+            let error = (*a - *e).length();
+            assert!(error <= 1e-5 * (1.0 + e.length()), "{:?} != {:?}", a, e);
+        });
+    }
+
+    /// Transform with `f` into a slice followed by a sentinel, which must be left as it is.
+    fn transform_with(f: TransformFn, w: f32, input: &[XYZ<f32>]) -> Vec<XYZ<f32>> {
+        let sentinel = XYZ::new(-7.0, -7.0, -7.0);
+        let mut out = vec![sentinel; input.len() + 1];
+        unsafe { f(&matrix(), w, input, &mut out[..input.len()]); }
+        assert_eq!(out.pop(), Some(sentinel), "written past the end");
+        out
+    }
+
+    fn scalar_transform(w: f32, input: &[XYZ<f32>]) -> Vec<XYZ<f32>> {
+        let mut out = vec![XYZ::ZERO; input.len()];
+        scalar::transform(&matrix(), w, input, &mut out);
+        out
+    }
+
+    #[test]
+    fn matches_matrix() {
+        let m = matrix();
+        let input = points(9);
+        let mut out = vec![XYZ::ZERO; input.len()];
+        transform_points(&m, &input, &mut out);
+        assert_close(&out, &input.iter().map(|p| m.transform_point(*p)).collect::<Vec<_>>());
+        transform_vectors(&m, &input, &mut out);
+        assert_close(&out, &input.iter().map(|v| m.transform_vector(*v)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn singular_normals() {
+        let mut m = matrix();
+        m.columns[2] = XYZW::new(0.0, 0.0, 0.0, 0.0);
+        let input = points(5);
+        let mut out = vec![XYZ::new(1.0, 1.0, 1.0); input.len()];
+        assert!(!transform_normals(&m, &input, &mut out));
+        assert!(out.iter().all(|n| *n == XYZ::ZERO));
+        assert!(transform_normals(&matrix(), &input, &mut out));
+    }
+
+    #[test]
+    fn empty_bounding_box() {
+        assert_eq!(bounding_box(&[]), Aabb::EMPTY);
+        assert_eq!(scalar::bounding_box(&[]), Aabb::EMPTY);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn x86_transform_matches_scalar() {
+        let mut functions: Vec<TransformFn> = Vec::new();
+        if is_x86_feature_detected!("sse") {
+            functions.push(x86::transform_sse);
+        }
+        if is_x86_feature_detected!("avx") {
+            functions.push(x86::transform_avx);
+        }
+
+        for &len in LENGTHS.iter() {
+            let input = points(len);
+            for &w in [0.0, 1.0].iter() {
+                let expected = scalar_transform(w, &input);
+                functions.iter().for_each(|f| assert_close(&transform_with(*f, w, &input), &expected));
+            }
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn x86_bounding_box_matches_scalar() {
+        let mut functions: Vec<BoundingBoxFn> = Vec::new();
+        if is_x86_feature_detected!("sse") {
+            functions.push(x86::bounding_box_sse);
+        }
+        if is_x86_feature_detected!("avx") {
+            functions.push(x86::bounding_box_avx);
+        }
+
+        for &len in LENGTHS.iter() {
+            let input = points(len);
+            let expected = scalar::bounding_box(&input);
+            // Min and max are exact.
+            functions.iter().for_each(|f| assert_eq!(unsafe { f(&input) }, expected, "length {}", len));
+        }
+    }
+}
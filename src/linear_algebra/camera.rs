@@ -0,0 +1,121 @@
+//! Pose of the camera and parameters of projections.
+//!
+//! Matrices are right-handed and look toward -z. Projections follow Vulkan: clip y is down
+//! and depth is in [0, 1].
+
+use super::{ XY, XYZ, Mat4, Frustum, Ray };
+
+pub struct Camera {
+    pos: XYZ<f32>,
+    up: XYZ<f32>,
+    dir: XYZ<f32>,
+}
+
+/// Parameters of projections.
+#[derive(Copy, Clone, Debug)]
+pub struct Lens {
+    /// Vertical field of view in radians for the perspective projection.
+    pub fov_y: f32,
+    /// Height of the view volume for the orthographic projection.
+    pub height: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(pos: XYZ<f32>, dir: XYZ<f32>, up: XYZ<f32>) -> Self {
+        Self { pos, up, dir }
+    }
+
+    #[inline]
+    pub fn position(&self) -> XYZ<f32> { self.pos }
+    #[inline]
+    pub fn direction(&self) -> XYZ<f32> { self.dir }
+    #[inline]
+    pub fn up(&self) -> XYZ<f32> { self.up }
+    #[inline]
+    pub fn set_position(&mut self, pos: XYZ<f32>) { self.pos = pos; }
+    #[inline]
+    pub fn set_direction(&mut self, dir: XYZ<f32>) { self.dir = dir; }
+
+    /// Turn toward `target`.
+    #[inline]
+    pub fn look_at(&mut self, target: XYZ<f32>) { self.dir = target - self.pos; }
+
+    /// Unit vector to the right of the view.
+    #[inline]
+    pub fn right(&self) -> XYZ<f32> { self.axes().0 }
+
+    /// Right-handed view matrix which looks toward -z.
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_to(self.pos, self.dir, self.up)
+    }
+
+    /// Inverse of `view_matrix`.
+    pub fn inverse_view_matrix(&self) -> Mat4 {
+        let (s, u, f) = self.axes();
+        Mat4::from_columns(s.extend(0.0), u.extend(0.0), (-f).extend(0.0), self.pos.extend(1.0))
+    }
+
+    /// Perspective projection * view.
+    pub fn view_projection(&self, lens: &Lens, aspect: f32) -> Mat4 {
+        lens.perspective(aspect) * self.view_matrix()
+    }
+
+    /// View volume of the perspective projection in world coordinates, for culling.
+    pub fn frustum(&self, lens: &Lens, aspect: f32) -> Frustum {
+        Frustum::from_matrix(&self.view_projection(lens, aspect))
+    }
+
+    /// Ray through the point in normalized device coordinates of the perspective
+    /// projection, e.g. `Mouse::position` for picking.
+    pub fn ray(&self, ndc: XY<f32>, lens: &Lens, aspect: f32) -> Ray {
+        let inverse = self.inverse_view_matrix() * lens.inverse_perspective(aspect);
+        Ray::from_ndc(ndc, &inverse)
+    }
+
+    /// Orthonormal (side, up, forward) axes.
+    fn axes(&self) -> (XYZ<f32>, XYZ<f32>, XYZ<f32>) {
+        let f = self.dir.normalize();
+        let s = f.cross(self.up).normalize();
+        let u = s.cross(f);
+        (s, u, f)
+    }
+}
+
+impl Lens {
+    pub fn perspective(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective(self.fov_y, aspect, self.near, self.far)
+    }
+
+    pub fn inverse_perspective(&self, aspect: f32) -> Mat4 {
+        let f = 1.0 / (self.fov_y * 0.5).tan();
+        let depth = self.near - self.far;
+        let c = self.far / depth;
+        let d = self.near * self.far / depth;
+        Mat4::from([
+            [aspect / f, 0.0, 0.0, 0.0],
+            [0.0, -1.0 / f, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0 / d],
+            [0.0, 0.0, -1.0, c / d],
+        ])
+    }
+
+    pub fn orthographic(&self, aspect: f32) -> Mat4 {
+        let half_height = self.height * 0.5;
+        let half_width = half_height * aspect;
+        Mat4::orthographic(-half_width, half_width, -half_height, half_height, self.near, self.far)
+    }
+
+    pub fn inverse_orthographic(&self, aspect: f32) -> Mat4 {
+        let half_height = self.height * 0.5;
+        let half_width = half_height * aspect;
+        let depth = self.far - self.near;
+        Mat4::from([
+            [half_width, 0.0, 0.0, 0.0],
+            [0.0, -half_height, 0.0, 0.0],
+            [0.0, 0.0, -depth, 0.0],
+            [0.0, 0.0, -self.near, 1.0],
+        ])
+    }
+}
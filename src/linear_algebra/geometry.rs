@@ -0,0 +1,437 @@
+//! Primitives and intersection tests for picking and culling.
+//!
+//! Tests which hit return the parameter `t` of the ray at the nearest hit, so the point is
+//! `ray.at(t)`. Rays starting inside of a volume hit it at `t = 0.0`.
+
+use super::{ XY, XYZ, XYZW, Mat4 };
+
+/// Axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: XYZ<f32>,
+    pub max: XYZ<f32>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: XYZ<f32>,
+    pub radius: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: XYZ<f32>,
+    /// Unit vector.
+    pub direction: XYZ<f32>,
+}
+
+/// Points `p` with `normal.dot(p) + distance == 0`. The normal is a unit vector and points
+/// to the positive side.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: XYZ<f32>,
+    pub distance: f32,
+}
+
+/// Six planes bounding a view volume, with normals toward the inside.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far.
+    planes: [Plane; 6],
+}
+
+impl Aabb {
+    /// Contains nothing, and `expand` of it contains only the point.
+    pub const EMPTY: Self = Self {
+        min: XYZ { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: XYZ { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY },
+    };
+
+    #[inline]
+    pub fn new(min: XYZ<f32>, max: XYZ<f32>) -> Self { Self { min, max } }
+
+    /// `EMPTY` if there are no points.
+    pub fn from_points<I: IntoIterator<Item = XYZ<f32>>>(points: I) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, point| aabb.expand(point))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    #[inline]
+    pub fn center(&self) -> XYZ<f32> { (self.min + self.max) * 0.5 }
+
+    /// Half of the size.
+    #[inline]
+    pub fn extents(&self) -> XYZ<f32> { (self.max - self.min) * 0.5 }
+
+    #[inline]
+    pub fn expand(&self, point: XYZ<f32>) -> Self {
+        Self::new(self.min.min(point), self.max.max(point))
+    }
+
+    #[inline]
+    pub fn union(&self, rhs: &Self) -> Self {
+        Self::new(self.min.min(rhs.min), self.max.max(rhs.max))
+    }
+
+    #[inline]
+    pub fn contains_point(&self, point: XYZ<f32>) -> bool {
+        self.min.x <= point.x && point.x <= self.max.x
+            && self.min.y <= point.y && point.y <= self.max.y
+            && self.min.z <= point.z && point.z <= self.max.z
+    }
+
+    #[inline]
+    pub fn intersects(&self, rhs: &Self) -> bool {
+        self.min.x <= rhs.max.x && rhs.min.x <= self.max.x
+            && self.min.y <= rhs.max.y && rhs.min.y <= self.max.y
+            && self.min.z <= rhs.max.z && rhs.min.z <= self.max.z
+    }
+
+    /// The box which bounds this box transformed by the matrix, e.g. a model matrix.
+    pub fn transform(&self, m: &Mat4) -> Self {
+        let center = m.transform_point(self.center());
+        let extents = self.extents();
+        // Extents along each axis are the sums of the absolute columns.
+        let abs = |column: XYZW<f32>| column.truncate().map(f32::abs);
+        let extents = abs(m[0]) * extents.x + abs(m[1]) * extents.y + abs(m[2]) * extents.z;
+        Self::new(center - extents, center + extents)
+    }
+
+    /// The sphere which bounds this box.
+    #[inline]
+    pub fn bounding_sphere(&self) -> Sphere { Sphere::new(self.center(), self.extents().length()) }
+}
+
+impl Sphere {
+    #[inline]
+    pub fn new(center: XYZ<f32>, radius: f32) -> Self { Self { center, radius } }
+
+    #[inline]
+    pub fn contains_point(&self, point: XYZ<f32>) -> bool {
+        self.center.distance(point) <= self.radius
+    }
+
+    #[inline]
+    pub fn intersects(&self, rhs: &Self) -> bool {
+        let radius = self.radius + rhs.radius;
+        (rhs.center - self.center).length_squared() <= radius * radius
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let closest = self.center.clamp(aabb.min, aabb.max);
+        (closest - self.center).length_squared() <= self.radius * self.radius
+    }
+}
+
+impl Ray {
+    /// The direction is normalized.
+    #[inline]
+    pub fn new(origin: XYZ<f32>, direction: XYZ<f32>) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    /// Ray through a point in normalized device coordinates, e.g. `Mouse::position`, from
+    /// the near plane toward the far plane. `inverse_view_projection` is the inverse of
+    /// projection * view with Vulkan depth in [0, 1].
+    pub fn from_ndc(ndc: XY<f32>, inverse_view_projection: &Mat4) -> Self {
+        let unproject = |depth: f32| {
+            let p = *inverse_view_projection * XYZW::new(ndc.x, ndc.y, depth, 1.0);
+            p.truncate() / p.w
+        };
+        let near = unproject(0.0);
+        Self::new(near, unproject(1.0) - near)
+    }
+
+    #[inline]
+    pub fn at(&self, t: f32) -> XYZ<f32> { self.origin + self.direction * t }
+
+    /// Slab test.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let slabs = [
+            (self.origin.x, self.direction.x, aabb.min.x, aabb.max.x),
+            (self.origin.y, self.direction.y, aabb.min.y, aabb.max.y),
+            (self.origin.z, self.direction.z, aabb.min.z, aabb.max.z),
+        ];
+        let mut enter = 0.0_f32;
+        let mut exit = f32::INFINITY;
+        for &(origin, direction, min, max) in slabs.iter() {
+            // Parallel to the slab, where the planes would give 0 * inf = NaN on a face.
+            if direction == 0.0 {
+                if origin < min || max < origin { return None; }
+                continue;
+            }
+            let inverse = 1.0 / direction;
+            let t0 = (min - origin) * inverse;
+            let t1 = (max - origin) * inverse;
+            enter = enter.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+        if enter <= exit { Some(enter) } else { None }
+    }
+
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let offset = self.origin - sphere.center;
+        let b = offset.dot(self.direction);
+        let c = offset.length_squared() - sphere.radius * sphere.radius;
+        // Outside and pointing away.
+        if c > 0.0 && b > 0.0 { return None; }
+
+        let discriminant = b * b - c;
+        if discriminant < 0.0 { return None; }
+        Some((-b - discriminant.sqrt()).max(0.0))
+    }
+
+    /// Möller–Trumbore. Both sides of the triangle are hit.
+    pub fn intersect_triangle(&self, a: XYZ<f32>, b: XYZ<f32>, c: XYZ<f32>) -> Option<f32> {
+        const EPSILON: f32 = 1e-7;
+
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(ac);
+        let det = ab.dot(p);
+        // Parallel to the triangle.
+        if det.abs() < EPSILON { return None; }
+
+        let inverse = 1.0 / det;
+        let offset = self.origin - a;
+        let u = offset.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) { return None; }
+
+        let q = offset.cross(ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 { return None; }
+
+        let t = ac.dot(q) * inverse;
+        if t >= 0.0 { Some(t) } else { None }
+    }
+
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let denominator = plane.normal.dot(self.direction);
+        if denominator == 0.0 { return None; }
+
+        let t = -plane.signed_distance(self.origin) / denominator;
+        if t >= 0.0 { Some(t) } else { None }
+    }
+}
+
+impl Plane {
+    /// The normal is normalized.
+    pub fn new(normal: XYZ<f32>, distance: f32) -> Self {
+        let length = normal.length();
+        Self { normal: normal / length, distance: distance / length }
+    }
+
+    /// The normal is normalized.
+    pub fn from_point_normal(point: XYZ<f32>, normal: XYZ<f32>) -> Self {
+        let normal = normal.normalize();
+        Self { normal, distance: -normal.dot(point) }
+    }
+
+    /// Counterclockwise points see the positive side.
+    pub fn from_points(a: XYZ<f32>, b: XYZ<f32>, c: XYZ<f32>) -> Self {
+        Self::from_point_normal(a, (b - a).cross(c - a))
+    }
+
+    /// Positive on the side of the normal.
+    #[inline]
+    pub fn signed_distance(&self, point: XYZ<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// (normal, distance). The normal is normalized.
+impl From<XYZW<f32>> for Plane {
+    #[inline]
+    fn from(v: XYZW<f32>) -> Self { Self::new(v.truncate(), v.w) }
+}
+
+impl Frustum {
+    /// Planes of a projection * view matrix with Vulkan depth in [0, 1].
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let [r0, r1, r2, r3] = m.transpose().columns;
+        Self {
+            planes: [
+                Plane::from(r3 + r0),
+                Plane::from(r3 - r0),
+                Plane::from(r3 + r1),
+                Plane::from(r3 - r1),
+                Plane::from(r2),
+                Plane::from(r3 - r2),
+            ],
+        }
+    }
+
+    #[inline]
+    pub fn planes(&self) -> &[Plane; 6] { &self.planes }
+
+    pub fn contains_point(&self, point: XYZ<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    /// Conservative: a sphere near a corner outside of the volume may count as intersecting.
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    /// Conservative like `intersects_sphere`. Tests the corner of the box farthest along
+    /// the normal of each plane.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = XYZ::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn unit_box() -> Aabb { Aabb::new(XYZ::splat(-1.0), XYZ::splat(1.0)) }
+
+    #[test]
+    fn aabb_slab_axis_parallel() {
+        let aabb = unit_box();
+        let ray = Ray::new(XYZ::new(-5.0, 0.5, 0.5), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        let ray = Ray::new(XYZ::new(0.0, 0.0, 5.0), XYZ::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        // Parallel to a slab and outside of it.
+        let ray = Ray::new(XYZ::new(-5.0, 2.0, 0.0), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+        // Along faces.
+        let ray = Ray::new(XYZ::new(-5.0, 1.0, 0.0), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        let ray = Ray::new(XYZ::new(-5.0, -1.0, 1.0), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        // Pointing away.
+        let ray = Ray::new(XYZ::new(-5.0, 0.0, 0.0), XYZ::new(-1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn aabb_slab() {
+        let aabb = unit_box();
+        let ray = Ray::new(XYZ::new(-3.0, -3.0, -3.0), XYZ::new(1.0, 1.0, 1.0));
+        let t = ray.intersect_aabb(&aabb).unwrap();
+        assert!((ray.at(t) - XYZ::splat(-1.0)).length() < EPSILON);
+        // Misses a corner.
+        let ray = Ray::new(XYZ::new(-3.0, 0.0, 0.0), XYZ::new(1.0, 1.5, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+        // Inside.
+        let ray = Ray::new(XYZ::ZERO, XYZ::new(0.3, -0.2, 1.0));
+        assert_eq!(ray.intersect_aabb(&aabb), Some(0.0));
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = Sphere::new(XYZ::new(0.0, 0.0, -10.0), 2.0);
+        let ray = Ray::new(XYZ::ZERO, XYZ::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_sphere(&sphere), Some(8.0));
+        let ray = Ray::new(XYZ::ZERO, XYZ::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersect_sphere(&sphere), None);
+        let ray = Ray::new(XYZ::new(0.0, 3.0, 0.0), XYZ::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_sphere(&sphere), None);
+        let ray = Ray::new(XYZ::new(0.0, 0.0, -9.0), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_sphere(&sphere), Some(0.0));
+
+        assert!(sphere.intersects(&Sphere::new(XYZ::new(0.0, 3.0, -10.0), 1.0)));
+        assert!(!sphere.intersects(&Sphere::new(XYZ::new(0.0, 3.1, -10.0), 1.0)));
+        assert!(sphere.intersects_aabb(&Aabb::new(XYZ::new(1.0, 1.0, -11.0), XYZ::new(2.0, 2.0, -9.0))));
+        assert!(!sphere.intersects_aabb(&Aabb::new(XYZ::new(1.5, 1.5, -11.0), XYZ::new(2.0, 2.0, -9.0))));
+    }
+
+    #[test]
+    fn triangle() {
+        let (a, b, c) = (XYZ::new(0.0, 0.0, 0.0), XYZ::new(1.0, 0.0, 0.0), XYZ::new(0.0, 1.0, 0.0));
+        let ray = Ray::new(XYZ::new(0.25, 0.25, 2.0), XYZ::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_triangle(a, b, c), Some(2.0));
+        // Both sides.
+        let ray = Ray::new(XYZ::new(0.25, 0.25, -2.0), XYZ::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersect_triangle(a, b, c), Some(2.0));
+        // Outside of the hypotenuse.
+        let ray = Ray::new(XYZ::new(0.75, 0.75, 2.0), XYZ::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_triangle(a, b, c), None);
+        // Parallel.
+        let ray = Ray::new(XYZ::new(0.25, 0.25, 1.0), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_triangle(a, b, c), None);
+        // Behind.
+        let ray = Ray::new(XYZ::new(0.25, 0.25, 2.0), XYZ::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersect_triangle(a, b, c), None);
+    }
+
+    #[test]
+    fn plane() {
+        let plane = Plane::from_points(XYZ::new(0.0, 1.0, 0.0), XYZ::new(0.0, 1.0, 1.0), XYZ::new(1.0, 1.0, 0.0));
+        assert!((plane.normal - XYZ::new(0.0, 1.0, 0.0)).length() < EPSILON);
+        assert!((plane.signed_distance(XYZ::new(5.0, 3.0, 2.0)) - 2.0).abs() < EPSILON);
+        assert_eq!(Plane::new(XYZ::new(0.0, 2.0, 0.0), -2.0), Plane { normal: XYZ::new(0.0, 1.0, 0.0), distance: -1.0 });
+
+        let ray = Ray::new(XYZ::new(0.0, 5.0, 0.0), XYZ::new(0.0, -1.0, 0.0));
+        assert!((ray.intersect_plane(&plane).unwrap() - 4.0).abs() < EPSILON);
+        let ray = Ray::new(XYZ::new(0.0, 5.0, 0.0), XYZ::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_plane(&plane), None);
+    }
+
+    #[test]
+    fn aabb_transform() {
+        let aabb = Aabb::new(XYZ::new(0.0, 0.0, 0.0), XYZ::new(2.0, 1.0, 1.0));
+        let m = Mat4::translation(XYZ::new(1.0, 0.0, 0.0)) * Mat4::rotation_z(std::f32::consts::FRAC_PI_2);
+        let transformed = aabb.transform(&m);
+        assert!((transformed.min - XYZ::new(0.0, 0.0, 0.0)).length() < EPSILON);
+        assert!((transformed.max - XYZ::new(1.0, 2.0, 1.0)).length() < EPSILON);
+
+        assert!(Aabb::EMPTY.is_empty());
+        let points = Aabb::from_points(vec![XYZ::new(1.0, -1.0, 0.0), XYZ::new(-2.0, 3.0, 1.0)]);
+        assert_eq!(points, Aabb::new(XYZ::new(-2.0, -1.0, 0.0), XYZ::new(1.0, 3.0, 1.0)));
+        assert!(points.intersects(&unit_box()));
+        assert!(!points.intersects(&Aabb::new(XYZ::splat(4.0), XYZ::splat(5.0))));
+    }
+
+    #[test]
+    fn frustum_culling() {
+        let view = Mat4::look_at(XYZ::ZERO, XYZ::new(0.0, 0.0, -1.0), XYZ::new(0.0, 1.0, 0.0));
+        let projection = Mat4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        let frustum = Frustum::from_matrix(&(projection * view));
+
+        assert!(frustum.contains_point(XYZ::new(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(XYZ::new(0.0, 0.0, 10.0)));
+        // Before the near plane and beyond the far one.
+        assert!(!frustum.contains_point(XYZ::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(XYZ::new(0.0, 0.0, -101.0)));
+        // Outside of the right plane, which is x = -z at 90 degrees.
+        assert!(!frustum.contains_point(XYZ::new(11.0, 0.0, -10.0)));
+
+        assert!(frustum.intersects_sphere(&Sphere::new(XYZ::new(11.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(XYZ::new(20.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(XYZ::new(0.0, 0.0, 5.0), 1.0)));
+
+        let behind = Aabb::new(XYZ::new(-1.0, -1.0, 1.0), XYZ::new(1.0, 1.0, 2.0));
+        assert!(!frustum.intersects_aabb(&behind));
+        let straddling = Aabb::new(XYZ::new(5.0, -1.0, -11.0), XYZ::new(15.0, 1.0, -9.0));
+        assert!(frustum.intersects_aabb(&straddling));
+    }
+
+    #[test]
+    fn ray_from_ndc() {
+        let view = Mat4::look_at(XYZ::new(0.0, 0.0, 5.0), XYZ::ZERO, XYZ::new(0.0, 1.0, 0.0));
+        let projection = Mat4::perspective(1.0, 1.5, 0.1, 100.0);
+        let inverse = (projection * view).inverse().unwrap();
+        let ray = Ray::from_ndc(XY::new(0.0, 0.0), &inverse);
+        assert!((ray.direction - XYZ::new(0.0, 0.0, -1.0)).length() < EPSILON);
+        assert!((ray.origin - XYZ::new(0.0, 0.0, 4.9)).length() < 1e-4);
+        // NDC y is down.
+        let ray = Ray::from_ndc(XY::new(0.0, -0.5), &inverse);
+        assert!(ray.direction.y > 0.0);
+    }
+}
@@ -0,0 +1,547 @@
+//! Column major `Mat2`, `Mat3` and `Mat4` of `f32`.
+//!
+//! `Mat4` has the layout of `mat4` in both std140 and std430, and `Mat2` the one of `mat2`
+//! in std430. Columns of `mat2` and `mat3` are padded to `vec4` in std140 (and `mat3` also
+//! in std430), so `to_std140` returns the padded columns.
+//!
+//! Projections follow Vulkan: clip y is down and depth is in [0, 1]. Views are right-handed
+//! and look toward -z.
+
+use std::ops::{ Mul, Index, IndexMut };
+
+use super::{ XY, XYZ, XYZW };
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Mat2 {
+    pub columns: [XY<f32>; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Mat3 {
+    pub columns: [XYZ<f32>; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Mat4 {
+    pub columns: [XYZW<f32>; 4],
+}
+
+impl Mat2 {
+    pub const IDENTITY: Self = Self::from_columns(XY { x: 1.0, y: 0.0 }, XY { x: 0.0, y: 1.0 });
+
+    #[inline]
+    pub const fn from_columns(x: XY<f32>, y: XY<f32>) -> Self { Self { columns: [x, y] } }
+
+    /// Counterclockwise rotation by the angle in radians.
+    pub fn rotation(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::from_columns(XY::new(cos, sin), XY::new(-sin, cos))
+    }
+
+    pub fn scale(scale: XY<f32>) -> Self {
+        Self::from_columns(XY::new(scale.x, 0.0), XY::new(0.0, scale.y))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let [x, y] = self.columns;
+        Self::from_columns(XY::new(x.x, y.x), XY::new(x.y, y.y))
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let [x, y] = self.columns;
+        x.x * y.y - y.x * x.y
+    }
+
+    /// `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 { return None; }
+
+        let [x, y] = self.columns;
+        let inv = 1.0 / det;
+        Some(Self::from_columns(XY::new(y.y * inv, -x.y * inv), XY::new(-y.x * inv, x.x * inv)))
+    }
+
+    /// Columns padded to `vec4` for std140.
+    pub fn to_std140(&self) -> [XYZW<f32>; 2] {
+        let [x, y] = self.columns;
+        [x.extend(0.0).extend(0.0), y.extend(0.0).extend(0.0)]
+    }
+}
+
+impl Mat3 {
+    pub const IDENTITY: Self = Self::from_columns(
+        XYZ { x: 1.0, y: 0.0, z: 0.0 },
+        XYZ { x: 0.0, y: 1.0, z: 0.0 },
+        XYZ { x: 0.0, y: 0.0, z: 1.0 },
+    );
+
+    #[inline]
+    pub const fn from_columns(x: XYZ<f32>, y: XYZ<f32>, z: XYZ<f32>) -> Self {
+        Self { columns: [x, y, z] }
+    }
+
+    /// Upper left 3x3 part.
+    pub fn from_mat4(m: &Mat4) -> Self {
+        let [x, y, z, _] = m.columns;
+        Self::from_columns(x.truncate(), y.truncate(), z.truncate())
+    }
+
+    /// Counterclockwise rotation around the unit axis by the angle in radians.
+    pub fn from_axis_angle(axis: XYZ<f32>, angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let XYZ { x, y, z } = axis;
+        let t = 1.0 - cos;
+        Self::from_columns(
+            XYZ::new(t * x * x + cos, t * x * y + sin * z, t * x * z - sin * y),
+            XYZ::new(t * x * y - sin * z, t * y * y + cos, t * y * z + sin * x),
+            XYZ::new(t * x * z + sin * y, t * y * z - sin * x, t * z * z + cos),
+        )
+    }
+
+    pub fn scale(scale: XYZ<f32>) -> Self {
+        Self::from_columns(
+            XYZ::new(scale.x, 0.0, 0.0),
+            XYZ::new(0.0, scale.y, 0.0),
+            XYZ::new(0.0, 0.0, scale.z),
+        )
+    }
+
+    pub fn transpose(&self) -> Self {
+        let [x, y, z] = self.columns;
+        Self::from_columns(
+            XYZ::new(x.x, y.x, z.x),
+            XYZ::new(x.y, y.y, z.y),
+            XYZ::new(x.z, y.z, z.z),
+        )
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let [x, y, z] = self.columns;
+        x.dot(y.cross(z))
+    }
+
+    /// `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 { return None; }
+
+        // Rows of the inverse are cross products of columns.
+        let [x, y, z] = self.columns;
+        let rows = Self::from_columns(y.cross(z), z.cross(x), x.cross(y));
+        Some(rows.transpose() * (1.0 / det))
+    }
+
+    /// Columns padded to `vec4` for std140 and std430.
+    pub fn to_std140(&self) -> [XYZW<f32>; 3] {
+        let [x, y, z] = self.columns;
+        [x.extend(0.0), y.extend(0.0), z.extend(0.0)]
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self::from_columns(
+        XYZW { x: 1.0, y: 0.0, z: 0.0, w: 0.0 },
+        XYZW { x: 0.0, y: 1.0, z: 0.0, w: 0.0 },
+        XYZW { x: 0.0, y: 0.0, z: 1.0, w: 0.0 },
+        XYZW { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+    );
+
+    #[inline]
+    pub const fn from_columns(x: XYZW<f32>, y: XYZW<f32>, z: XYZW<f32>, w: XYZW<f32>) -> Self {
+        Self { columns: [x, y, z, w] }
+    }
+
+    /// Upper left 3x3 part is `m`, without translation.
+    pub fn from_mat3(m: &Mat3) -> Self {
+        let [x, y, z] = m.columns;
+        Self::from_columns(x.extend(0.0), y.extend(0.0), z.extend(0.0), XYZW::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    pub fn translation(translation: XYZ<f32>) -> Self {
+        let mut m = Self::IDENTITY;
+        m.columns[3] = translation.extend(1.0);
+        m
+    }
+
+    pub fn scale(scale: XYZ<f32>) -> Self { Self::from_mat3(&Mat3::scale(scale)) }
+
+    /// Counterclockwise rotation around the unit axis by the angle in radians.
+    pub fn from_axis_angle(axis: XYZ<f32>, angle: f32) -> Self {
+        Self::from_mat3(&Mat3::from_axis_angle(axis, angle))
+    }
+
+    #[inline]
+    pub fn rotation_x(angle: f32) -> Self { Self::from_axis_angle(XYZ::new(1.0, 0.0, 0.0), angle) }
+    #[inline]
+    pub fn rotation_y(angle: f32) -> Self { Self::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), angle) }
+    #[inline]
+    pub fn rotation_z(angle: f32) -> Self { Self::from_axis_angle(XYZ::new(0.0, 0.0, 1.0), angle) }
+
+    /// View from `eye` toward `target`.
+    pub fn look_at(eye: XYZ<f32>, target: XYZ<f32>, up: XYZ<f32>) -> Self {
+        Self::look_to(eye, target - eye, up)
+    }
+
+    /// View from `eye` in the direction.
+    pub fn look_to(eye: XYZ<f32>, direction: XYZ<f32>, up: XYZ<f32>) -> Self {
+        let f = direction.normalize();
+        let s = f.cross(up).normalize();
+        let u = s.cross(f);
+        Self::from_columns(
+            XYZW::new(s.x, u.x, -f.x, 0.0),
+            XYZW::new(s.y, u.y, -f.y, 0.0),
+            XYZW::new(s.z, u.z, -f.z, 0.0),
+            XYZW::new(-s.dot(eye), -u.dot(eye), f.dot(eye), 1.0),
+        )
+    }
+
+    /// Perspective projection which maps `near` to depth 0 and `far` to 1.
+    /// `fov_y` is in radians and `aspect` is width / height.
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / (fov_y * 0.5).tan();
+        let depth = near - far;
+        Self::from_columns(
+            XYZW::new(f / aspect, 0.0, 0.0, 0.0),
+            XYZW::new(0.0, -f, 0.0, 0.0),
+            XYZW::new(0.0, 0.0, far / depth, -1.0),
+            XYZW::new(0.0, 0.0, near * far / depth, 0.0),
+        )
+    }
+
+    /// Perspective projection with reversed depth, which maps `near` to depth 1 and `far`
+    /// to 0 for better precision. Use with `GREATER` depth compare and clear depth 0.
+    pub fn perspective_reversed(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / (fov_y * 0.5).tan();
+        let depth = far - near;
+        Self::from_columns(
+            XYZW::new(f / aspect, 0.0, 0.0, 0.0),
+            XYZW::new(0.0, -f, 0.0, 0.0),
+            XYZW::new(0.0, 0.0, near / depth, -1.0),
+            XYZW::new(0.0, 0.0, near * far / depth, 0.0),
+        )
+    }
+
+    /// Orthographic projection of the box which maps `near` to depth 0 and `far` to 1.
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        let width = right - left;
+        let height = top - bottom;
+        let depth = far - near;
+        Self::from_columns(
+            XYZW::new(2.0 / width, 0.0, 0.0, 0.0),
+            XYZW::new(0.0, -2.0 / height, 0.0, 0.0),
+            XYZW::new(0.0, 0.0, -1.0 / depth, 0.0),
+            XYZW::new(-(right + left) / width, (top + bottom) / height, -near / depth, 1.0),
+        )
+    }
+
+    pub fn transpose(&self) -> Self {
+        let [x, y, z, w] = self.columns;
+        Self::from_columns(
+            XYZW::new(x.x, y.x, z.x, w.x),
+            XYZW::new(x.y, y.y, z.y, w.y),
+            XYZW::new(x.z, y.z, z.z, w.z),
+            XYZW::new(x.w, y.w, z.w, w.w),
+        )
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.cofactor_pairs();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    /// `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let (s, c) = self.cofactor_pairs();
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if det == 0.0 { return None; }
+
+        let [a0, a1, a2, a3] = self.rows();
+        let inv = 1.0 / det;
+        // Rows of the adjugate, which are columns of the transposed cofactors.
+        let rows = [
+            XYZW::new(
+                a1.y * c[5] - a1.z * c[4] + a1.w * c[3],
+                -a0.y * c[5] + a0.z * c[4] - a0.w * c[3],
+                a3.y * s[5] - a3.z * s[4] + a3.w * s[3],
+                -a2.y * s[5] + a2.z * s[4] - a2.w * s[3],
+            ),
+            XYZW::new(
+                -a1.x * c[5] + a1.z * c[2] - a1.w * c[1],
+                a0.x * c[5] - a0.z * c[2] + a0.w * c[1],
+                -a3.x * s[5] + a3.z * s[2] - a3.w * s[1],
+                a2.x * s[5] - a2.z * s[2] + a2.w * s[1],
+            ),
+            XYZW::new(
+                a1.x * c[4] - a1.y * c[2] + a1.w * c[0],
+                -a0.x * c[4] + a0.y * c[2] - a0.w * c[0],
+                a3.x * s[4] - a3.y * s[2] + a3.w * s[0],
+                -a2.x * s[4] + a2.y * s[2] - a2.w * s[0],
+            ),
+            XYZW::new(
+                -a1.x * c[3] + a1.y * c[1] - a1.z * c[0],
+                a0.x * c[3] - a0.y * c[1] + a0.z * c[0],
+                -a3.x * s[3] + a3.y * s[1] - a3.z * s[0],
+                a2.x * s[3] - a2.y * s[1] + a2.z * s[0],
+            ),
+        ];
+
+        Some(Self::from_columns(rows[0], rows[1], rows[2], rows[3]).transpose() * inv)
+    }
+
+    /// Transform a position, with w = 1.
+    #[inline]
+    pub fn transform_point(&self, point: XYZ<f32>) -> XYZ<f32> {
+        (*self * point.extend(1.0)).truncate()
+    }
+
+    /// Transform a direction, with w = 0, so translation is ignored.
+    #[inline]
+    pub fn transform_vector(&self, vector: XYZ<f32>) -> XYZ<f32> {
+        (*self * vector.extend(0.0)).truncate()
+    }
+
+    /// Matrix for normals: inverse transpose of the upper left 3x3 part.
+    pub fn normal_matrix(&self) -> Option<Mat3> {
+        Mat3::from_mat4(self).inverse().map(|m| m.transpose())
+    }
+
+    fn rows(&self) -> [XYZW<f32>; 4] {
+        self.transpose().columns
+    }
+
+    /// 2x2 determinants of the upper two rows (`s`) and the lower two rows (`c`).
+    fn cofactor_pairs(&self) -> ([f32; 6], [f32; 6]) {
+        let [a0, a1, a2, a3] = self.rows();
+        let s = [
+            a0.x * a1.y - a1.x * a0.y,
+            a0.x * a1.z - a1.x * a0.z,
+            a0.x * a1.w - a1.x * a0.w,
+            a0.y * a1.z - a1.y * a0.z,
+            a0.y * a1.w - a1.y * a0.w,
+            a0.z * a1.w - a1.z * a0.w,
+        ];
+        let c = [
+            a2.x * a3.y - a3.x * a2.y,
+            a2.x * a3.z - a3.x * a2.z,
+            a2.x * a3.w - a3.x * a2.w,
+            a2.y * a3.z - a3.y * a2.z,
+            a2.y * a3.w - a3.y * a2.w,
+            a2.z * a3.w - a3.z * a2.w,
+        ];
+        (s, c)
+    }
+}
+
+/// Implements products, scaling and indexing by columns.
+macro_rules! impl_matrix {
+    ($matrix:ident, $vector:ident, $n:expr, { $($c:ident),* }) => {
+        impl Mul<$vector<f32>> for $matrix {
+            type Output = $vector<f32>;
+            #[inline]
+            fn mul(self, rhs: $vector<f32>) -> $vector<f32> {
+                let [$($c),*] = self.columns;
+                let mut sum = $vector::<f32>::ZERO;
+                $(sum += $c * rhs.$c;)*
+                sum
+            }
+        }
+
+        impl Mul for $matrix {
+            type Output = Self;
+            #[inline]
+            fn mul(self, rhs: Self) -> Self {
+                let mut columns = rhs.columns;
+                columns.iter_mut().for_each(|column| *column = self * *column);
+                Self { columns }
+            }
+        }
+
+        impl Mul<f32> for $matrix {
+            type Output = Self;
+            #[inline]
+            fn mul(self, rhs: f32) -> Self {
+                let mut columns = self.columns;
+                columns.iter_mut().for_each(|column| *column *= rhs);
+                Self { columns }
+            }
+        }
+
+        impl Index<usize> for $matrix {
+            type Output = $vector<f32>;
+            #[inline]
+            fn index(&self, column: usize) -> &$vector<f32> { &self.columns[column] }
+        }
+
+        impl IndexMut<usize> for $matrix {
+            #[inline]
+            fn index_mut(&mut self, column: usize) -> &mut $vector<f32> { &mut self.columns[column] }
+        }
+
+        impl From<[[f32; $n]; $n]> for $matrix {
+            #[inline]
+            fn from(columns: [[f32; $n]; $n]) -> Self {
+                let [$($c),*] = columns;
+                Self { columns: [$($vector::from($c)),*] }
+            }
+        }
+
+        impl From<$matrix> for [[f32; $n]; $n] {
+            #[inline]
+            fn from(m: $matrix) -> Self {
+                let [$($c),*] = m.columns;
+                [$($c.into()),*]
+            }
+        }
+    };
+}
+
+impl_matrix!(Mat2, XY, 2, { x, y });
+impl_matrix!(Mat3, XYZ, 3, { x, y, z });
+impl_matrix!(Mat4, XYZW, 4, { x, y, z, w });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_near(a: Mat4, b: Mat4) {
+        let close = a.columns
+            .iter()
+            .zip(b.columns.iter())
+            .all(|(a, b)| (*a - *b).length() < EPSILON);
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    /// Depth of a point in view coordinates after the perspective division.
+    fn depth(projection: &Mat4, z: f32) -> f32 {
+        let clip = *projection * XYZW::new(0.0, 0.0, z, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn inverse() {
+        let m = Mat4::translation(XYZ::new(1.0, -2.0, 3.0))
+            * Mat4::rotation_x(0.7)
+            * Mat4::rotation_y(-1.2)
+            * Mat4::scale(XYZ::new(2.0, 0.5, 3.0));
+        let inverse = m.inverse().unwrap();
+        assert_near(m * inverse, Mat4::IDENTITY);
+        assert_near(inverse * m, Mat4::IDENTITY);
+
+        // Not affine.
+        let projection = Mat4::perspective(1.0, 1.5, 0.1, 100.0);
+        let inverse = projection.inverse().unwrap();
+        assert_near(projection * inverse, Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn inverse_singular() {
+        assert_eq!(Mat4::scale(XYZ::new(1.0, 0.0, 1.0)).inverse(), None);
+        assert_eq!(Mat3::scale(XYZ::new(0.0, 1.0, 1.0)).inverse(), None);
+        assert_eq!(Mat2::scale(XY::new(1.0, 0.0)).inverse(), None);
+    }
+
+    #[test]
+    fn inverse_2_and_3() {
+        let m = Mat2::rotation(0.3) * Mat2::scale(XY::new(2.0, 4.0));
+        let product = m * m.inverse().unwrap();
+        assert!((product[0] - XY::new(1.0, 0.0)).length() < EPSILON);
+        assert!((product[1] - XY::new(0.0, 1.0)).length() < EPSILON);
+
+        let axis = XYZ::new(1.0_f32, 1.0, 0.0).normalize();
+        let m = Mat3::from_axis_angle(axis, 0.9) * Mat3::scale(XYZ::new(1.0, 2.0, 3.0));
+        let product = m * m.inverse().unwrap();
+        assert!((product[0] - XYZ::new(1.0, 0.0, 0.0)).length() < EPSILON);
+        assert!((product[1] - XYZ::new(0.0, 1.0, 0.0)).length() < EPSILON);
+        assert!((product[2] - XYZ::new(0.0, 0.0, 1.0)).length() < EPSILON);
+    }
+
+    #[test]
+    fn determinant() {
+        assert_eq!(Mat4::IDENTITY.determinant(), 1.0);
+        assert_eq!(Mat4::scale(XYZ::new(2.0, 3.0, 4.0)).determinant(), 24.0);
+        assert!((Mat4::rotation_z(1.1).determinant() - 1.0).abs() < EPSILON);
+        // Translation does not change volumes.
+        let m = Mat4::translation(XYZ::new(5.0, 6.0, 7.0)) * Mat4::scale(XYZ::new(-1.0, 2.0, 2.0));
+        assert!((m.determinant() + 4.0).abs() < EPSILON);
+        assert!((m.transpose().determinant() - m.determinant()).abs() < EPSILON);
+        assert_eq!(Mat3::scale(XYZ::new(2.0, 3.0, 4.0)).determinant(), 24.0);
+        assert_eq!(Mat2::scale(XY::new(2.0, 3.0)).determinant(), 6.0);
+    }
+
+    #[test]
+    fn perspective_depth() {
+        let (near, far) = (0.1, 100.0);
+        let projection = Mat4::perspective(1.0, 16.0 / 9.0, near, far);
+        assert!(depth(&projection, -near).abs() < EPSILON);
+        assert!((depth(&projection, -far) - 1.0).abs() < EPSILON);
+        assert!(depth(&projection, -1.0) > 0.0 && depth(&projection, -1.0) < 1.0);
+
+        let reversed = Mat4::perspective_reversed(1.0, 16.0 / 9.0, near, far);
+        assert!((depth(&reversed, -near) - 1.0).abs() < EPSILON);
+        assert!(depth(&reversed, -far).abs() < EPSILON);
+    }
+
+    #[test]
+    fn perspective_edges() {
+        // The top and the right of the view at the distance 1 from a fov of 90 degrees.
+        let projection = Mat4::perspective(std::f32::consts::FRAC_PI_2, 2.0, 0.1, 10.0);
+        let clip = projection * XYZW::new(2.0, 1.0, -1.0, 1.0);
+        assert!((clip.x / clip.w - 1.0).abs() < EPSILON);
+        // Clip y is down.
+        assert!((clip.y / clip.w + 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn orthographic() {
+        let projection = Mat4::orthographic(-2.0, 4.0, -1.0, 3.0, 1.0, 11.0);
+        let near = projection.transform_point(XYZ::new(-2.0, 3.0, -1.0));
+        assert!((near - XYZ::new(-1.0, -1.0, 0.0)).length() < EPSILON);
+        let far = projection.transform_point(XYZ::new(4.0, -1.0, -11.0));
+        assert!((far - XYZ::new(1.0, 1.0, 1.0)).length() < EPSILON);
+    }
+
+    #[test]
+    fn look_at() {
+        let eye = XYZ::new(3.0, 2.0, 1.0);
+        let target = XYZ::new(-1.0, 0.5, 2.0);
+        let view = Mat4::look_at(eye, target, XYZ::new(0.0, 1.0, 0.0));
+        assert!(view.transform_point(eye).length() < EPSILON);
+        // The view looks toward -z.
+        let target = view.transform_point(target);
+        assert!(target.x.abs() < EPSILON && target.y.abs() < EPSILON && target.z < 0.0);
+        assert!((view.determinant() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn transform_vector_ignores_translation() {
+        let m = Mat4::translation(XYZ::new(1.0, 2.0, 3.0)) * Mat4::scale(XYZ::new(2.0, 2.0, 2.0));
+        assert_eq!(m.transform_vector(XYZ::new(1.0, 0.0, 0.0)), XYZ::new(2.0, 0.0, 0.0));
+        assert_eq!(m.transform_point(XYZ::new(1.0, 0.0, 0.0)), XYZ::new(3.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn normal_matrix() {
+        // Normals stay perpendicular to tangents under non-uniform scale.
+        let m = Mat4::rotation_y(0.4) * Mat4::scale(XYZ::new(1.0, 4.0, 1.0));
+        let normal = XYZ::new(1.0, 1.0, 0.0);
+        let tangent = XYZ::new(1.0, -1.0, 0.0);
+        let normal = m.normal_matrix().unwrap() * normal;
+        assert!(normal.dot(m.transform_vector(tangent)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn products_compose() {
+        let p = XYZ::new(1.0, 2.0, 3.0);
+        let a = Mat4::rotation_z(0.5);
+        let b = Mat4::translation(XYZ::new(-1.0, 0.0, 4.0));
+        let composed = (a * b).transform_point(p);
+        assert!((composed - a.transform_point(b.transform_point(p))).length() < EPSILON);
+        assert_eq!(Mat4::from(<[[f32; 4]; 4]>::from(a)), a);
+    }
+}
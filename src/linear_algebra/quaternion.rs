@@ -0,0 +1,347 @@
+//! Unit quaternions for rotations.
+//!
+//! `Quat` has the layout of `vec4` (x, y, z, w) as read by `rotate` in `dim3/glsl.vert`.
+//! `a * b` rotates by `b` first and then by `a`.
+
+use std::ops::{ Add, Mul, MulAssign, Neg };
+
+use super::{ XYZ, XYZW, Mat3, Mat4 };
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub const IDENTITY: Self = Self { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+
+    #[inline]
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self { Self { x, y, z, w } }
+
+    /// Counterclockwise rotation around the unit axis by the angle in radians.
+    pub fn from_axis_angle(axis: XYZ<f32>, angle: f32) -> Self {
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// Rotation by `yaw` around y, then `pitch` around the rotated x and `roll` around the
+    /// rotated z, in radians. With y up and -z forward, this is how a character or a
+    /// camera turns.
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Self::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), yaw)
+            * Self::from_axis_angle(XYZ::new(1.0, 0.0, 0.0), pitch)
+            * Self::from_axis_angle(XYZ::new(0.0, 0.0, 1.0), roll)
+    }
+
+    /// Rotation which turns the unit vector `from` onto the unit vector `to`.
+    pub fn from_rotation_arc(from: XYZ<f32>, to: XYZ<f32>) -> Self {
+        let dot = from.dot(to);
+        if dot < -0.999_999 {
+            // Opposite directions: half a turn around any perpendicular axis.
+            let axis = XYZ::new(1.0, 0.0, 0.0).cross(from);
+            let axis = if axis.length_squared() < 1e-6 { XYZ::new(0.0, 1.0, 0.0).cross(from) } else { axis };
+            return Self::from_axis_angle(axis.normalize(), std::f32::consts::PI);
+        }
+
+        let axis = from.cross(to);
+        Self::new(axis.x, axis.y, axis.z, 1.0 + dot).normalize()
+    }
+
+    /// Rotation of an orthonormal matrix without reflection.
+    pub fn from_mat3(m: &Mat3) -> Self {
+        // `m[column].row`. Start from the largest component for precision.
+        let [c0, c1, c2] = m.columns;
+        let trace = c0.x + c1.y + c2.z;
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new((c1.z - c2.y) / s, (c2.x - c0.z) / s, (c0.y - c1.x) / s, 0.25 * s)
+        } else if c0.x > c1.y && c0.x > c2.z {
+            let s = (1.0 + c0.x - c1.y - c2.z).sqrt() * 2.0;
+            Self::new(0.25 * s, (c1.x + c0.y) / s, (c2.x + c0.z) / s, (c1.z - c2.y) / s)
+        } else if c1.y > c2.z {
+            let s = (1.0 + c1.y - c0.x - c2.z).sqrt() * 2.0;
+            Self::new((c1.x + c0.y) / s, 0.25 * s, (c2.y + c1.z) / s, (c2.x - c0.z) / s)
+        } else {
+            let s = (1.0 + c2.z - c0.x - c1.y).sqrt() * 2.0;
+            Self::new((c2.x + c0.z) / s, (c2.y + c1.z) / s, 0.25 * s, (c0.y - c1.x) / s)
+        };
+        q.normalize()
+    }
+
+    /// (yaw, pitch, roll) of `from_euler`. Pitch is in [-pi/2, pi/2], and roll is zero at
+    /// the poles where yaw and roll turn around the same axis.
+    pub fn to_euler(self) -> (f32, f32, f32) {
+        let m = self.to_mat3();
+        // `m[column].row`
+        let sin_pitch = -m[2].y;
+        if sin_pitch.abs() > 0.999_999 {
+            let yaw = (-m[0].z).atan2(m[0].x);
+            return (yaw, sin_pitch.signum() * std::f32::consts::FRAC_PI_2, 0.0);
+        }
+
+        let yaw = m[2].x.atan2(m[2].z);
+        let pitch = sin_pitch.asin();
+        let roll = m[0].y.atan2(m[1].y);
+        (yaw, pitch, roll)
+    }
+
+    /// (unit axis, angle in radians). The axis is x for the identity.
+    pub fn to_axis_angle(self) -> (XYZ<f32>, f32) {
+        let q = self.normalize();
+        let angle = 2.0 * q.w.clamp(-1.0, 1.0).acos();
+        let axis = XYZ::new(q.x, q.y, q.z).try_normalize().unwrap_or(XYZ::new(1.0, 0.0, 0.0));
+        (axis, angle)
+    }
+
+    #[inline]
+    pub fn xyz(self) -> XYZ<f32> { XYZ::new(self.x, self.y, self.z) }
+
+    #[inline]
+    pub fn dot(self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    #[inline]
+    pub fn length(self) -> f32 { self.dot(self).sqrt() }
+
+    /// The identity if the length is zero.
+    pub fn normalize(self) -> Self {
+        let length = self.length();
+        if length > 0.0 && length.is_finite() { self * (1.0 / length) } else { Self::IDENTITY }
+    }
+
+    /// Inverse of a unit quaternion.
+    #[inline]
+    pub fn conjugate(self) -> Self { Self::new(-self.x, -self.y, -self.z, self.w) }
+
+    /// Inverse of any non-zero quaternion.
+    pub fn inverse(self) -> Self { self.conjugate() * (1.0 / self.dot(self)) }
+
+    /// Rotate the vector. Same as `rotate` in `dim3/glsl.vert`.
+    #[inline]
+    pub fn rotate(self, v: XYZ<f32>) -> XYZ<f32> {
+        let q = self.xyz();
+        v + q.cross(q.cross(v) + v * self.w) * 2.0
+    }
+
+    /// Normalized linear interpolation. Cheaper than `slerp` and fine for close rotations.
+    pub fn nlerp(self, rhs: Self, t: f32) -> Self {
+        let rhs = if self.dot(rhs) < 0.0 { -rhs } else { rhs };
+        (self * (1.0 - t) + rhs * t).normalize()
+    }
+
+    /// Spherical linear interpolation along the shortest arc, `self` at `t = 0.0` and
+    /// `rhs` at `t = 1.0`.
+    pub fn slerp(self, rhs: Self, t: f32) -> Self {
+        let mut dot = self.dot(rhs);
+        let rhs = if dot < 0.0 { dot = -dot; -rhs } else { rhs };
+
+        // Nearly parallel: the sine below is close to zero.
+        if dot > 0.9995 {
+            return self.nlerp(rhs, t);
+        }
+
+        let theta = dot.min(1.0).acos();
+        let sin = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin;
+        let b = (t * theta).sin() / sin;
+        self * a + rhs * b
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        let Self { x, y, z, w } = self;
+        let (x2, y2, z2) = (x + x, y + y, z + z);
+        let (xx, yy, zz) = (x * x2, y * y2, z * z2);
+        let (xy, xz, yz) = (x * y2, x * z2, y * z2);
+        let (wx, wy, wz) = (w * x2, w * y2, w * z2);
+        Mat3::from_columns(
+            XYZ::new(1.0 - yy - zz, xy + wz, xz - wy),
+            XYZ::new(xy - wz, 1.0 - xx - zz, yz + wx),
+            XYZ::new(xz + wy, yz - wx, 1.0 - xx - yy),
+        )
+    }
+
+    #[inline]
+    pub fn to_mat4(self) -> Mat4 { Mat4::from_mat3(&self.to_mat3()) }
+}
+
+impl Default for Quat {
+    #[inline]
+    fn default() -> Self { Self::IDENTITY }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        let (a, b) = (self.xyz(), rhs.xyz());
+        let v = b * self.w + a * rhs.w + a.cross(b);
+        Self::new(v.x, v.y, v.z, self.w * rhs.w - a.dot(b))
+    }
+}
+
+impl MulAssign for Quat {
+    #[inline]
+    fn mul_assign(&mut self, rhs: Self) { *self = *self * rhs; }
+}
+
+impl Mul<XYZ<f32>> for Quat {
+    type Output = XYZ<f32>;
+    #[inline]
+    fn mul(self, rhs: XYZ<f32>) -> XYZ<f32> { self.rotate(rhs) }
+}
+
+/// Scales all components, for interpolation.
+impl Mul<f32> for Quat {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: f32) -> Self { Self::new(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs) }
+}
+
+impl Add for Quat {
+    type Output = Self;
+    #[inline]
+    fn add(self, rhs: Self) -> Self { Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z, self.w + rhs.w) }
+}
+
+/// The same rotation with all components negated.
+impl Neg for Quat {
+    type Output = Self;
+    #[inline]
+    fn neg(self) -> Self { Self::new(-self.x, -self.y, -self.z, -self.w) }
+}
+
+impl From<Quat> for XYZW<f32> {
+    #[inline]
+    fn from(q: Quat) -> Self { XYZW::new(q.x, q.y, q.z, q.w) }
+}
+
+impl From<XYZW<f32>> for Quat {
+    #[inline]
+    fn from(v: XYZW<f32>) -> Self { Self::new(v.x, v.y, v.z, v.w) }
+}
+
+impl From<[f32; 4]> for Quat {
+    #[inline]
+    fn from([x, y, z, w]: [f32; 4]) -> Self { Self::new(x, y, z, w) }
+}
+
+impl From<Quat> for Mat4 {
+    #[inline]
+    fn from(q: Quat) -> Self { q.to_mat4() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::{ FRAC_PI_2, PI };
+
+    const EPSILON: f32 = 1e-5;
+
+    /// The same rotation, whose sign of components may differ.
+    fn same_rotation(a: Quat, b: Quat) -> bool { a.dot(b).abs() > 1.0 - EPSILON }
+
+    fn near(a: XYZ<f32>, b: XYZ<f32>) -> bool { (a - b).length() < EPSILON }
+
+    #[test]
+    fn rotate_axes() {
+        let q = Quat::from_axis_angle(XYZ::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        // Counterclockwise.
+        assert!(near(q * XYZ::new(1.0, 0.0, 0.0), XYZ::new(0.0, 1.0, 0.0)));
+        assert!(near(q.to_mat3() * XYZ::new(1.0, 0.0, 0.0), XYZ::new(0.0, 1.0, 0.0)));
+        assert_eq!(Quat::IDENTITY * XYZ::new(1.0, 2.0, 3.0), XYZ::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn product_order() {
+        let a = Quat::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), FRAC_PI_2);
+        let b = Quat::from_axis_angle(XYZ::new(1.0, 0.0, 0.0), FRAC_PI_2);
+        let v = XYZ::new(0.0, 1.0, 0.0);
+        // `b` first and then `a`.
+        assert!(near((a * b) * v, a * (b * v)));
+        assert!(near((a * b).to_mat3() * v, a.to_mat3() * (b.to_mat3() * v)));
+        assert!(same_rotation(a * a.inverse(), Quat::IDENTITY));
+        assert!(same_rotation(a.conjugate(), a.inverse()));
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let angles = [(0.3, -0.4, 0.5), (-2.5, 1.2, -3.0), (3.0, 0.0, 0.0), (0.0, 0.0, -1.0)];
+        angles.iter().for_each(|&(yaw, pitch, roll)| {
+            let (y, p, r) = Quat::from_euler(yaw, pitch, roll).to_euler();
+            assert!((y - yaw).abs() < 1e-4 && (p - pitch).abs() < 1e-4 && (r - roll).abs() < 1e-4,
+                "{:?} != {:?}", (y, p, r), (yaw, pitch, roll));
+        });
+    }
+
+    #[test]
+    fn euler_at_poles() {
+        // Yaw and roll turn around the same axis, so roll is folded into yaw.
+        let q = Quat::from_euler(0.4, FRAC_PI_2, 0.3);
+        let (yaw, pitch, roll) = q.to_euler();
+        assert!((pitch - FRAC_PI_2).abs() < 1e-3);
+        assert_eq!(roll, 0.0);
+        assert!(same_rotation(Quat::from_euler(yaw, pitch, roll), q));
+    }
+
+    #[test]
+    fn from_mat3_round_trip() {
+        // Each branch of the largest component.
+        let rotations = [
+            Quat::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), 0.5),
+            Quat::from_axis_angle(XYZ::new(1.0, 0.0, 0.0), 3.0),
+            Quat::from_axis_angle(XYZ::new(0.0, 1.0, 0.0), 3.0),
+            Quat::from_axis_angle(XYZ::new(0.0, 0.0, 1.0), 3.0),
+            Quat::from_euler(2.0, -1.0, 2.5),
+        ];
+        rotations.iter().for_each(|&q| {
+            assert!(same_rotation(Quat::from_mat3(&q.to_mat3()), q), "{:?}", q);
+        });
+    }
+
+    #[test]
+    fn rotation_arc() {
+        let from = XYZ::new(1.0, 0.0, 0.0);
+        let to = XYZ::new(0.0, 0.6, 0.8);
+        assert!(near(Quat::from_rotation_arc(from, to) * from, to));
+        // Opposite directions.
+        assert!(near(Quat::from_rotation_arc(from, -from) * from, -from));
+        assert!(near(Quat::from_rotation_arc(to, -to) * to, -to));
+    }
+
+    #[test]
+    fn axis_angle() {
+        let axis = XYZ::new(0.0, 0.6, 0.8);
+        let (a, angle) = Quat::from_axis_angle(axis, 1.25).to_axis_angle();
+        assert!(near(a, axis));
+        assert!((angle - 1.25).abs() < EPSILON);
+        assert_eq!(Quat::IDENTITY.to_axis_angle(), (XYZ::new(1.0, 0.0, 0.0), 0.0));
+    }
+
+    #[test]
+    fn slerp() {
+        let axis = XYZ::new(0.0, 0.0, 1.0);
+        let a = Quat::from_axis_angle(axis, 0.0);
+        let b = Quat::from_axis_angle(axis, PI * 0.75);
+        assert!(same_rotation(a.slerp(b, 0.0), a));
+        assert!(same_rotation(a.slerp(b, 1.0), b));
+        // Constant angular velocity.
+        assert!(same_rotation(a.slerp(b, 1.0 / 3.0), Quat::from_axis_angle(axis, PI * 0.25)));
+        // The shortest arc, also when the signs differ.
+        assert!(same_rotation(a.slerp(-b, 0.5), Quat::from_axis_angle(axis, PI * 0.375)));
+        // Nearly parallel falls back to nlerp.
+        let c = Quat::from_axis_angle(axis, 1e-3);
+        assert!((a.slerp(c, 0.5).length() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn normalize_zero() {
+        assert_eq!(Quat::new(0.0, 0.0, 0.0, 0.0).normalize(), Quat::IDENTITY);
+        assert!((Quat::new(1.0, 2.0, 3.0, 4.0).normalize().length() - 1.0).abs() < EPSILON);
+    }
+}
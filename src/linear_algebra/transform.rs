@@ -0,0 +1,201 @@
+//! Translation, rotation and scale of an object.
+//!
+//! A point is scaled, then rotated and then translated, in the same order as `dim3/glsl.vert`.
+//! `a * b` applies `b` first and then `a`, e.g. `parent * child` puts the child in the
+//! space of the parent.
+
+use std::ops::Mul;
+
+use super::{ XYZ, Quat, Mat3, Mat4 };
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: XYZ<f32>,
+    pub rotation: Quat,
+    pub scale: XYZ<f32>,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: XYZ { x: 0.0, y: 0.0, z: 0.0 },
+        rotation: Quat::IDENTITY,
+        scale: XYZ { x: 1.0, y: 1.0, z: 1.0 },
+    };
+
+    pub fn new(translation: XYZ<f32>, rotation: Quat, scale: XYZ<f32>) -> Self {
+        Self { translation, rotation, scale }
+    }
+
+    #[inline]
+    pub fn from_translation(translation: XYZ<f32>) -> Self { Self { translation, ..Self::IDENTITY } }
+    #[inline]
+    pub fn from_rotation(rotation: Quat) -> Self { Self { rotation, ..Self::IDENTITY } }
+    #[inline]
+    pub fn from_scale(scale: XYZ<f32>) -> Self { Self { scale, ..Self::IDENTITY } }
+
+    /// Decompose a model matrix without shear, e.g. of glTF nodes. A negative scale comes
+    /// out as a rotation with positive scales unless the determinant is negative, in which
+    /// case x is negated.
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let (x, y, z) = (m[0].truncate(), m[1].truncate(), m[2].truncate());
+        let sign = if x.cross(y).dot(z) < 0.0 { -1.0 } else { 1.0 };
+        let scale = XYZ::new(x.length() * sign, y.length(), z.length());
+        let rotation = Quat::from_mat3(&Mat3::from_columns(x / scale.x, y / scale.y, z / scale.z));
+        Self { translation: m[3].truncate(), rotation: rotation.normalize(), scale }
+    }
+
+    /// Rotate so that -z points from the translation toward `target`.
+    pub fn look_at(mut self, target: XYZ<f32>, up: XYZ<f32>) -> Self {
+        let forward = (target - self.translation).normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+        self.rotation = Quat::from_mat3(&Mat3::from_columns(right, up, -forward));
+        self
+    }
+
+    #[inline]
+    pub fn transform_point(&self, point: XYZ<f32>) -> XYZ<f32> {
+        self.rotation.rotate(point * self.scale) + self.translation
+    }
+
+    /// Ignores the translation.
+    #[inline]
+    pub fn transform_vector(&self, vector: XYZ<f32>) -> XYZ<f32> {
+        self.rotation.rotate(vector * self.scale)
+    }
+
+    /// Exact if the scale is uniform. A non-uniform scale followed by a rotation
+    /// skews, which a `Transform` can't express; use `to_matrix().inverse()` then.
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.conjugate();
+        let scale = XYZ::splat(1.0) / self.scale;
+        let translation = -(rotation.rotate(self.translation) * scale);
+        Self { translation, rotation, scale }
+    }
+
+    /// Model matrix: translation * rotation * scale.
+    pub fn to_matrix(&self) -> Mat4 {
+        let mut m = self.rotation.to_mat4();
+        m[0] *= self.scale.x;
+        m[1] *= self.scale.y;
+        m[2] *= self.scale.z;
+        m[3] = self.translation.extend(1.0);
+        m
+    }
+
+    /// `self` at `t = 0.0` and `rhs` at `t = 1.0`, with slerp for the rotation.
+    pub fn lerp(&self, rhs: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(rhs.translation, t),
+            rotation: self.rotation.slerp(rhs.rotation, t),
+            scale: self.scale.lerp(rhs.scale, t),
+        }
+    }
+}
+
+impl Default for Transform {
+    #[inline]
+    fn default() -> Self { Self::IDENTITY }
+}
+
+/// Exact if the scale of `self` is uniform, like `inverse`.
+impl Mul for Transform {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            translation: self.transform_point(rhs.translation),
+            rotation: self.rotation * rhs.rotation,
+            scale: self.scale * rhs.scale,
+        }
+    }
+}
+
+impl Mul<XYZ<f32>> for Transform {
+    type Output = XYZ<f32>;
+    #[inline]
+    fn mul(self, rhs: XYZ<f32>) -> XYZ<f32> { self.transform_point(rhs) }
+}
+
+impl From<&Transform> for Mat4 {
+    #[inline]
+    fn from(transform: &Transform) -> Self { transform.to_matrix() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn near(a: XYZ<f32>, b: XYZ<f32>) -> bool { (a - b).length() < EPSILON }
+
+    fn uniform() -> Transform {
+        Transform::new(
+            XYZ::new(1.0, -2.0, 3.0),
+            Quat::from_euler(0.3, -0.7, 1.1),
+            XYZ::splat(2.0),
+        )
+    }
+
+    #[test]
+    fn matches_matrix() {
+        let transform = Transform::new(
+            XYZ::new(1.0, -2.0, 3.0),
+            Quat::from_euler(0.3, -0.7, 1.1),
+            XYZ::new(2.0, 0.5, 3.0),
+        );
+        let m = transform.to_matrix();
+        let point = XYZ::new(0.5, 4.0, -1.5);
+        assert!(near(transform.transform_point(point), m.transform_point(point)));
+        assert!(near(transform.transform_vector(point), m.transform_vector(point)));
+        assert!(near(transform * point, m.transform_point(point)));
+    }
+
+    #[test]
+    fn inverse() {
+        let transform = uniform();
+        let point = XYZ::new(0.5, 4.0, -1.5);
+        assert!(near(transform.inverse() * (transform * point), point));
+        assert!(near(transform * (transform.inverse() * point), point));
+
+        let m = (transform * transform.inverse()).to_matrix();
+        assert!(near(m.transform_point(point), point));
+    }
+
+    #[test]
+    fn product_order() {
+        let parent = uniform();
+        let child = Transform::new(
+            XYZ::new(0.0, 1.0, 0.0),
+            Quat::from_axis_angle(XYZ::new(1.0, 0.0, 0.0), 0.5),
+            XYZ::new(1.0, 3.0, 1.0),
+        );
+        let point = XYZ::new(1.0, 1.0, -2.0);
+        // `child` first and then `parent`.
+        assert!(near((parent * child) * point, parent * (child * point)));
+        let m = parent.to_matrix() * child.to_matrix();
+        assert!(near((parent * child) * point, m.transform_point(point)));
+    }
+
+    #[test]
+    fn look_at() {
+        let target = XYZ::new(4.0, 0.0, -1.0);
+        let transform = Transform::from_translation(XYZ::new(1.0, 2.0, 3.0))
+            .look_at(target, XYZ::new(0.0, 1.0, 0.0));
+        let forward = transform.transform_vector(XYZ::new(0.0, 0.0, -1.0));
+        assert!(near(forward, (target - transform.translation).normalize()));
+        // The right stays horizontal.
+        assert!(transform.transform_vector(XYZ::new(1.0, 0.0, 0.0)).y.abs() < EPSILON);
+    }
+
+    #[test]
+    fn lerp() {
+        let a = Transform::IDENTITY;
+        let b = uniform();
+        assert_eq!(a.lerp(&b, 0.0), a);
+        let end = a.lerp(&b, 1.0);
+        assert!(near(end.translation, b.translation) && near(end.scale, b.scale));
+        assert!(end.rotation.dot(b.rotation).abs() > 1.0 - EPSILON);
+        assert!(near(a.lerp(&b, 0.5).scale, XYZ::splat(1.5)));
+    }
+}
@@ -0,0 +1,366 @@
+//! `XY`, `XYZ` and `XYZW` with arithmetic operators and geometric functions.
+//!
+//! `+`, `-`, `*` and `/` between vectors are component-wise, and `*` and `/` with a scalar
+//! scale all components. Geometric functions are implemented for `f32` and `f64`.
+
+use std::ops::{
+    Neg, Add, Sub, Mul, Div,
+    AddAssign, SubAssign, MulAssign, DivAssign,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq)]
+pub struct XY<T> {
+    pub x: T,
+    pub y: T,
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct XYZ<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct XYZW<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+impl<T> XY<T> {
+    pub fn new(x: T, y: T) -> Self {
+        Self { x, y }
+    }
+
+    #[inline]
+    pub fn extend(self, z: T) -> XYZ<T> { XYZ::new(self.x, self.y, z) }
+}
+
+impl<T> XYZ<T> {
+    pub fn new(x: T, y: T, z: T) -> Self {
+        Self { x, y, z }
+    }
+
+    #[inline]
+    pub fn extend(self, w: T) -> XYZW<T> { XYZW::new(self.x, self.y, self.z, w) }
+    #[inline]
+    pub fn truncate(self) -> XY<T> { XY::new(self.x, self.y) }
+}
+
+impl<T> XYZW<T> {
+    pub fn new(x: T, y: T, z: T, w: T) -> Self {
+        Self { x, y, z, w }
+    }
+
+    #[inline]
+    pub fn truncate(self) -> XYZ<T> { XYZ::new(self.x, self.y, self.z) }
+}
+
+impl<T> XYZ<T> where T: Copy + Mul<Output = T> + Sub<Output = T> {
+    /// Right-handed cross product.
+    #[inline]
+    pub fn cross(self, rhs: Self) -> Self {
+        Self {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
+        }
+    }
+}
+
+/// Numbers which `ZERO` of vectors is made of.
+pub trait Zero {
+    const ZERO: Self;
+}
+
+macro_rules! impl_zero {
+    ($($t:ty = $zero:expr),*) => { $(impl Zero for $t { const ZERO: Self = $zero; })* };
+}
+
+impl_zero!(f32 = 0.0, f64 = 0.0, i32 = 0, u32 = 0, usize = 0);
+
+/// Implements operators and functions for a vector type with the components.
+macro_rules! impl_vector {
+    (@float $vector:ident { $($c:ident),* } $float:ty) => {
+        impl $vector<$float> {
+            #[inline]
+            pub fn length_squared(self) -> $float { self.dot(self) }
+
+            #[inline]
+            pub fn length(self) -> $float { self.dot(self).sqrt() }
+
+            #[inline]
+            pub fn distance(self, rhs: Self) -> $float { (rhs - self).length() }
+
+            /// Unit vector in the same direction. A zero vector stays zero instead of NaN.
+            #[inline]
+            pub fn normalize(self) -> Self { self.try_normalize().unwrap_or(Self::ZERO) }
+
+            /// `None` if the length is zero or not finite.
+            #[inline]
+            pub fn try_normalize(self) -> Option<Self> {
+                let length = self.length();
+                if length > 0.0 && length.is_finite() { Some(self / length) } else { None }
+            }
+
+            /// `self` at `t = 0.0` and `rhs` at `t = 1.0`.
+            #[inline]
+            pub fn lerp(self, rhs: Self, t: $float) -> Self { self + (rhs - self) * t }
+        }
+
+        impl Mul<$vector<$float>> for $float {
+            type Output = $vector<$float>;
+            #[inline]
+            fn mul(self, rhs: $vector<$float>) -> $vector<$float> { rhs * self }
+        }
+    };
+
+    ($vector:ident { $($c:ident),* }) => {
+        impl<T: Zero> $vector<T> {
+            pub const ZERO: Self = Self { $($c: T::ZERO),* };
+        }
+
+        impl<T: Copy> $vector<T> {
+            /// All components are `value`.
+            #[inline]
+            pub fn splat(value: T) -> Self { Self { $($c: value),* } }
+
+            /// Apply `f` to each component.
+            #[inline]
+            pub fn map<U, F: Fn(T) -> U>(self, f: F) -> $vector<U> { $vector { $($c: f(self.$c)),* } }
+        }
+
+        impl<T> $vector<T> where T: Copy + Add<Output = T> + Mul<Output = T> + Default {
+            #[inline]
+            pub fn dot(self, rhs: Self) -> T {
+                let mut sum = T::default();
+                $(sum = sum + self.$c * rhs.$c;)*
+                sum
+            }
+        }
+
+        impl<T> $vector<T> where T: Copy + PartialOrd {
+            /// Component-wise minimum.
+            #[inline]
+            pub fn min(self, rhs: Self) -> Self {
+                Self { $($c: if rhs.$c < self.$c { rhs.$c } else { self.$c }),* }
+            }
+
+            /// Component-wise maximum.
+            #[inline]
+            pub fn max(self, rhs: Self) -> Self {
+                Self { $($c: if rhs.$c > self.$c { rhs.$c } else { self.$c }),* }
+            }
+
+            /// Component-wise clamp into `[min, max]`.
+            #[inline]
+            pub fn clamp(self, min: Self, max: Self) -> Self { self.max(min).min(max) }
+        }
+
+        impl<T: Neg<Output = T>> Neg for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn neg(self) -> Self { Self { $($c: -self.$c),* } }
+        }
+
+        impl<T: Add<Output = T>> Add for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn add(self, rhs: Self) -> Self { Self { $($c: self.$c + rhs.$c),* } }
+        }
+
+        impl<T: Sub<Output = T>> Sub for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn sub(self, rhs: Self) -> Self { Self { $($c: self.$c - rhs.$c),* } }
+        }
+
+        impl<T: Mul<Output = T>> Mul for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn mul(self, rhs: Self) -> Self { Self { $($c: self.$c * rhs.$c),* } }
+        }
+
+        impl<T: Div<Output = T>> Div for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn div(self, rhs: Self) -> Self { Self { $($c: self.$c / rhs.$c),* } }
+        }
+
+        impl<T: Mul<Output = T> + Copy> Mul<T> for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn mul(self, rhs: T) -> Self { Self { $($c: self.$c * rhs),* } }
+        }
+
+        impl<T: Div<Output = T> + Copy> Div<T> for $vector<T> {
+            type Output = Self;
+            #[inline]
+            fn div(self, rhs: T) -> Self { Self { $($c: self.$c / rhs),* } }
+        }
+
+        impl<T: AddAssign> AddAssign for $vector<T> {
+            #[inline]
+            fn add_assign(&mut self, rhs: Self) { $(self.$c += rhs.$c;)* }
+        }
+
+        impl<T: SubAssign> SubAssign for $vector<T> {
+            #[inline]
+            fn sub_assign(&mut self, rhs: Self) { $(self.$c -= rhs.$c;)* }
+        }
+
+        impl<T: MulAssign> MulAssign for $vector<T> {
+            #[inline]
+            fn mul_assign(&mut self, rhs: Self) { $(self.$c *= rhs.$c;)* }
+        }
+
+        impl<T: DivAssign> DivAssign for $vector<T> {
+            #[inline]
+            fn div_assign(&mut self, rhs: Self) { $(self.$c /= rhs.$c;)* }
+        }
+
+        impl<T: MulAssign + Copy> MulAssign<T> for $vector<T> {
+            #[inline]
+            fn mul_assign(&mut self, rhs: T) { $(self.$c *= rhs;)* }
+        }
+
+        impl<T: DivAssign + Copy> DivAssign<T> for $vector<T> {
+            #[inline]
+            fn div_assign(&mut self, rhs: T) { $(self.$c /= rhs;)* }
+        }
+
+        impl_vector!(@float $vector { $($c),* } f32);
+        impl_vector!(@float $vector { $($c),* } f64);
+    };
+}
+
+impl_vector!(XY { x, y });
+impl_vector!(XYZ { x, y, z });
+impl_vector!(XYZW { x, y, z, w });
+
+/// Implements conversions between a vector type and arrays and tuples.
+macro_rules! impl_conversion {
+    ($vector:ident { $($c:ident),* } [$n:expr] ($($t:ident),*)) => {
+        impl<T> From<[T; $n]> for $vector<T> {
+            #[inline]
+            fn from([$($c),*]: [T; $n]) -> Self { Self { $($c),* } }
+        }
+
+        impl<T> From<$vector<T>> for [T; $n] {
+            #[inline]
+            fn from(v: $vector<T>) -> Self { [$(v.$c),*] }
+        }
+
+        impl<T> From<($($t),*)> for $vector<T> {
+            #[inline]
+            fn from(($($c),*): ($($t),*)) -> Self { Self { $($c),* } }
+        }
+    };
+}
+
+impl_conversion!(XY { x, y } [2] (T, T));
+impl_conversion!(XYZ { x, y, z } [3] (T, T, T));
+impl_conversion!(XYZW { x, y, z, w } [4] (T, T, T, T));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_zero() {
+        assert_eq!(XYZ::<f32>::ZERO.normalize(), XYZ::ZERO);
+        assert_eq!(XY::<f64>::ZERO.try_normalize(), None);
+        assert_eq!(XYZ::new(f32::NAN, 0.0, 0.0).try_normalize(), None);
+        assert_eq!(XYZ::new(f32::INFINITY, 0.0, 0.0).try_normalize(), None);
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(XYZ::new(0.0_f32, 3.0, 0.0).normalize(), XYZ::new(0.0, 1.0, 0.0));
+        assert_eq!(XY::new(3.0_f64, 4.0).try_normalize(), Some(XY::new(0.6, 0.8)));
+        let length = XYZW::new(1.0_f32, -2.0, 3.0, -4.0).normalize().length();
+        assert!((length - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn lerp_endpoints() {
+        let a = XYZ::new(1.0_f32, -2.0, 3.0);
+        let b = XYZ::new(-4.0, 5.0, 0.5);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.5), XYZ::new(-1.5, 1.5, 1.75));
+    }
+
+    #[test]
+    fn cross_and_dot() {
+        let x = XYZ::new(1.0_f32, 0.0, 0.0);
+        let y = XYZ::new(0.0, 1.0, 0.0);
+        let z = XYZ::new(0.0, 0.0, 1.0);
+        // Right-handed.
+        assert_eq!(x.cross(y), z);
+        assert_eq!(y.cross(z), x);
+        assert_eq!(z.cross(x), y);
+        assert_eq!(y.cross(x), -z);
+
+        let a = XYZ::new(1, 2, 3);
+        let b = XYZ::new(-4, 5, 6);
+        assert_eq!(a.dot(b), 24);
+        assert_eq!(a.cross(b).dot(a), 0);
+        assert_eq!(a.cross(b).dot(b), 0);
+        assert_eq!(XYZW::new(1.0_f32, 2.0, 3.0, 4.0).length_squared(), 30.0);
+        assert_eq!(XY::new(0.0_f32, 0.0).distance(XY::new(3.0, 4.0)), 5.0);
+    }
+
+    #[test]
+    fn operators() {
+        let a = XY::new(6.0_f32, -8.0);
+        let b = XY::new(2.0, 4.0);
+        assert_eq!(-a, XY::new(-6.0, 8.0));
+        assert_eq!(a + b, XY::new(8.0, -4.0));
+        assert_eq!(a - b, XY::new(4.0, -12.0));
+        assert_eq!(a * b, XY::new(12.0, -32.0));
+        assert_eq!(a / b, XY::new(3.0, -2.0));
+        assert_eq!(a * 0.5, XY::new(3.0, -4.0));
+        assert_eq!(0.5 * a, XY::new(3.0, -4.0));
+        assert_eq!(a / 2.0, XY::new(3.0, -4.0));
+
+        let mut c = a;
+        c += b;
+        assert_eq!(c, a + b);
+        c -= b;
+        assert_eq!(c, a);
+        c *= b;
+        assert_eq!(c, a * b);
+        c /= b;
+        assert_eq!(c, a);
+        c *= 2.0;
+        assert_eq!(c, a * 2.0);
+        c /= 2.0;
+        assert_eq!(c, a);
+    }
+
+    #[test]
+    fn component_wise() {
+        let a = XYZ::new(1, 5, -3);
+        let b = XYZ::new(2, -1, -3);
+        assert_eq!(a.min(b), XYZ::new(1, -1, -3));
+        assert_eq!(a.max(b), XYZ::new(2, 5, -3));
+        assert_eq!(a.clamp(XYZ::splat(0), XYZ::splat(2)), XYZ::new(1, 2, 0));
+        assert_eq!(a.map(|c| c * 2), XYZ::new(2, 10, -6));
+        assert_eq!(XYZ::<i32>::ZERO, XYZ::default());
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(XYZ::from([1, 2, 3]), XYZ::new(1, 2, 3));
+        assert_eq!(<[i32; 4]>::from(XYZW::new(1, 2, 3, 4)), [1, 2, 3, 4]);
+        assert_eq!(XY::from((1, 2)), XY::new(1, 2));
+        assert_eq!(XY::new(1, 2).extend(3).extend(4).truncate().truncate(), XY::new(1, 2));
+    }
+}
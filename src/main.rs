@@ -1,14 +1,3 @@
-//mod utility;
-mod linear_algebra;
-mod vulkan;
-mod window;
-mod engine;
-//mod graphics;
-//mod player;
-//mod field;
-mod gui;
-mod input;
-
 fn main() {
-    engine::run();
+    sinsha::engine::run();
 }
@@ -4,6 +4,11 @@ pub struct Timer {
     start: time::SystemTime,
 }
 
+impl Default for Timer {
+    #[inline]
+    fn default() -> Self { Self::new() }
+}
+
 impl Timer {
     #[inline]
     pub fn new() -> Self { Self { start: time::SystemTime::now() } }
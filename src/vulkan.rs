@@ -12,7 +12,8 @@
 //! ## vk::Buffer
 //! Allocating and Deallocating.
 
-mod memory;
+pub mod command;
+#[macro_use]
 pub mod render;
 
 use ash::vk;
@@ -25,16 +26,17 @@ use ash::version::{ EntryV1_0, InstanceV1_0, DeviceV1_0 };
 use winit::Window;
 
 use std::ptr;
-use std::sync::Once;
 use std::ffi::CString;
 use std::mem::ManuallyDrop;
 
 pub struct Vulkan {
-    entry: Entry,
+    /// Keeps the Vulkan library loaded.
+    _entry: Entry,
     instance: Instance,
     surface: ManuallyDrop<SurfaceKHR>,
     physical_device: PhysicalDevice,
     device: Device,
+    queue: Queue,
     debug: ManuallyDrop<DebugEXT>,
 }
 
@@ -46,6 +48,7 @@ struct SurfaceKHR {
 
 struct PhysicalDevice {
     handle: vk::PhysicalDevice,
+    properties: vk::PhysicalDeviceProperties,
     memory_types: Vec<vk::MemoryType>,
 }
 
@@ -56,8 +59,11 @@ struct DebugEXT {
     utils: vk::DebugUtilsMessengerEXT,
 }
 
-// TODO: Decide Queue implementings.
-pub struct Queue(vk::Queue);
+/// The queue of graphics and presentation, which every command buffer is submitted to.
+struct Queue {
+    handle: vk::Queue,
+    family_index: u32,
+}
 
 impl Vulkan {
     pub fn new(window: Window) -> Self {
@@ -65,9 +71,9 @@ impl Vulkan {
         let instance = Self::create_instance(&entry);
         let debug = DebugEXT::new_in_manually_drop(&entry, &instance);
         let surface = SurfaceKHR::new_in_manually_drop(&entry, &instance, window);
-        let (physical_device, device) = Self::create_device(&instance, &surface);
+        let (physical_device, device, queue) = Self::create_device(&instance, &surface);
 
-        Self { entry, instance, surface, physical_device, device, debug }
+        Self { _entry: entry, instance, surface, physical_device, device, queue, debug }
     }
 
     fn create_instance(entry: &Entry) -> Instance {
@@ -81,19 +87,12 @@ impl Vulkan {
             api_version: vk_make_version!(1, 1, 117),
         };
 
-        let instance_extensions = if cfg!(debug_assertions) {
-            vec![
-                khr::Surface::name().as_ptr() as _,
-                khr::Win32Surface::name().as_ptr() as _,
-                ext::DebugReport::name().as_ptr() as _,
-                ext::DebugUtils::name().as_ptr() as _,
-            ]
-        } else {
-            vec![
-                khr::Surface::name().as_ptr() as _,
-                khr::Win32Surface::name().as_ptr() as _,
-            ]
-        };
+        let mut instance_extensions = vec![khr::Surface::name().as_ptr()];
+        instance_extensions.extend(SurfaceKHR::platform_extension_names(entry));
+        if cfg!(debug_assertions) {
+            instance_extensions.push(ext::DebugReport::name().as_ptr());
+            instance_extensions.push(ext::DebugUtils::name().as_ptr());
+        }
 
         let debug_layer = CString::new("VK_LAYER_LUNARG_standard_validation").unwrap();
         let instance_layers = if cfg!(debug_assertions) {
@@ -115,15 +114,11 @@ impl Vulkan {
         unsafe { entry.create_instance(&instance_info, None).unwrap() }
     }
 
-    fn create_device(instance: &Instance, surface: &SurfaceKHR) -> (PhysicalDevice, Device) {
+    fn create_device(instance: &Instance, surface: &SurfaceKHR) -> (PhysicalDevice, Device, Queue) {
         let vk_physical_devices = unsafe { instance.enumerate_physical_devices().unwrap() };
         let (vk_physical_device, queue_family_index) = vk_physical_devices
             .into_iter()
             .find_map(|vk_physical_device| {
-                let property = unsafe {
-                    instance.get_physical_device_properties(vk_physical_device)
-                };
-
                 let queue_families = unsafe {
                     instance.get_physical_device_queue_family_properties(vk_physical_device)
                 };
@@ -150,6 +145,7 @@ impl Vulkan {
             })
             .unwrap();
 
+        let properties = unsafe { instance.get_physical_device_properties(vk_physical_device) };
         let memory_properties = unsafe {
             instance.get_physical_device_memory_properties(vk_physical_device)
         };
@@ -160,6 +156,7 @@ impl Vulkan {
 
         let physical_device = PhysicalDevice {
             handle: vk_physical_device,
+            properties,
             memory_types,
         };
 
@@ -169,16 +166,10 @@ impl Vulkan {
             .queue_priorities(&queue_priorities[..])
             .build();
 
-        let extensions = [];
-        let layers = if cfg!(debug_assertions) {
-            vec![khr::Swapchain::name().as_ptr()]
-        } else {
-            vec![]
-        };
+        let extensions = [khr::Swapchain::name().as_ptr()];
         let device_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(std::slice::from_ref(&queue_info))
             .enabled_extension_names(&extensions[..])
-            .enabled_layer_names(&layers[..])
             .build();
 
         let device = unsafe {
@@ -187,7 +178,74 @@ impl Vulkan {
                 .unwrap()
         };
 
-        (physical_device, device)
+        let queue = Queue {
+            handle: unsafe { device.get_device_queue(queue_family_index, 0) },
+            family_index: queue_family_index,
+        };
+
+        (physical_device, device, queue)
+    }
+}
+
+impl Vulkan {
+    /// Name the object in validation messages and capture tools.
+    /// Nothing is done in release builds, where debug utils are not enabled.
+    /// Names only help debugging, so failures are logged and otherwise ignored.
+    pub fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        if !cfg!(debug_assertions) { return; }
+
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(err) => {
+                eprintln!("failed to name {:?} {:?}: {}", H::TYPE, name, err);
+                return;
+            }
+        };
+        let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name);
+
+        let result = unsafe {
+            self.debug.utils_loader.debug_utils_set_object_name(self.device.handle(), &info)
+        };
+        if let Err(err) = result {
+            eprintln!("failed to name {:?} {:?}: {}", H::TYPE, name, err);
+        }
+    }
+
+    /// Open a labeled region of commands, e.g. a subpass. Nothing is done in release builds.
+    /// # Safety
+    /// The command buffer must be recording, and the region must be closed in it.
+    pub unsafe fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        if !cfg!(debug_assertions) { return; }
+
+        let name = CString::new(name).unwrap();
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(&name);
+        self.debug.utils_loader.cmd_begin_debug_utils_label(command_buffer, &label);
+    }
+
+    /// Close the region opened by `begin_label`.
+    /// # Safety
+    /// The command buffer must be recording, with a region open.
+    pub unsafe fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if !cfg!(debug_assertions) { return; }
+        self.debug.utils_loader.cmd_end_debug_utils_label(command_buffer);
+    }
+}
+
+impl PhysicalDevice {
+    /// Search the index of a memory type which is in `type_bits` and has all of `flags`.
+    fn memory_type_index(&self, type_bits: u32, flags: vk::MemoryPropertyFlags) -> Option<u32> {
+        self.memory_types
+            .iter()
+            .enumerate()
+            .position(|(i, vk::MemoryType { property_flags, .. })| {
+                let supported = type_bits & 1 << i as u32 != 0;
+                supported && property_flags.contains(flags)
+            })
+            .map(|index| index as u32)
     }
 }
 
@@ -236,28 +294,16 @@ impl DebugEXT {
     }
 
     unsafe extern "system" fn report_callback(
-        flags: vk::DebugReportFlagsEXT,
+        _flags: vk::DebugReportFlagsEXT,
         _object_type: vk::DebugReportObjectTypeEXT,
         _object: u64,
         _location: usize,
         _message_code: i32,
         _p_layer_prefix: *const i8,
-        p_message: *const i8,
+        _p_message: *const i8,
         _p_user_data: *mut std::ffi::c_void,
     ) -> vk::Bool32 {
-        let message = std::ffi::CStr::from_ptr(p_message as _).to_str().unwrap();
-        let header = "\u{001b}[37;1mReport\u{001b}[0m";
-        let flags = match flags {
-            vk::DebugReportFlagsEXT::ERROR => "\u{001b}[31mError\u{001b}[0m",
-            vk::DebugReportFlagsEXT::WARNING => "\u{001b}[33mWarning\u{001b}[0m",
-            vk::DebugReportFlagsEXT::PERFORMANCE_WARNING => "\u{001b}[35mPerformance Warning\u{001b}[0m",
-            vk::DebugReportFlagsEXT::DEBUG => "\u{001b}[32mDebug\u{001b}[0m",
-            vk::DebugReportFlagsEXT::INFORMATION => "\u{001b}[34mInformation\u{001b}[0m",
-            _ => "Unknown",
-        };
-
-        // eprintln!("{}: {}\n {}", header, flags, message);
-
+        // `utils_callback` prints the same messages.
         vk::FALSE
     }
 
@@ -265,7 +311,7 @@ impl DebugEXT {
         message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
         message_types: vk::DebugUtilsMessageTypeFlagsEXT,
         p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-        p_user_data: *mut std::ffi::c_void,
+        _p_user_data: *mut std::ffi::c_void,
     ) -> vk::Bool32 {
         let message = std::ffi::CStr::from_ptr((*p_callback_data).p_message).to_str().unwrap();
 
@@ -317,6 +363,30 @@ impl SurfaceKHR {
     }
 
 
+    /// Names of the instance extensions which `handle` needs.
+    #[cfg(target_os = "windows")]
+    fn platform_extension_names(_entry: &Entry) -> Vec<*const i8> {
+        vec![khr::Win32Surface::name().as_ptr()]
+    }
+
+    /// Names of the instance extensions which `handle` needs. Both of Xlib and Wayland are
+    /// enabled if they are available, since the window decides which one is used.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn platform_extension_names(entry: &Entry) -> Vec<*const i8> {
+        let available = entry.enumerate_instance_extension_properties().unwrap_or_default();
+        let is_available = |name: &std::ffi::CStr| {
+            available
+                .iter()
+                .any(|properties| unsafe { std::ffi::CStr::from_ptr(properties.extension_name.as_ptr()) } == name)
+        };
+
+        [khr::XlibSurface::name(), khr::WaylandSurface::name()]
+            .iter()
+            .filter(|name| is_available(name))
+            .map(|name| name.as_ptr())
+            .collect()
+    }
+
     #[cfg(target_os = "windows")]
     unsafe fn handle(
         entry: &Entry,
@@ -334,6 +404,33 @@ impl SurfaceKHR {
             .create_win32_surface(&*info, None)
             .unwrap()
     }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    unsafe fn handle(
+        entry: &Entry,
+        instance: &Instance,
+        window: &Window
+    ) -> vk::SurfaceKHR {
+        use winit::os::unix::WindowExt;
+
+        if let (Some(display), Some(surface)) = (window.get_wayland_display(), window.get_wayland_surface()) {
+            let info = vk::WaylandSurfaceCreateInfoKHR::builder()
+                .display(display)
+                .surface(surface);
+
+            return khr::WaylandSurface::new(entry, instance)
+                .create_wayland_surface(&info, None)
+                .unwrap();
+        }
+
+        let info = vk::XlibSurfaceCreateInfoKHR::builder()
+            .dpy(window.get_xlib_display().unwrap() as *mut vk::Display)
+            .window(window.get_xlib_window().unwrap() as vk::Window);
+
+        khr::XlibSurface::new(entry, instance)
+            .create_xlib_surface(&info, None)
+            .unwrap()
+    }
 }
 
 impl Drop for SurfaceKHR {
@@ -1,136 +1,868 @@
-use super::*;
+use ash::vk;
+use ash::version::DeviceV1_0;
+use ash::Device;
 
-/// Host access to CommandPool must be externally synchronized.
-/// Synchronization take some cost, so this struct is not Send.
-pub struct CommandPool<I, D> where I: Borrow<Instance>, D: Borrow<Device<I>> {
-    _marker: PhantomData<(I, *const ())>,
-    device: D,
+use super::Vulkan;
+
+use std::any::Any;
+use std::mem;
+use std::panic::{ self, AssertUnwindSafe };
+use std::slice;
+use std::sync::Arc;
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::thread::{ self, JoinHandle };
+
+/// Host access to CommandPool must be externally synchronized, so everything allocating from
+/// or resetting it takes `&mut self`. It can be moved to another thread, e.g. a worker.
+pub struct CommandPool {
     handle: vk::CommandPool,
 }
 
-pub struct CommandBuffer<I, D, P> where
-    I: Borrow<Instance>,
-    D: Borrow<Device<I>>,
-    P: Borrow<CommandPool<I, D>>,
-{
-    _marker: PhantomData<(I, D)>,
-    pool: P,
+/// A command buffer in the state `S`, one of `Initial`, `Recording` and `Executable`.
+///
+/// Resources referred by recorded commands are kept by the command buffer until it is reset,
+/// so that they live until the submission completes. They are `Send`, so secondary command
+/// buffers can be recorded by worker threads.
+/// Command buffers are freed with their pool.
+pub struct CommandBuffer<S = Initial> {
     handle: vk::CommandBuffer,
+    level: vk::CommandBufferLevel,
+    resources: Vec<Box<dyn Any + Send>>,
+    state: S,
 }
 
-impl<I, D> CommandPool<I, D> where I: Borrow<Instance>, D: Borrow<Device<I>> {
-    pub fn new(
-        device: D,
-        flags: vk::CommandPoolCreateFlags,
-        queue: &Queue<I, D>,
-    ) -> Self {
-        let info = vk::CommandPoolCreateInfo {
-            s_type: StructureType::COMMAND_POOL_CREATE_INFO,
-            p_next: ptr::null(),
-            flags,
-            queue_family_index: queue.family_index,
-        };
+/// Allocated or reset. Nothing is recorded.
+pub struct Initial;
+/// Between begin and end.
+pub struct Recording<'a> {
+    vulkan: &'a Vulkan,
+}
+/// Recorded and ready to be submitted or executed by primary command buffers.
+pub struct Executable;
+
+/// A submitted command buffer, which is given back by `wait`.
+pub struct Pending {
+    command_buffer: CommandBuffer<Executable>,
+    fence: vk::Fence,
+}
+
+/// A command pool for each worker thread, which records secondary command buffers in
+/// parallel. Keep one for each frame in flight and reset it when the frame completes.
+/// The threads are shared by all of them.
+pub struct WorkerPools {
+    workers: Arc<Workers>,
+    pools: Vec<WorkerPool>,
+}
+
+/// Threads which live until the last `WorkerPools` sharing them is dropped. Each of them runs
+/// jobs received from its channel in order.
+pub struct Workers {
+    senders: Vec<Sender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Results of jobs sent to workers. Dropping it waits for the jobs which have not sent their
+/// results, so that they never outlive data borrowed from the sender, even while unwinding.
+struct Completion<R> {
+    receiver: Receiver<thread::Result<R>>,
+    pending: usize,
+}
+
+/// The render pass, subpass and framebuffer which a secondary command buffer continues.
+#[derive(Copy, Clone, Debug)]
+pub struct Inheritance {
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+    pub framebuffer: vk::Framebuffer,
+}
+
+/// Secondary command buffers allocated from the pool are reused after it is reset.
+struct WorkerPool {
+    pool: CommandPool,
+    secondaries: Vec<vk::CommandBuffer>,
+    used: usize,
+}
+
+/// Buffers which commands can refer to.
+pub trait BufferResource {
+    fn buffer_handle(&self) -> vk::Buffer;
+    /// Offset of the range in the buffer.
+    fn buffer_offset(&self) -> u64;
+    fn buffer_size(&self) -> u64;
+}
+
+/// Images which commands can refer to.
+pub trait ImageResource {
+    fn image_handle(&self) -> vk::Image;
+}
 
-        let handle = unsafe { device.borrow().handle.create_command_pool(&info, None).unwrap() };
+impl CommandPool {
+    /// A pool for the queue of `vulkan`.
+    pub fn new(vulkan: &Vulkan, flags: vk::CommandPoolCreateFlags) -> Self {
+        let info = vk::CommandPoolCreateInfo::builder()
+            .flags(flags)
+            .queue_family_index(vulkan.queue.family_index);
 
-        Self { _marker: PhantomData, device, handle }
+        let handle = unsafe { vulkan.device.create_command_pool(&info, None).unwrap() };
+
+        Self { handle }
+    }
+
+    pub fn allocate_primary(&mut self, vulkan: &Vulkan, count: u32) -> Vec<CommandBuffer> {
+        self.allocate(vulkan, vk::CommandBufferLevel::PRIMARY, count)
+    }
+
+    pub fn allocate_secondary(&mut self, vulkan: &Vulkan, count: u32) -> Vec<CommandBuffer> {
+        self.allocate(vulkan, vk::CommandBufferLevel::SECONDARY, count)
+    }
+
+    fn allocate(&mut self, vulkan: &Vulkan, level: vk::CommandBufferLevel, count: u32) -> Vec<CommandBuffer> {
+        self.allocate_handles(vulkan, level, count)
+            .into_iter()
+            .map(|handle| CommandBuffer::from_handle(handle, level))
+            .collect()
+    }
+
+    fn allocate_handles(
+        &mut self,
+        vulkan: &Vulkan,
+        level: vk::CommandBufferLevel,
+        count: u32,
+    ) -> Vec<vk::CommandBuffer> {
+        let info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(self.handle)
+            .level(level)
+            .command_buffer_count(count);
+
+        unsafe { vulkan.device.allocate_command_buffers(&info).unwrap() }
     }
 
-    pub unsafe fn reset(&self) {
-        self.device.borrow().handle
+    /// Reset all command buffers allocated from this pool into the initial state.
+    /// # Safety
+    /// None of them may be pending. They must be reset or dropped before they are begun again,
+    /// since their types still tell the previous states.
+    pub unsafe fn reset(&mut self, vulkan: &Vulkan) {
+        vulkan.device
             .reset_command_pool(self.handle, vk::CommandPoolResetFlags::empty())
             .unwrap()
     }
+
+    /// # Safety
+    /// The command buffer must be allocated from this pool and must not be pending.
+    pub unsafe fn free<S>(&mut self, vulkan: &Vulkan, command_buffer: CommandBuffer<S>) {
+        vulkan.device.free_command_buffers(self.handle, &[command_buffer.handle]);
+    }
+
+    /// Name in validation messages and capture tools.
+    #[inline]
+    pub fn set_name(&self, vulkan: &Vulkan, name: &str) { vulkan.set_object_name(self.handle, name) }
+
+    /// Command buffers allocated from this pool are freed together.
+    /// # Safety
+    /// None of them may be pending.
+    pub unsafe fn destroy(self, vulkan: &Vulkan) {
+        vulkan.device.destroy_command_pool(self.handle, None);
+    }
 }
 
-impl<I, D, P> CommandBuffer<I, D, P> where
-    I: Borrow<Instance>,
-    D: Borrow<Device<I>>,
-    P: Borrow<CommandPool<I, D>>,
-{
-    pub fn begin_primary(
-        pool: P,
-        usage: &[vk::CommandBufferUsageFlags],
-    ) -> Vec<Self> where P: Clone {
-        let info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(pool.borrow().handle)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(usage.len() as u32);
+impl CommandBuffer<Initial> {
+    fn from_handle(handle: vk::CommandBuffer, level: vk::CommandBufferLevel) -> Self {
+        Self { handle, level, resources: Vec::new(), state: Initial }
+    }
+
+    /// Begin a primary command buffer.
+    pub fn begin<'a>(self, vulkan: &'a Vulkan, usage: vk::CommandBufferUsageFlags) -> CommandBuffer<Recording<'a>> {
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::PRIMARY);
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(usage);
 
-        let handles = unsafe {
-            pool.borrow().device.borrow().handle.allocate_command_buffers(&info).unwrap()
+        unsafe { vulkan.device.begin_command_buffer(self.handle, &info).unwrap(); }
+        self.into_state(Recording { vulkan })
+    }
+
+    /// Begin a secondary command buffer which continues the render pass of `inheritance`.
+    pub fn begin_secondary<'a>(
+        self,
+        vulkan: &'a Vulkan,
+        usage: vk::CommandBufferUsageFlags,
+        inheritance: &Inheritance,
+    ) -> CommandBuffer<Recording<'a>> {
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::SECONDARY);
+        let inheritance = vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(inheritance.render_pass)
+            .subpass(inheritance.subpass)
+            .framebuffer(inheritance.framebuffer);
+        let info = vk::CommandBufferBeginInfo::builder()
+            .inheritance_info(&inheritance)
+            .flags(usage | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE);
+
+        unsafe { vulkan.device.begin_command_buffer(self.handle, &info).unwrap(); }
+        self.into_state(Recording { vulkan })
+    }
+}
+
+impl<'a> CommandBuffer<Recording<'a>> {
+    pub fn end(self) -> CommandBuffer<Executable> {
+        unsafe { self.device().end_command_buffer(self.handle).unwrap(); }
+        self.into_state(Executable)
+    }
+
+    pub fn bind_pipeline(&mut self, bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline) -> &mut Self {
+        unsafe { self.device().cmd_bind_pipeline(self.handle, bind_point, pipeline); }
+        self
+    }
+
+    pub fn bind_descriptor_sets(
+        &mut self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) -> &mut Self {
+        unsafe {
+            self.device().cmd_bind_descriptor_sets(
+                self.handle,
+                bind_point,
+                layout,
+                first_set,
+                descriptor_sets,
+                dynamic_offsets,
+            );
+        }
+        self
+    }
+
+    pub fn push_constants<T: Copy>(
+        &mut self,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        value: &T,
+    ) -> &mut Self {
+        let bytes = unsafe {
+            slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
         };
+        unsafe { self.device().cmd_push_constants(self.handle, layout, stages, offset, bytes); }
+        self
+    }
 
-        let command_buffers = handles.into_iter()
-            .map(|handle| CommandBuffer { _marker: PhantomData, pool: pool.clone(), handle })
-            .collect::<Vec<_>>();
+    /// Bind whole ranges of the buffers from `first_binding`.
+    pub fn bind_vertex_buffers<R>(&mut self, first_binding: u32, buffers: &[R]) -> &mut Self where
+        R: BufferResource + Clone + Send + 'static,
+    {
+        let handles = buffers.iter().map(|buffer| buffer.buffer_handle()).collect::<Vec<_>>();
+        let offsets = buffers.iter().map(|buffer| buffer.buffer_offset()).collect::<Vec<_>>();
+
+        unsafe {
+            self.device()
+                .cmd_bind_vertex_buffers(self.handle, first_binding, &handles[..], &offsets[..]);
+        }
+        buffers.iter().for_each(|buffer| self.keep(buffer));
+        self
+    }
+
+    /// `offset` is relative to the range of the buffer, e.g. where indices follow vertices.
+    pub fn bind_index_buffer<R>(&mut self, buffer: &R, offset: u64, index_type: vk::IndexType) -> &mut Self where
+        R: BufferResource + Clone + Send + 'static,
+    {
+        debug_assert!(offset < buffer.buffer_size());
+        unsafe {
+            self.device().cmd_bind_index_buffer(
+                self.handle,
+                buffer.buffer_handle(),
+                buffer.buffer_offset() + offset,
+                index_type,
+            );
+        }
+        self.keep(buffer);
+        self
+    }
+
+    pub fn set_viewport(&mut self, viewport: vk::Viewport) -> &mut Self {
+        unsafe { self.device().cmd_set_viewport(self.handle, 0, &[viewport]); }
+        self
+    }
 
-        command_buffers.iter()
-            .zip(usage.iter())
-            .for_each(|(command_buffer, usage)| {
-                let info = vk::CommandBufferBeginInfo::builder()
-                    .flags(*usage);
+    pub fn set_scissor(&mut self, scissor: vk::Rect2D) -> &mut Self {
+        unsafe { self.device().cmd_set_scissor(self.handle, 0, &[scissor]); }
+        self
+    }
+
+    pub fn draw(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    ) -> &mut Self {
+        unsafe {
+            self.device()
+                .cmd_draw(self.handle, vertex_count, instance_count, first_vertex, first_instance);
+        }
+        self
+    }
+
+    pub fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) -> &mut Self {
+        unsafe {
+            self.device().cmd_draw_indexed(
+                self.handle,
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            );
+        }
+        self
+    }
 
-                unsafe {
-                    pool.borrow().device.borrow().handle.begin_command_buffer(command_buffer.handle, &info).unwrap();
+    /// Draw with `vk::DrawIndirectCommand`s from the start of the buffer range.
+    pub fn draw_indirect<R>(&mut self, buffer: &R, draw_count: u32, stride: u32) -> &mut Self where
+        R: BufferResource + Clone + Send + 'static,
+    {
+        unsafe {
+            self.device().cmd_draw_indirect(
+                self.handle,
+                buffer.buffer_handle(),
+                buffer.buffer_offset(),
+                draw_count,
+                stride,
+            );
+        }
+        self.keep(buffer);
+        self
+    }
+
+    /// Draw with `vk::DrawIndexedIndirectCommand`s from the start of the buffer range.
+    pub fn draw_indexed_indirect<R>(&mut self, buffer: &R, draw_count: u32, stride: u32) -> &mut Self where
+        R: BufferResource + Clone + Send + 'static,
+    {
+        unsafe {
+            self.device().cmd_draw_indexed_indirect(
+                self.handle,
+                buffer.buffer_handle(),
+                buffer.buffer_offset(),
+                draw_count,
+                stride,
+            );
+        }
+        self.keep(buffer);
+        self
+    }
+
+    /// Offsets of the regions are relative to the ranges of the buffers.
+    pub fn copy_buffer<S, T>(&mut self, src: &S, dst: &T, regions: &[vk::BufferCopy]) -> &mut Self where
+        S: BufferResource + Clone + Send + 'static,
+        T: BufferResource + Clone + Send + 'static,
+    {
+        let regions = regions
+            .iter()
+            .map(|region| {
+                debug_assert!(region.src_offset + region.size <= src.buffer_size());
+                debug_assert!(region.dst_offset + region.size <= dst.buffer_size());
+                vk::BufferCopy {
+                    src_offset: src.buffer_offset() + region.src_offset,
+                    dst_offset: dst.buffer_offset() + region.dst_offset,
+                    size: region.size,
                 }
-            });
+            })
+            .collect::<Vec<_>>();
 
-        command_buffers
+        unsafe {
+            self.device()
+                .cmd_copy_buffer(self.handle, src.buffer_handle(), dst.buffer_handle(), &regions[..]);
+        }
+        self.keep(src);
+        self.keep(dst);
+        self
     }
 
-    pub fn begin_secondary(
-        pool: P,
-        usage: &[vk::CommandBufferUsageFlags],
-        inheritances: &[vk::CommandBufferInheritanceInfo],
-    ) -> Vec<Self> where P: Clone {
-        let info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(pool.borrow().handle)
-            .level(vk::CommandBufferLevel::SECONDARY)
-            .command_buffer_count(usage.len() as u32);
+    /// Offsets of the regions are relative to the range of the buffer.
+    pub fn copy_buffer_to_image<S, T>(
+        &mut self,
+        src: &S,
+        dst: &T,
+        dst_layout: vk::ImageLayout,
+        regions: &[vk::BufferImageCopy],
+    ) -> &mut Self where
+        S: BufferResource + Clone + Send + 'static,
+        T: ImageResource + Clone + Send + 'static,
+    {
+        let regions = regions
+            .iter()
+            .map(|region| vk::BufferImageCopy {
+                buffer_offset: src.buffer_offset() + region.buffer_offset,
+                ..*region
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.device().cmd_copy_buffer_to_image(
+                self.handle,
+                src.buffer_handle(),
+                dst.image_handle(),
+                dst_layout,
+                &regions[..],
+            );
+        }
+        self.keep(src);
+        self.keep(dst);
+        self
+    }
+
+    pub fn copy_image<S, T>(
+        &mut self,
+        src: &S,
+        src_layout: vk::ImageLayout,
+        dst: &T,
+        dst_layout: vk::ImageLayout,
+        regions: &[vk::ImageCopy],
+    ) -> &mut Self where
+        S: ImageResource + Clone + Send + 'static,
+        T: ImageResource + Clone + Send + 'static,
+    {
+        unsafe {
+            self.device().cmd_copy_image(
+                self.handle,
+                src.image_handle(),
+                src_layout,
+                dst.image_handle(),
+                dst_layout,
+                regions,
+            );
+        }
+        self.keep(src);
+        self.keep(dst);
+        self
+    }
+
+    pub fn memory_barrier(
+        &mut self,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+    ) -> &mut Self {
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .build();
+
+        unsafe {
+            self.device().cmd_pipeline_barrier(
+                self.handle,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+        self
+    }
 
-        let handles = unsafe {
-            pool.borrow().device.borrow().handle.allocate_command_buffers(&info).unwrap()
+    /// `buffer`, `offset` and `size` of the barrier are replaced by the range of the buffer.
+    pub fn buffer_barrier<R>(
+        &mut self,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        buffer: &R,
+        barrier: vk::BufferMemoryBarrier,
+    ) -> &mut Self where
+        R: BufferResource + Clone + Send + 'static,
+    {
+        let barrier = vk::BufferMemoryBarrier {
+            buffer: buffer.buffer_handle(),
+            offset: buffer.buffer_offset(),
+            size: buffer.buffer_size(),
+            ..barrier
         };
 
-        let command_buffers = handles.into_iter()
-            .map(|handle| CommandBuffer { _marker: PhantomData, pool: pool.clone(), handle })
-            .collect::<Vec<_>>();
+        unsafe {
+            self.device().cmd_pipeline_barrier(
+                self.handle,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+        }
+        self.keep(buffer);
+        self
+    }
 
-        assert_eq!(usage.len(), inheritances.len());
-        command_buffers.iter()
-            .zip(usage.iter())
-            .zip(inheritances.iter())
-            .for_each(|((command_buffer, usage), inheritance)| {
-                let info = vk::CommandBufferBeginInfo::builder()
-                    .inheritance_info(inheritance)
-                    .flags(*usage);
-
-                unsafe {
-                    pool.borrow().device.borrow().handle.begin_command_buffer(command_buffer.handle, &info).unwrap();
-                }
-            });
+    /// `image` of the barrier is replaced by the image.
+    pub fn image_barrier<R>(
+        &mut self,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        image: &R,
+        barrier: vk::ImageMemoryBarrier,
+    ) -> &mut Self where
+        R: ImageResource + Clone + Send + 'static,
+    {
+        let barrier = vk::ImageMemoryBarrier { image: image.image_handle(), ..barrier };
+
+        unsafe {
+            self.device().cmd_pipeline_barrier(
+                self.handle,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+        self.keep(image);
+        self
+    }
+
+    pub fn begin_render_pass(
+        &mut self,
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        render_area: vk::Rect2D,
+        clear_values: &[vk::ClearValue],
+        contents: vk::SubpassContents,
+    ) -> &mut Self {
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::PRIMARY);
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(framebuffer)
+            .render_area(render_area)
+            .clear_values(clear_values);
+
+        unsafe { self.device().cmd_begin_render_pass(self.handle, &info, contents); }
+        self
+    }
+
+    pub fn next_subpass(&mut self, contents: vk::SubpassContents) -> &mut Self {
+        unsafe { self.device().cmd_next_subpass(self.handle, contents); }
+        self
+    }
+
+    pub fn end_render_pass(&mut self) -> &mut Self {
+        unsafe { self.device().cmd_end_render_pass(self.handle); }
+        self
+    }
+
+    /// The secondary command buffers are kept by this command buffer, along with resources
+    /// which they refer to.
+    pub fn execute_commands(&mut self, secondaries: Vec<CommandBuffer<Executable>>) -> &mut Self {
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::PRIMARY);
+        debug_assert!(secondaries.iter().all(|secondary| secondary.level == vk::CommandBufferLevel::SECONDARY));
+        let handles = secondaries.iter().map(|secondary| secondary.handle).collect::<Vec<_>>();
+
+        // At least one command buffer must be executed.
+        if !handles.is_empty() {
+            unsafe { self.device().cmd_execute_commands(self.handle, &handles[..]); }
+        }
+        self.resources.extend(secondaries.into_iter().map(|secondary| Box::new(secondary) as Box<dyn Any + Send>));
+        self
+    }
+
+    /// Must be recorded outside of render passes.
+    pub fn reset_query_pool(&mut self, pool: vk::QueryPool, first_query: u32, query_count: u32) -> &mut Self {
+        unsafe { self.device().cmd_reset_query_pool(self.handle, pool, first_query, query_count); }
+        self
+    }
+
+    /// Write a timestamp when the commands before reach `stage`.
+    pub fn write_timestamp(&mut self, stage: vk::PipelineStageFlags, pool: vk::QueryPool, query: u32) -> &mut Self {
+        unsafe { self.device().cmd_write_timestamp(self.handle, stage, pool, query); }
+        self
+    }
 
-        command_buffers
+    /// Open a labeled region of commands, e.g. a subpass.
+    pub fn begin_label(&mut self, name: &str) -> &mut Self {
+        unsafe { self.state.vulkan.begin_label(self.handle, name); }
+        self
     }
 
-    pub fn free(self) {
-        unsafe { self.pool.borrow().device.borrow().handle.free_command_buffers(self.pool.borrow().handle, &[self.handle]); }
+    pub fn end_label(&mut self) -> &mut Self {
+        unsafe { self.state.vulkan.end_label(self.handle); }
+        self
+    }
+
+    /// Keep a resource which commands refer to without a command of this type, e.g. a texture
+    /// in a bound descriptor set.
+    #[inline]
+    pub fn keep<R: Clone + Send + 'static>(&mut self, resource: &R) {
+        self.resources.push(Box::new(resource.clone()));
+    }
+
+    #[inline]
+    fn device(&self) -> &Device { &self.state.vulkan.device }
+}
+
+impl CommandBuffer<Executable> {
+    /// Submit this primary command buffer, which signals the unsignaled `fence` when it
+    /// completes. The returned `Pending` keeps this and the resources until then.
+    /// # Safety
+    /// The queue must not be used by other threads at the same time.
+    pub unsafe fn submit(
+        self,
+        vulkan: &Vulkan,
+        waits: &[(vk::Semaphore, vk::PipelineStageFlags)],
+        signals: &[vk::Semaphore],
+        fence: vk::Fence,
+    ) -> Pending {
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::PRIMARY);
+        let wait_semaphores = waits.iter().map(|(semaphore, _)| *semaphore).collect::<Vec<_>>();
+        let wait_stages = waits.iter().map(|(_, stage)| *stage).collect::<Vec<_>>();
+        let command_buffers = [self.handle];
+        let info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores[..])
+            .wait_dst_stage_mask(&wait_stages[..])
+            .command_buffers(&command_buffers[..])
+            .signal_semaphores(signals)
+            .build();
+
+        vulkan.device.queue_submit(vulkan.queue.handle, &[info], fence).unwrap();
+
+        Pending { command_buffer: self, fence }
+    }
+}
+
+impl<S> CommandBuffer<S> {
+    /// Reset into the initial state and release the resources.
+    /// The pool must be created with `vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER`.
+    pub fn reset(mut self, vulkan: &Vulkan) -> CommandBuffer<Initial> {
+        unsafe {
+            vulkan.device
+                .reset_command_buffer(self.handle, vk::CommandBufferResetFlags::empty())
+                .unwrap();
+        }
+        self.resources.clear();
+        self.into_state(Initial)
     }
 
     #[inline]
     pub fn handle(&self) -> vk::CommandBuffer { self.handle }
 
+    /// Name in validation messages and capture tools.
+    #[inline]
+    pub fn set_name(&self, vulkan: &Vulkan, name: &str) { vulkan.set_object_name(self.handle, name) }
+
+    fn into_state<T>(self, state: T) -> CommandBuffer<T> {
+        CommandBuffer {
+            handle: self.handle,
+            level: self.level,
+            resources: self.resources,
+            state,
+        }
+    }
+}
+
+impl Pending {
+    pub fn is_complete(&self, vulkan: &Vulkan) -> bool {
+        match unsafe { vulkan.device.get_fence_status(self.fence) } {
+            Ok(()) => true,
+            Err(vk::Result::NOT_READY) => false,
+            Err(err) => panic!("{:?}", err),
+        }
+    }
+
+    /// Wait for the submission to complete. The command buffer can be submitted again or reset.
+    /// The fence is left signaled.
+    pub fn wait(self, vulkan: &Vulkan) -> CommandBuffer<Executable> {
+        unsafe { vulkan.device.wait_for_fences(&[self.fence], true, !0).unwrap(); }
+        self.command_buffer
+    }
+}
+
+impl Workers {
+    pub fn new(worker_count: usize) -> Self {
+        let (senders, threads) = (0..worker_count)
+            .map(|index| {
+                let (sender, receiver) = mpsc::channel::<Job>();
+                let thread = thread::Builder::new()
+                    .name(format!("worker {}", index))
+                    .spawn(move || receiver.into_iter().for_each(|job| job()))
+                    .unwrap();
+                (sender, thread)
+            })
+            .unzip();
+
+        Self { senders, threads }
+    }
+
     #[inline]
-    fn device_handle(&self) -> &VkDevice { &self.pool.borrow().device.borrow().handle }
+    pub fn count(&self) -> usize { self.senders.len() }
+}
+
+impl Drop for Workers {
+    /// Close the channels and wait for the threads to finish their jobs.
+    fn drop(&mut self) {
+        self.senders.clear();
+        self.threads.drain(..).for_each(|thread| thread.join().unwrap());
+    }
 }
 
+impl<R> Completion<R> {
+    /// Results in the order the jobs complete. Panics of jobs are resumed here.
+    fn wait(mut self) -> Vec<R> {
+        let mut results = Vec::with_capacity(self.pending);
+        while self.pending > 0 {
+            // Jobs always send their results, so the channel is not closed before.
+            let result = self.receiver.recv().unwrap();
+            self.pending -= 1;
+            match result {
+                Ok(result) => results.push(result),
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+        results
+    }
+}
 
-impl<I, D> Drop for CommandPool<I, D> where
-    I: Borrow<Instance>,
-    D: Borrow<Device<I>>,
-{
+impl<R> Drop for Completion<R> {
     fn drop(&mut self) {
-        unsafe { self.device.borrow().handle.destroy_command_pool(self.handle, None); }
+        while self.pending > 0 && self.receiver.recv().is_ok() {
+            self.pending -= 1;
+        }
+    }
+}
+
+impl WorkerPools {
+    /// A command pool for each of `workers`.
+    pub fn new(vulkan: &Vulkan, workers: &Arc<Workers>) -> Self {
+        let pools = (0..workers.count())
+            .map(|_| WorkerPool {
+                pool: CommandPool::new(vulkan, vk::CommandPoolCreateFlags::TRANSIENT),
+                secondaries: Vec::new(),
+                used: 0,
+            })
+            .collect();
+
+        Self { workers: workers.clone(), pools }
+    }
+
+    #[inline]
+    pub fn worker_count(&self) -> usize { self.pools.len() }
+
+    /// Reset all pools, and their command buffers are reused by following `record`.
+    /// # Safety
+    /// Command buffers recorded from these pools must not be pending, and must not be used
+    /// after this.
+    pub unsafe fn reset(&mut self, vulkan: &Vulkan) {
+        self.pools.iter_mut().for_each(|pool| {
+            pool.pool.reset(vulkan);
+            pool.used = 0;
+        });
     }
+
+    /// Record a secondary command buffer for each chunk, e.g. a range of G-Buffer draws or GUI
+    /// elements. Chunks are distributed over the worker threads in turn, and the command
+    /// buffers are returned in the order of the chunks, ready for `execute_commands`.
+    pub fn record<T, F>(
+        &mut self,
+        vulkan: &Vulkan,
+        chunks: Vec<T>,
+        usage: vk::CommandBufferUsageFlags,
+        inheritance: &Inheritance,
+        record: F,
+    ) -> Vec<CommandBuffer<Executable>> where
+        T: Send,
+        F: Fn(T, &mut CommandBuffer<Recording>) + Sync,
+    {
+        let worker_count = self.pools.len();
+        let chunk_count = chunks.len();
+
+        // Chunks of each worker with their indices.
+        let mut assigned = (0..worker_count).map(|_| Vec::new()).collect::<Vec<_>>();
+        chunks.into_iter()
+            .enumerate()
+            .for_each(|(index, chunk)| assigned[index % worker_count].push((index, chunk)));
+
+        let record = &record;
+        let inheritance = *inheritance;
+        let (sender, receiver) = mpsc::channel();
+        let mut completion = Completion { receiver, pending: 0 };
+        self.pools
+            .iter_mut()
+            .zip(assigned)
+            .zip(self.workers.senders.iter())
+            .filter(|((_, chunks), _)| !chunks.is_empty())
+            .for_each(|((pool, chunks), worker)| {
+                let sender = sender.clone();
+                let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                    let recorded = panic::catch_unwind(AssertUnwindSafe(|| {
+                        let secondaries = pool.take(vulkan, chunks.len());
+                        secondaries.into_iter()
+                            .zip(chunks)
+                            .map(|(secondary, (index, chunk))| {
+                                let mut secondary = secondary.begin_secondary(vulkan, usage, &inheritance);
+                                record(chunk, &mut secondary);
+                                (index, secondary.end())
+                            })
+                            .collect::<Vec<_>>()
+                    }));
+                    let _ = sender.send(recorded);
+                });
+                // Safety: `completion` waits for the job before the borrows end, also when
+                // this unwinds.
+                let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + '_>, Job>(job) };
+                worker.send(job).unwrap();
+                completion.pending += 1;
+            });
+
+        let mut recorded = completion.wait().into_iter().flatten().collect::<Vec<_>>();
+        recorded.sort_by_key(|(index, _)| *index);
+        debug_assert_eq!(recorded.len(), chunk_count);
+        recorded.into_iter().map(|(_, secondary)| secondary).collect()
+    }
+
+    /// # Safety
+    /// Command buffers recorded from these pools must not be pending.
+    pub unsafe fn destroy(self, vulkan: &Vulkan) {
+        self.pools.into_iter().for_each(|pool| pool.pool.destroy(vulkan));
+    }
+}
+
+impl WorkerPool {
+    /// Secondary command buffers which are not used since the last reset, allocating more if needed.
+    fn take(&mut self, vulkan: &Vulkan, count: usize) -> Vec<CommandBuffer> {
+        let end = self.used + count;
+        if end > self.secondaries.len() {
+            let more = (end - self.secondaries.len()) as u32;
+            let handles = self.pool.allocate_handles(vulkan, vk::CommandBufferLevel::SECONDARY, more);
+            self.secondaries.extend(handles);
+        }
+
+        let taken = self.secondaries[self.used..end]
+            .iter()
+            .map(|handle| CommandBuffer::from_handle(*handle, vk::CommandBufferLevel::SECONDARY))
+            .collect();
+        self.used = end;
+        taken
+    }
+}
+
+impl<T: BufferResource + ?Sized> BufferResource for Arc<T> {
+    #[inline]
+    fn buffer_handle(&self) -> vk::Buffer { (**self).buffer_handle() }
+    #[inline]
+    fn buffer_offset(&self) -> u64 { (**self).buffer_offset() }
+    #[inline]
+    fn buffer_size(&self) -> u64 { (**self).buffer_size() }
+}
+
+impl<T: ImageResource + ?Sized> ImageResource for Arc<T> {
+    #[inline]
+    fn image_handle(&self) -> vk::Image { (**self).image_handle() }
 }
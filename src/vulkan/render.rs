@@ -1,27 +1,113 @@
+/// SPIR-V which `build.rs` compiled into `OUT_DIR`, e.g. `include_spirv!("dim3", "vert")`.
+macro_rules! include_spirv {
+    ($shader:literal, $stage:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $shader, "/", $stage, ".spv"))
+    };
+}
+
+/// SPIR-V of a shader for `load` functions, which is the reloaded one with the `hot_reload`
+/// feature, e.g. `spirv!(render, "dim3", "vert")`.
+macro_rules! spirv {
+    ($render:expr, $shader:literal, $stage:literal) => {{
+        #[cfg(feature = "hot_reload")]
+        let spirv = $render.hot_reload
+            .spirv($shader, $stage)
+            .unwrap_or(include_spirv!($shader, $stage));
+        #[cfg(not(feature = "hot_reload"))]
+        let spirv = include_spirv!($shader, $stage);
+        spirv
+    }};
+}
+
+// Macros must be defined before the modules using them.
+#[macro_use]
+mod pipeline;
+mod reflect;
+mod descriptor;
+mod dim3;
+mod mesh;
+mod texture;
 mod gui_rect_2d;
+mod lighting;
+mod shadow;
+mod profiler;
+mod frame;
+#[cfg(feature = "hot_reload")]
+mod compile;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
+
+pub use dim3::{
+    Vertex,
+    SkinnedVertex,
+    Object,
+    SkinnedObject,
+    SkinnedRecorder,
+    Projection,
+    CameraUniform,
+    MAX_JOINTS,
+};
+pub use mesh::{ MeshBuffer, Recorder };
+pub use gui_rect_2d::{ GuiRect, GuiRecorder };
+pub use texture::Texture;
+pub use lighting::{ Light, LightId, ViewMatrices };
+pub use shadow::{ ViewFrustum, ShadowObject };
+pub use profiler::{ Pass, FrameTimes };
+pub use pipeline::{
+    GraphicsPipelineBuilder,
+    VertexInput,
+    VertexAttribute,
+    vertex_format_of,
+};
+pub use reflect::ReflectErr;
+pub use descriptor::{ DescriptorAllocator, DescriptorWriter, DescriptorImage };
+pub use frame::Destroy;
 
 use ash::vk;
 use ash::extensions::khr;
-use ash::{ Instance, Device };
-use ash::version::DeviceV1_0;
-use winit::Window;
+use ash::Device;
+use ash::version::{ DeviceV1_0, InstanceV1_0 };
+
+use crate::linear_algebra::{ XYZ, Mat4 };
 
 use super::{ Vulkan, PhysicalDevice };
+use super::command::{ CommandPool, CommandBuffer, Recording, Pending, WorkerPools, Workers, Inheritance };
+use super::command::{ BufferResource, ImageResource };
+use dim3::{ Cameras, Joints };
+use lighting::Lights;
+use shadow::ShadowMap;
+use profiler::Profiler;
+use frame::Frames;
+
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
 
-use std::borrow::Borrow;
-use std::ops::Index;
-use std::path::Path;
-use std::ffi::CString;
+/// Column major 4x4 matrix. `[column][row]`.
+pub type Matrix = Mat4;
 
 pub struct Render {
     swapchain: SwapchainKHR,
     render_pass: vk::RenderPass,
     framebuffers: Framebuffers,
     pipeline_cache: vk::PipelineCache,
+    cameras: Cameras,
+    joints: Joints,
+    lights: Lights,
+    shadow_map: ShadowMap,
+    descriptors: DescriptorAllocator,
+    profiler: Profiler,
+    frames: Frames,
+    /// The swapchain is recreated at the beginning of the next frame.
+    swapchain_outdated: bool,
+    #[cfg(feature = "hot_reload")]
+    hot_reload: hot_reload::HotReload,
 }
 
 pub struct Shader {
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    /// Indexed by set numbers.
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    /// Descriptors of one set of each layout.
+    descriptor_pool_sizes: Vec<Vec<vk::DescriptorPoolSize>>,
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
 }
@@ -30,11 +116,8 @@ struct SwapchainKHR {
     loader: khr::Swapchain,
     handle: vk::SwapchainKHR,
     images: Vec<vk::Image>,
-    min_image_count: u32,
     format: vk::Format,
-    color_space: vk::ColorSpaceKHR,
     extent: vk::Extent2D,
-    present_mode: vk::PresentModeKHR,
 }
 
 struct Framebuffers {
@@ -42,31 +125,627 @@ struct Framebuffers {
     memory: vk::DeviceMemory,
     images: Vec<[vk::Image; 4]>,
     views: Vec<[vk::ImageView; 5]>,
+    normal_format: vk::Format,
 }
 
+/// Host visible and coherent buffer which is divided into a region for each framebuffer,
+/// so that it can be written every frame without flushing.
+struct HostBuffer {
+    handle: vk::Buffer,
+    memory: vk::DeviceMemory,
+    mapped: *mut u8,
+    region_size: u64,
+}
+
+// The mapping is only written through `region`, which is unsafe and leaves synchronization to
+// callers, so sharing the pointer itself is harmless.
+unsafe impl Send for HostBuffer {}
+unsafe impl Sync for HostBuffer {}
+
 impl Render {
+    /// Frames are recorded with a worker thread for each core but one.
     pub fn new(vulkan: &Vulkan) -> Self {
-        let swapchain = unsafe { Self::create_swapchain(vulkan) };
-        let render_pass = Self::create_render_pass(&vulkan.device);
+        Self::with_worker_count(vulkan, Self::default_worker_count())
+    }
+
+    /// `worker_count` threads record secondary command buffers of each frame, e.g. by
+    /// `execute_g_buffer`.
+    pub fn with_worker_count(vulkan: &Vulkan, worker_count: usize) -> Self {
+        assert!(worker_count > 0);
+        let swapchain = unsafe { Self::create_swapchain(vulkan, vk::SwapchainKHR::null()) };
+        let normal_format = Self::normal_g_buffer_format(vulkan);
+        let render_pass = Self::create_render_pass(&vulkan.device, swapchain.format, normal_format);
         let framebuffers = Self::create_framebuffers(
             &vulkan.device,
             &vulkan.physical_device,
             &swapchain,
             render_pass,
+            normal_format,
         );
         let pipeline_cache = Self::create_pipeline_cache(vulkan);
+        let cameras = unsafe { Cameras::new(vulkan, framebuffers.handles.len()) };
+        let joints = unsafe { Joints::new(vulkan, framebuffers.handles.len()) };
+        let lights = unsafe { Lights::new(vulkan, framebuffers.handles.len()) };
+        let shadow_map = unsafe { ShadowMap::new(vulkan, framebuffers.handles.len()) };
+        let descriptors = DescriptorAllocator::new(framebuffers.handles.len());
+        let profiler = unsafe { Profiler::new(vulkan, framebuffers.handles.len()) };
+        let frames = unsafe { Frames::new(vulkan, framebuffers.handles.len(), worker_count) };
+
+        vulkan.set_object_name(render_pass, "main render pass");
+        vulkan.set_object_name(pipeline_cache, "pipeline cache");
+        Self::name_framebuffers(vulkan, &framebuffers, &swapchain);
 
         Self {
             swapchain,
             render_pass,
             framebuffers,
             pipeline_cache,
+            cameras,
+            joints,
+            lights,
+            shadow_map,
+            descriptors,
+            profiler,
+            frames,
+            swapchain_outdated: false,
+            #[cfg(feature = "hot_reload")]
+            hot_reload: hot_reload::HotReload::new(),
+        }
+    }
+
+    /// Load the pipeline of the G-Buffer subpass and bind the camera uniform buffers to it.
+    /// # Safety
+    /// The camera descriptor sets are reallocated, so no pending frame may use them.
+    pub unsafe fn load_dim3(
+        &mut self,
+        vulkan: &Vulkan,
+        projection: Projection,
+    ) -> Result<Shader, ReflectErr> {
+        let shader = dim3::load(vulkan, self, Self::G_BUFFER_SUBPASS, projection)?;
+        self.cameras.write_descriptor_sets(
+            vulkan,
+            self.framebuffers.handles.len(),
+            shader.descriptor_set_layouts[0],
+        );
+        Ok(shader)
+    }
+
+    /// Load the skinned pipeline of the G-Buffer subpass and bind the camera uniform buffers
+    /// and the joint storage buffers to it. The camera set is compatible with dim3.
+    /// # Safety
+    /// The camera and joint descriptor sets are reallocated, so no pending frame may use them.
+    pub unsafe fn load_dim3_skinned(
+        &mut self,
+        vulkan: &Vulkan,
+        projection: Projection,
+    ) -> Result<Shader, ReflectErr> {
+        let shader = dim3::load_skinned(vulkan, self, Self::G_BUFFER_SUBPASS, projection)?;
+        self.cameras.write_descriptor_sets(
+            vulkan,
+            self.framebuffers.handles.len(),
+            shader.descriptor_set_layouts[0],
+        );
+        self.joints.write_descriptor_sets(
+            vulkan,
+            self.framebuffers.handles.len(),
+            shader.descriptor_set_layouts[1],
+        );
+        Ok(shader)
+    }
+
+    /// Load the textured pipeline of the G-Buffer subpass and bind the camera uniform buffers
+    /// to it. The camera set is compatible with dim3, so either pipeline can use it.
+    /// Vertices must begin with a position, a normal, a tangent and texture coordinates,
+    /// e.g. `graphics::Vertex`, and materials are set 1 of the shader.
+    /// # Safety
+    /// The camera descriptor sets are reallocated, so no pending frame may use them.
+    pub unsafe fn load_mesh<V: VertexInput>(
+        &mut self,
+        vulkan: &Vulkan,
+        projection: Projection,
+    ) -> Result<Shader, ReflectErr> {
+        let shader = mesh::load::<V>(vulkan, self, Self::G_BUFFER_SUBPASS, projection)?;
+        self.cameras.write_descriptor_sets(
+            vulkan,
+            self.framebuffers.handles.len(),
+            shader.descriptor_set_layouts[0],
+        );
+        Ok(shader)
+    }
+
+    /// Load the pipeline of the lighting subpass and bind G-Buffers, lights and the shadow map
+    /// to it.
+    /// # Safety
+    /// The lighting descriptor sets are reallocated, so no pending frame may use them.
+    pub unsafe fn load_lighting(&mut self, vulkan: &Vulkan) -> Result<Shader, ReflectErr> {
+        let shader = lighting::load(vulkan, self, Self::LIGHTING_SUBPASS)?;
+        self.lights.write_descriptor_sets(
+            vulkan,
+            &self.framebuffers,
+            &self.shadow_map,
+            shader.descriptor_set_layouts[0],
+        );
+        Ok(shader)
+    }
+
+    /// Load the pipeline of textured rectangles in the GUI subpass, whose textures are set 0
+    /// of the shader.
+    /// # Safety
+    /// `vulkan` must be the one the render was made with.
+    pub unsafe fn load_gui_rect(&self, vulkan: &Vulkan) -> Result<Shader, ReflectErr> {
+        gui_rect_2d::load(vulkan, self, Self::GUI_SUBPASS)
+    }
+
+    /// Load the depth only pipeline of the shadow pass.
+    /// Vertices must begin with a position of `XYZ<f32>`.
+    /// # Safety
+    /// `vulkan` must be the one the render was made with.
+    #[inline]
+    pub unsafe fn load_shadow<V: VertexInput>(&self, vulkan: &Vulkan) -> Result<Shader, ReflectErr> {
+        shadow::load::<V>(vulkan, self)
+    }
+
+    /// Recompile shader sources modified since the last call, and return names of shaders,
+    /// e.g. "dim3", which have new SPIR-V. Call this between frames and `reload_shader` for
+    /// each of them.
+    #[cfg(feature = "hot_reload")]
+    pub fn poll_shaders(&mut self) -> Vec<String> {
+        self.hot_reload.poll()
+    }
+
+    /// Replace `shader` by the one `load` creates from the latest SPIR-V.
+    /// If it fails, the error is printed and `shader` is kept.
+    ///
+    /// This waits until the device becomes idle before `load`, because loading rewrites
+    /// descriptor sets of the render which frames in flight use, e.g. the ones of lights.
+    /// The old pipeline is retired.
+    /// ```ignore
+    /// if render.poll_shaders().iter().any(|name| name == "dim3") {
+    ///     render.reload_shader(vulkan, &mut dim3, |render| unsafe {
+    ///         render.load_dim3(vulkan, Projection::Perspective)
+    ///     });
+    /// }
+    /// ```
+    /// # Safety
+    /// `vulkan` must be the one the render was made with. Call this between frames.
+    #[cfg(feature = "hot_reload")]
+    pub unsafe fn reload_shader<F>(&mut self, vulkan: &Vulkan, shader: &mut Shader, load: F)
+        where F: FnOnce(&mut Self) -> Result<Shader, ReflectErr>
+    {
+        vulkan.device.device_wait_idle().unwrap();
+        match load(self) {
+            Ok(new) => {
+                let old = std::mem::replace(shader, new);
+                self.retire(Arc::new(old));
+            }
+            Err(err) => eprintln!("\u{001b}[31mShader Reload Error:\u{001b}[m {:?}", err),
+        }
+    }
+
+    /// Record setting the viewport and scissor to the whole swapchain image.
+    /// Every pipeline has them as dynamic states, so this is needed after binding a pipeline
+    /// in the main render pass.
+    #[inline]
+    pub fn set_full_viewport(&self, command_buffer: &mut CommandBuffer<Recording>) {
+        Self::set_viewport(command_buffer, self.swapchain.extent);
+    }
+
+    /// Returns `None` if the light buffer is full.
+    #[inline]
+    pub fn add_light(&mut self, light: Light) -> Option<LightId> { self.lights.add(light) }
+    /// Returns `false` if the light has been removed.
+    #[inline]
+    pub fn update_light(&mut self, id: LightId, light: Light) -> bool { self.lights.update(id, light) }
+    #[inline]
+    pub fn remove_light(&mut self, id: LightId) -> Option<Light> { self.lights.remove(id) }
+    #[inline]
+    pub fn light(&self, id: LightId) -> Option<&Light> { self.lights.get(id) }
+    #[inline]
+    pub fn set_ambient_light(&mut self, color: XYZ<f32>) { self.lights.set_ambient(color) }
+    /// Set the directional light which casts shadows.
+    /// Returns `false` and keeps the current sun if the light has been removed.
+    #[inline]
+    pub fn set_sun(&mut self, id: Option<LightId>) -> bool { self.lights.set_sun(id) }
+
+    /// Acquire a swapchain image and begin the primary command buffer of its frame, after the
+    /// previous frame of the same framebuffer completes. Resources retired up to that frame
+    /// are destroyed here, and its times are collected into `frame_times`. Returns the index
+    /// of the framebuffer with the command buffer, which is given back to `end_frame`.
+    ///
+    /// The swapchain and the framebuffers are recreated first if they no longer match the
+    /// window. Returns `None` while the window has no area, e.g. minimized.
+    /// # Safety
+    /// Commands recorded into the frame must refer to resources living until it completes,
+    /// e.g. by `retire`.
+    pub unsafe fn begin_frame<'a>(&mut self, vulkan: &'a Vulkan) -> Option<(usize, CommandBuffer<Recording<'a>>)> {
+        loop {
+            if self.swapchain_outdated && !self.recreate_swapchain(vulkan) {
+                return None;
+            }
+            match self.frames.begin(vulkan, &self.swapchain) {
+                Some((framebuffer_index, mut command_buffer)) => {
+                    self.profiler.begin_frame(vulkan, &mut command_buffer, framebuffer_index);
+                    return Some((framebuffer_index, command_buffer));
+                }
+                None => self.swapchain_outdated = true,
+            }
+        }
+    }
+
+    /// Submit the frame begun by `begin_frame` and present its swapchain image.
+    /// # Safety
+    /// The queue must not be used by other threads at the same time.
+    pub unsafe fn end_frame(
+        &mut self,
+        vulkan: &Vulkan,
+        framebuffer_index: usize,
+        command_buffer: CommandBuffer<Recording>,
+    ) {
+        if self.frames.end(vulkan, &self.swapchain, framebuffer_index, command_buffer) {
+            self.swapchain_outdated = true;
+        }
+    }
+
+    /// Recreate the swapchain and the framebuffers before the next frame, e.g. when the window
+    /// is resized. Presentation also does this when the swapchain no longer matches the window.
+    #[inline]
+    pub fn invalidate_swapchain(&mut self) { self.swapchain_outdated = true; }
+
+    /// Destroy the resource once the frame being recorded, and every frame before it, has
+    /// completed and the last clone of `resource` is dropped, e.g. by a command buffer keeping
+    /// it. Resources are retired instead of destroyed while frames in flight may use them.
+    #[inline]
+    pub fn retire<T: Destroy + 'static>(&mut self, resource: Arc<T>) { self.frames.retire(resource) }
+
+    /// Record the beginning of the main render pass into the framebuffer, whose first subpass
+    /// writes the G-Buffers.
+    pub fn begin_render_pass(
+        &self,
+        command_buffer: &mut CommandBuffer<Recording>,
+        framebuffer_index: usize,
+        contents: vk::SubpassContents,
+    ) {
+        // In the order of attachments.
+        let color = |float32| vk::ClearValue { color: vk::ClearColorValue { float32 } };
+        let clear_values = [
+            color([0.0, 0.0, 0.0, 1.0]),
+            vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+            color([0.0; 4]),
+            color([0.0; 4]),
+            color([0.0; 4]),
+        ];
+
+        command_buffer.begin_render_pass(
+            self.render_pass,
+            self.framebuffers.handles[framebuffer_index],
+            vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: self.swapchain.extent },
+            &clear_values[..],
+            contents,
+        );
+    }
+
+    /// Record moving to the lighting subpass and shading the G-Buffers with the pipeline of
+    /// `load_lighting`. The G-Buffer and lighting subpasses are timed here.
+    pub fn record_lighting(
+        &self,
+        command_buffer: &mut CommandBuffer<Recording>,
+        framebuffer_index: usize,
+        shader: &Shader,
+    ) {
+        command_buffer
+            .next_subpass(vk::SubpassContents::INLINE)
+            .bind_pipeline(vk::PipelineBindPoint::GRAPHICS, shader.pipeline)
+            .bind_descriptor_sets(
+                vk::PipelineBindPoint::GRAPHICS,
+                shader.pipeline_layout,
+                0,
+                &[self.lighting_descriptor_set(framebuffer_index)],
+                &[],
+            );
+        // The G-Buffer subpass may be executed by secondary command buffers, where no
+        // timestamp can be written.
+        self.profiler.end_pass(command_buffer, Pass::GBuffer);
+        self.set_full_viewport(command_buffer);
+        // A quad covering the screen as a triangle strip.
+        command_buffer.draw(4, 1, 0, 0);
+        self.profiler.end_pass(command_buffer, Pass::Lighting);
+    }
+
+    /// Record the G-Buffer subpass with secondary command buffers, which the worker threads of
+    /// the frame record in parallel, one for each chunk. `record` draws a chunk with a
+    /// `Recorder` begun with `shader` of `load_mesh`. The render pass must be begun with
+    /// `vk::SubpassContents::SECONDARY_COMMAND_BUFFERS`.
+    pub fn execute_g_buffer<T, F>(
+        &mut self,
+        vulkan: &Vulkan,
+        command_buffer: &mut CommandBuffer<Recording>,
+        framebuffer_index: usize,
+        shader: &Shader,
+        chunks: Vec<T>,
+        record: F,
+    ) where
+        T: Send,
+        F: Fn(T, &mut Recorder) + Sync,
+    {
+        let camera = self.dim3_descriptor_set(framebuffer_index);
+        let extent = self.swapchain.extent;
+        let inheritance = self.inheritance(framebuffer_index, Self::G_BUFFER_SUBPASS);
+
+        let secondaries = self.frames.workers(framebuffer_index).record(
+            vulkan,
+            chunks,
+            vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            &inheritance,
+            |chunk, secondary| record(chunk, &mut Recorder::with_camera(secondary, shader, camera, extent)),
+        );
+        command_buffer.execute_commands(secondaries);
+    }
+
+    /// Record more secondary command buffers of the G-Buffer subpass like `execute_g_buffer`,
+    /// which draw skinned meshes. `record` draws a chunk with a `SkinnedRecorder` begun with
+    /// `shader` of `load_dim3_skinned`, after the joint matrices are uploaded.
+    pub fn execute_skinned<T, F>(
+        &mut self,
+        vulkan: &Vulkan,
+        command_buffer: &mut CommandBuffer<Recording>,
+        framebuffer_index: usize,
+        shader: &Shader,
+        chunks: Vec<T>,
+        record: F,
+    ) where
+        T: Send,
+        F: Fn(T, &mut SkinnedRecorder) + Sync,
+    {
+        let camera = self.dim3_descriptor_set(framebuffer_index);
+        let joints = self.joints_descriptor_set(framebuffer_index);
+        let extent = self.swapchain.extent;
+        let inheritance = self.inheritance(framebuffer_index, Self::G_BUFFER_SUBPASS);
+
+        let secondaries = self.frames.workers(framebuffer_index).record(
+            vulkan,
+            chunks,
+            vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            &inheritance,
+            |chunk, secondary| {
+                record(chunk, &mut SkinnedRecorder::begin(secondary, shader, camera, joints, extent))
+            },
+        );
+        command_buffer.execute_commands(secondaries);
+    }
+
+    /// Record moving to the GUI subpass and drawing it with secondary command buffers like
+    /// `execute_g_buffer`, then end the render pass, which finishes timing the frame. `record`
+    /// draws a chunk with a `GuiRecorder` begun with `shader` of `load_gui_rect`.
+    pub fn execute_gui<T, F>(
+        &mut self,
+        vulkan: &Vulkan,
+        command_buffer: &mut CommandBuffer<Recording>,
+        framebuffer_index: usize,
+        shader: &Shader,
+        chunks: Vec<T>,
+        record: F,
+    ) where
+        T: Send,
+        F: Fn(T, &mut GuiRecorder) + Sync,
+    {
+        let extent = self.swapchain.extent;
+        let inheritance = self.inheritance(framebuffer_index, Self::GUI_SUBPASS);
+
+        command_buffer.next_subpass(vk::SubpassContents::SECONDARY_COMMAND_BUFFERS);
+        let secondaries = self.frames.workers(framebuffer_index).record(
+            vulkan,
+            chunks,
+            vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            &inheritance,
+            |chunk, secondary| record(chunk, &mut GuiRecorder::begin(secondary, shader, extent)),
+        );
+        command_buffer
+            .execute_commands(secondaries)
+            .end_render_pass();
+        self.profiler.end_pass(command_buffer, Pass::Gui);
+    }
+
+    /// Write the camera for the frame rendered into the framebuffer.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub unsafe fn upload_camera(&self, framebuffer_index: usize, camera: &CameraUniform) {
+        self.cameras.upload(framebuffer_index, camera);
+    }
+
+    /// Append joint matrices of a skinned object, e.g. `Skeleton::joint_matrices`, for the
+    /// frame rendered into the framebuffer. Returns the offset for `SkinnedObject`, or `None`
+    /// if there are already too many matrices of `MAX_JOINTS` in the frame.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub unsafe fn upload_joints(&mut self, framebuffer_index: usize, matrices: &[Matrix]) -> Option<u32> {
+        self.joints.upload(framebuffer_index, matrices)
+    }
+
+    /// Forget the joint matrices of the framebuffer. Call it before the first `upload_joints`
+    /// of each frame.
+    #[inline]
+    pub fn clear_joints(&mut self, framebuffer_index: usize) { self.joints.clear(framebuffer_index) }
+
+    /// Aspect ratio (width / height) of the swapchain images.
+    #[inline]
+    pub fn aspect(&self) -> f32 {
+        self.swapchain.extent.width as f32 / self.swapchain.extent.height as f32
+    }
+
+    /// Write the current lights and the camera for the frame rendered into the framebuffer.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub unsafe fn upload_lights(&self, framebuffer_index: usize, matrices: &ViewMatrices) {
+        self.lights.upload(framebuffer_index, matrices, self.swapchain.extent);
+    }
+
+    /// Fit the shadow cascades of the sun to the view frustum of the frame rendered into
+    /// the framebuffer. Nothing is done if there is no sun.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub unsafe fn update_shadow(&mut self, framebuffer_index: usize, frustum: &ViewFrustum) {
+        if let Some(sun_direction) = self.lights.sun_direction() {
+            self.shadow_map.update(framebuffer_index, frustum, sun_direction);
         }
     }
 
+    /// Record the shadow pass, which is timed in `frame_times`. It must be recorded before the
+    /// main render pass. See `ShadowMap::record`.
+    pub fn record_shadow<F>(
+        &self,
+        command_buffer: &mut CommandBuffer<Recording>,
+        shader: &Shader,
+        draw: F,
+    ) where F: FnMut(&mut CommandBuffer<Recording>, usize, &Matrix) {
+        self.shadow_map.record(command_buffer, shader, draw);
+        self.profiler.end_pass(command_buffer, Pass::Shadow);
+    }
+
+    /// Descriptor set of the camera for the G-Buffer subpass.
+    #[inline]
+    pub fn dim3_descriptor_set(&self, framebuffer_index: usize) -> vk::DescriptorSet {
+        self.cameras.descriptor_set(framebuffer_index)
+    }
+
+    /// Descriptor set of joint matrices, set 1 of `load_dim3_skinned`.
+    #[inline]
+    pub fn joints_descriptor_set(&self, framebuffer_index: usize) -> vk::DescriptorSet {
+        self.joints.descriptor_set(framebuffer_index)
+    }
+
+    /// Descriptor set of G-Buffers and lights for the lighting subpass.
+    #[inline]
+    pub fn lighting_descriptor_set(&self, framebuffer_index: usize) -> vk::DescriptorSet {
+        self.lights.descriptor_set(framebuffer_index)
+    }
+
+    /// Allocate a descriptor set for the `set` number of the shader, e.g. for a material.
+    /// It lives until `free_descriptor_set` or until the render is destroyed.
+    /// # Safety
+    /// The shader must be alive, and `set` must be one of its sets.
+    pub unsafe fn allocate_descriptor_set(
+        &mut self,
+        vulkan: &Vulkan,
+        shader: &Shader,
+        set: u32,
+    ) -> vk::DescriptorSet {
+        self.descriptors.allocate(vulkan, shader, set)
+    }
+
+    /// Free a descriptor set of `allocate_descriptor_set` after frames using it complete, so
+    /// that it can be allocated again.
+    /// # Safety
+    /// The set must not be used after this.
+    pub unsafe fn free_descriptor_set(&mut self, set: vk::DescriptorSet) {
+        let freed = self.descriptors.free(set);
+        self.retire(Arc::new(freed));
+    }
+
+    /// Allocate a descriptor set which lives until `reset_transient_descriptor_sets` of the
+    /// framebuffer, e.g. for GUI elements which change every frame.
+    /// # Safety
+    /// The shader must be alive, and `set` must be one of its sets.
+    pub unsafe fn allocate_transient_descriptor_set(
+        &mut self,
+        vulkan: &Vulkan,
+        framebuffer_index: usize,
+        shader: &Shader,
+        set: u32,
+    ) -> vk::DescriptorSet {
+        self.descriptors.allocate_transient(vulkan, framebuffer_index, shader, set)
+    }
+
+    /// Free the transient descriptor sets of the framebuffer before recording its frame.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub unsafe fn reset_transient_descriptor_sets(&mut self, vulkan: &Vulkan, framebuffer_index: usize) {
+        self.descriptors.reset_frame(vulkan, framebuffer_index);
+    }
+
+    /// Rolling averages of the last frames, which can be shown as an overlay.
+    #[inline]
+    pub fn frame_times(&self) -> FrameTimes { self.profiler.averages() }
+
+    /// Dump the times of the last frames as CSV.
+    pub fn write_frame_times_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.profiler.write_csv(path.as_ref())
+    }
+
+    /// Open a labeled region for the pass, e.g. around a subpass, named same as profiles.
+    #[inline]
+    pub fn begin_pass_label(&self, command_buffer: &mut CommandBuffer<Recording>, pass: Pass) {
+        command_buffer.begin_label(pass.name());
+    }
+
+    #[inline]
+    pub fn end_pass_label(&self, command_buffer: &mut CommandBuffer<Recording>) {
+        command_buffer.end_label();
+    }
+
+    /// Recreate the swapchain for the current size of the window, and the framebuffers of its
+    /// images. The lighting descriptor sets are rewritten with the new G-Buffers.
+    /// Returns `false` and keeps everything while the window has no area.
+    unsafe fn recreate_swapchain(&mut self, vulkan: &Vulkan) -> bool {
+        let device = &vulkan.device;
+        let capabilities = vulkan.surface.loader
+            .get_physical_device_surface_capabilities(
+                vulkan.physical_device.handle,
+                vulkan.surface.handle,
+            )
+            .unwrap();
+        let extent = Self::surface_extent(vulkan, &capabilities);
+        if extent.width == 0 || extent.height == 0 {
+            return false;
+        }
+
+        // Frames in flight use the framebuffers.
+        device.device_wait_idle().unwrap();
+
+        let swapchain = Self::create_swapchain(vulkan, self.swapchain.handle);
+        let old = std::mem::replace(&mut self.swapchain, swapchain);
+        // Everything sized by the number of framebuffers, e.g. frames, is kept.
+        assert_eq!(self.swapchain.images.len(), old.images.len(), "the number of swapchain images changed");
+        assert_eq!(self.swapchain.format, old.format, "the format of the swapchain changed");
+
+        let framebuffers = Self::create_framebuffers(
+            device,
+            &vulkan.physical_device,
+            &self.swapchain,
+            self.render_pass,
+            self.framebuffers.normal_format,
+        );
+        std::mem::replace(&mut self.framebuffers, framebuffers).destroy(device);
+        old.loader.destroy_swapchain(old.handle, None);
+
+        Self::name_framebuffers(vulkan, &self.framebuffers, &self.swapchain);
+        self.lights.write_input_attachments(vulkan, &self.framebuffers);
+        self.swapchain_outdated = false;
+        true
+    }
+
+    /// The current extent is u32::MAX if the swapchain decides the size of the surface, and
+    /// then it is the size of the window.
+    fn surface_extent(vulkan: &Vulkan, capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+        if capabilities.current_extent.width != u32::MAX {
+            return capabilities.current_extent;
+        }
+
+        let window = &vulkan.surface.window;
+        let size = window.get_inner_size().unwrap().to_physical(window.get_hidpi_factor());
+        vk::Extent2D {
+            width: (size.width as u32)
+                .max(capabilities.min_image_extent.width)
+                .min(capabilities.max_image_extent.width),
+            height: (size.height as u32)
+                .max(capabilities.min_image_extent.height)
+                .min(capabilities.max_image_extent.height),
+        }
+    }
+
+    /// `old_swapchain` is null, or the swapchain which this replaces.
     /// # Safety
     /// Ensure the device has swapchain extension.
-    unsafe fn create_swapchain(vulkan: &Vulkan) -> SwapchainKHR {
+    unsafe fn create_swapchain(vulkan: &Vulkan, old_swapchain: vk::SwapchainKHR) -> SwapchainKHR {
         let loader = khr::Swapchain::new(&vulkan.instance, &vulkan.device);
 
         // evaluate minimum image count.
@@ -92,10 +771,10 @@ impl Render {
             .unwrap();
         let &vk::SurfaceFormatKHR { format, color_space } = supported_surface_format.iter()
             .find(|format| format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
-            .unwrap_or(supported_surface_format.iter().next().unwrap());
+            .unwrap_or(&supported_surface_format[0]);
 
         // evaluate extent.
-        let extent = capabilities.current_extent;
+        let extent = Self::surface_extent(vulkan, &capabilities);
 
         // evaluate present mode.
         let supported_present_modes = vulkan.surface.loader
@@ -110,7 +789,7 @@ impl Render {
                 supported_present_modes.iter()
                     .find(|mode| **mode == vk::PresentModeKHR::FIFO)
             })
-            .unwrap_or(supported_present_modes.iter().next().unwrap());
+            .unwrap_or(&supported_present_modes[0]);
 
         // create vk::SwapchainKHR.
         let info = vk::SwapchainCreateInfoKHR::builder()
@@ -119,15 +798,16 @@ impl Render {
             .min_image_count(min_image_count)
             .image_format(format)
             .image_color_space(color_space)
-            .image_extent(unimplemented!())
+            .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&[])
             .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(unimplemented!())
-            .clipped(true);
+            .present_mode(present_mode)
+            .clipped(true)
+            .old_swapchain(old_swapchain);
 
         let handle = loader.create_swapchain(&info, None).unwrap();
 
@@ -137,11 +817,8 @@ impl Render {
             loader,
             handle,
             images,
-            min_image_count,
             format,
-            color_space,
             extent,
-            present_mode,
         }
     }
 
@@ -150,15 +827,52 @@ impl Render {
     const SAMPLE_COUNT: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_1;
     const SWAPCHAIN_ATTACHMENT_INDEX: u32 = 0;
     const DEPTH_ATTACHMENT_INDEX: u32 = 1;
-    const POSITION_G_BUFFER_ATTACHMENT_INDEX: u32 = 2;
-    const NORMAL_G_BUFFER_ATTACHMENT_INDEX: u32 = 3;
-    const COLOR_G_BUFFER_ATTACHMENT_INDEX: u32 = 4;
+    const NORMAL_G_BUFFER_ATTACHMENT_INDEX: u32 = 2;
+    const ALBEDO_G_BUFFER_ATTACHMENT_INDEX: u32 = 3;
+    const MATERIAL_G_BUFFER_ATTACHMENT_INDEX: u32 = 4;
+
+    // Formats of G-Buffers. 16 bytes per pixel in total with depth.
+    // Positions are reconstructed from depth in the lighting subpass.
+    const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+    /// Octahedral-encoded normal vectors in view coordinates, in the first format which the
+    /// device can render to. Every device can render to R16G16_SFLOAT.
+    const NORMAL_G_BUFFER_FORMATS: [vk::Format; 2] = [
+        vk::Format::R16G16_SNORM,
+        vk::Format::R16G16_SFLOAT,
+    ];
+    /// rgb: albedo, a: unused.
+    const ALBEDO_G_BUFFER_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+    /// r: roughness, g: metallic, b: material id, a: unused.
+    const MATERIAL_G_BUFFER_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    // These are indices of subpasses.
+    const G_BUFFER_SUBPASS: u32 = 0;
+    const LIGHTING_SUBPASS: u32 = 1;
+    const GUI_SUBPASS: u32 = 2;
+
+    fn normal_g_buffer_format(vulkan: &Vulkan) -> vk::Format {
+        Self::NORMAL_G_BUFFER_FORMATS
+            .iter()
+            .copied()
+            .find(|&format| {
+                let properties = unsafe {
+                    vulkan.instance
+                        .get_physical_device_format_properties(vulkan.physical_device.handle, format)
+                };
+                properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT)
+            })
+            .unwrap()
+    }
 
-    fn create_render_pass(device: &Device) -> vk::RenderPass {
+    fn create_render_pass(
+        device: &Device,
+        swapchain_format: vk::Format,
+        normal_format: vk::Format,
+    ) -> vk::RenderPass {
         let attachments = [
             // To present on surface.
             vk::AttachmentDescription::builder()
-                .format(unimplemented!("This must be the swapchain format."))
+                .format(swapchain_format)
                 .samples(vk::SampleCountFlags::TYPE_1)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
                 .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
@@ -168,48 +882,48 @@ impl Render {
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .build(),
 
-            // Depth image.
+            // Depth image. Also read in the lighting subpass to reconstruct positions.
             vk::AttachmentDescription::builder()
-                .format(vk::Format::D24_UNORM_S8_UINT)
+                .format(Self::DEPTH_FORMAT)
                 .samples(Self::SAMPLE_COUNT)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .build(),
 
-            // This is what is called G-Buffer. Storing positions of vertex.
+            // This is what is called G-Buffer. Storing normal vectors of vertex.
             vk::AttachmentDescription::builder()
-                .format(vk::Format::R32G32B32_SFLOAT)
+                .format(normal_format)
                 .samples(Self::SAMPLE_COUNT)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) // TODO: Is this valid?
+                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .build(),
 
-            // This is what is called G-Buffer. Storing normal vectors of vertex.
+            // This is what is called G-Buffer. Storing albedo of vertex.
             vk::AttachmentDescription::builder()
-                .format(vk::Format::R32G32B32_SFLOAT)
+                .format(Self::ALBEDO_G_BUFFER_FORMAT)
                 .samples(Self::SAMPLE_COUNT)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) // TODO: Is this valid?
+                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .build(),
 
-            // This is what is called G-Buffer. Storing color of vertex.
+            // This is what is called G-Buffer. Storing roughness, metallic and material id.
             vk::AttachmentDescription::builder()
-                .format(vk::Format::R32G32B32_SFLOAT)
+                .format(Self::MATERIAL_G_BUFFER_FORMAT)
                 .samples(Self::SAMPLE_COUNT)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) // TODO: Is this valid?
+                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -222,15 +936,15 @@ impl Render {
         // Render objects in G-Buffers.
         let first_subpass_color_attachments = [
             vk::AttachmentReference::builder()
-                .attachment(Self::POSITION_G_BUFFER_ATTACHMENT_INDEX)
+                .attachment(Self::NORMAL_G_BUFFER_ATTACHMENT_INDEX)
                 .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                 .build(),
             vk::AttachmentReference::builder()
-                .attachment(Self::NORMAL_G_BUFFER_ATTACHMENT_INDEX)
+                .attachment(Self::ALBEDO_G_BUFFER_ATTACHMENT_INDEX)
                 .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                 .build(),
             vk::AttachmentReference::builder()
-                .attachment(Self::COLOR_G_BUFFER_ATTACHMENT_INDEX)
+                .attachment(Self::MATERIAL_G_BUFFER_ATTACHMENT_INDEX)
                 .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                 .build(),
         ];
@@ -248,18 +962,22 @@ impl Render {
                 .build(),
         ];
 
-        // G-Buffers.
+        // Depth and G-Buffers.
         let second_subpass_input_attachments = [
             vk::AttachmentReference::builder()
-                .attachment(Self::POSITION_G_BUFFER_ATTACHMENT_INDEX)
-                .layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .attachment(Self::DEPTH_ATTACHMENT_INDEX)
+                .layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
                 .build(),
             vk::AttachmentReference::builder()
                 .attachment(Self::NORMAL_G_BUFFER_ATTACHMENT_INDEX)
                 .layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .build(),
             vk::AttachmentReference::builder()
-                .attachment(Self::COLOR_G_BUFFER_ATTACHMENT_INDEX)
+                .attachment(Self::ALBEDO_G_BUFFER_ATTACHMENT_INDEX)
+                .layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build(),
+            vk::AttachmentReference::builder()
+                .attachment(Self::MATERIAL_G_BUFFER_ATTACHMENT_INDEX)
                 .layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .build(),
         ];
@@ -297,40 +1015,71 @@ impl Render {
         // Sync 3 subpasses and swapchain image layout transition
         // (ColorAttachmentOptimal -> PresentSrcKHR) happened in second subpass.
         let subass_dependencies = [
+            // The previous frame of the framebuffer must finish with depth and G-Buffers.
             vk::SubpassDependency::builder()
                 .src_subpass(vk::SUBPASS_EXTERNAL)
-                .dst_subpass(0)
-                .src_stage_mask(unimplemented!())
-                .dst_stage_mask(unimplemented!())
-                .src_access_mask(unimplemented!())
-                .dst_access_mask(unimplemented!())
+                .dst_subpass(Self::G_BUFFER_SUBPASS)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                )
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                )
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                )
                 .dependency_flags(vk::DependencyFlags::BY_REGION)
                 .build(),
+            // The swapchain image is first used in the lighting subpass. Its layout transition
+            // waits for the acquire semaphore, which is waited at COLOR_ATTACHMENT_OUTPUT.
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(Self::LIGHTING_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .build(),
+            // Depth and G-Buffers are written in the first subpass and read as input attachments
+            // in the lighting subpass.
             vk::SubpassDependency::builder()
-                .src_subpass(0)
-                .dst_subpass(1)
-                .src_stage_mask(unimplemented!())
-                .dst_stage_mask(unimplemented!())
-                .src_access_mask(unimplemented!())
-                .dst_access_mask(unimplemented!())
+                .src_subpass(Self::G_BUFFER_SUBPASS)
+                .dst_subpass(Self::LIGHTING_SUBPASS)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                )
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                )
+                .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
                 .dependency_flags(vk::DependencyFlags::BY_REGION)
                 .build(),
             vk::SubpassDependency::builder()
-                .src_subpass(1)
-                .dst_subpass(2)
-                .src_stage_mask(unimplemented!())
-                .dst_stage_mask(unimplemented!())
-                .src_access_mask(unimplemented!())
-                .dst_access_mask(unimplemented!())
+                .src_subpass(Self::LIGHTING_SUBPASS)
+                .dst_subpass(Self::GUI_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                )
                 .dependency_flags(vk::DependencyFlags::BY_REGION)
                 .build(),
             vk::SubpassDependency::builder()
-                .src_subpass(2)
+                .src_subpass(Self::GUI_SUBPASS)
                 .dst_subpass(vk::SUBPASS_EXTERNAL)
-                .src_stage_mask(unimplemented!())
-                .dst_stage_mask(unimplemented!())
-                .src_access_mask(unimplemented!())
-                .dst_access_mask(unimplemented!())
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::empty())
                 .dependency_flags(vk::DependencyFlags::BY_REGION)
                 .build(),
         ];
@@ -340,9 +1089,7 @@ impl Render {
             .subpasses(&subpasses[..])
             .dependencies(&subass_dependencies[..]);
 
-        let render_pass = unsafe { device.create_render_pass(&render_pass_info, None).unwrap() };
-
-        render_pass
+        unsafe { device.create_render_pass(&render_pass_info, None).unwrap() }
     }
 
     fn create_framebuffers(
@@ -350,11 +1097,17 @@ impl Render {
         physical_device: &PhysicalDevice,
         swapchain: &SwapchainKHR,
         render_pass: vk::RenderPass,
+        normal_format: vk::Format,
     ) -> Framebuffers {
         // Create images. --
-        let g_buffer_position_info = vk::ImageCreateInfo::builder()
-            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT)
-            .format(vk::Format::R32G32B32_SFLOAT)
+        // These images live only in the render pass, so they can be transient.
+        let g_buffer_normal_info = vk::ImageCreateInfo::builder()
+            .usage(
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::INPUT_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+            )
+            .format(normal_format)
             .image_type(vk::ImageType::TYPE_2D)
             .extent(
                 vk::Extent3D::builder()
@@ -372,27 +1125,33 @@ impl Render {
             .queue_family_indices(&[])
             .build();
 
-        let g_buffer_normal_info = g_buffer_position_info;
+        let g_buffer_albedo_info = {
+            let mut temp = g_buffer_normal_info;
+            temp.format = Self::ALBEDO_G_BUFFER_FORMAT;
+            temp
+        };
 
-        let g_buffer_color_info = {
-            let mut temp = g_buffer_position_info;
-            temp.format = vk::Format::R8G8B8A8_UNORM;
+        let g_buffer_material_info = {
+            let mut temp = g_buffer_normal_info;
+            temp.format = Self::MATERIAL_G_BUFFER_FORMAT;
             temp
         };
 
         let depth_info = {
-            let mut temp = g_buffer_position_info.clone();
-            temp.usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
-            temp.format = vk::Format::D24_UNORM_S8_UINT;
+            let mut temp = g_buffer_normal_info;
+            temp.usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::INPUT_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+            temp.format = Self::DEPTH_FORMAT;
             temp
         };
 
         // Infos of vk::Image for one framebuffer.
         let infos_for_one_framebuffer = [
             depth_info,
-            g_buffer_position_info,
             g_buffer_normal_info,
-            g_buffer_color_info,
+            g_buffer_albedo_info,
+            g_buffer_material_info,
         ];
 
         // Create vk::Images for all framebuffers.
@@ -411,20 +1170,25 @@ impl Render {
             .collect::<Vec<_>>();
 
         // Allocate vk::DeviceMemory. --
-        // Select memory type.
+        // Select memory type. Images of all framebuffers have the same requirements.
         let mut requirements = [vk::MemoryRequirements::default(); 4];
-        unimplemented!("size maybe not enough because of alignment.");
-        let (supported_memory_types, size) = images[0]
+        let supported_memory_types = images[0]
             .iter()
             .zip(requirements.iter_mut())
-            .fold((!0, 0), |(bit_flags, size), (image, requirements)| {
+            .fold(!0, |bit_flags, (image, requirements)| {
                 *requirements = unsafe { device.get_image_memory_requirements(*image) };
-                let new_bit_flags = bit_flags & requirements.memory_type_bits;
-                let new_size = size + requirements.size;
-                (new_bit_flags, new_size)
+                bit_flags & requirements.memory_type_bits
             });
         let requirements = requirements;
 
+        // Images of all framebuffers are placed in order, each at a multiple of its alignment.
+        let align = |offset: u64, alignment: u64| offset.div_ceil(alignment) * alignment;
+        let size = images.iter().fold(0, |offset, _| {
+            requirements
+                .iter()
+                .fold(offset, |offset, requirements| align(offset, requirements.alignment) + requirements.size)
+        });
+
         let memory_type_index = physical_device.memory_types
             .iter()
             .enumerate()
@@ -444,9 +1208,9 @@ impl Render {
 
         // Bind vk::Images to vk::DeviceMemory, and then, create vk::ImageViews. --
         // Infos of vk::ImageViews for one framebuffer. (but, only image field is invalid)
-        let g_buffer_position_info = vk::ImageViewCreateInfo::builder()
+        let g_buffer_normal_info = vk::ImageViewCreateInfo::builder()
             .flags(vk::ImageViewCreateFlags::empty())
-            .format(vk::Format::R32G32B32_SFLOAT)
+            .format(normal_format)
             .view_type(vk::ImageViewType::TYPE_2D)
             .components(
                 vk::ComponentMapping::builder()
@@ -466,20 +1230,24 @@ impl Render {
                     .build()
             )
             .build();
-        let g_buffer_normal_info = g_buffer_position_info;
-        let g_buffer_color_info = {
-            let mut temp = g_buffer_position_info;
-            temp.format = vk::Format::R8G8B8A8_UNORM;
+        let g_buffer_albedo_info = {
+            let mut temp = g_buffer_normal_info;
+            temp.format = Self::ALBEDO_G_BUFFER_FORMAT;
+            temp
+        };
+        let g_buffer_material_info = {
+            let mut temp = g_buffer_normal_info;
+            temp.format = Self::MATERIAL_G_BUFFER_FORMAT;
             temp
         };
         let depth_image_view_info = {
-            let mut tmp = g_buffer_position_info;
-            tmp.format = vk::Format::D24_UNORM_S8_UINT;
+            let mut tmp = g_buffer_normal_info;
+            tmp.format = Self::DEPTH_FORMAT;
             tmp.subresource_range.aspect_mask = vk::ImageAspectFlags::DEPTH;
             tmp
         };
         let swapchain_image_view_info = {
-            let mut tmp = g_buffer_position_info;
+            let mut tmp = g_buffer_normal_info;
             tmp.format = swapchain.format;
             tmp
         };
@@ -495,15 +1263,15 @@ impl Render {
             .fold(0, |offset, ((swapchain_image, images), image_views_uninit)| {
                 // Create vk::ImageViews of swapchain.
                 // For now, the image field of the info is null, so make it a valid handle.
-                let mut info = swapchain_image_view_info.clone();
+                let mut info = swapchain_image_view_info;
                 info.image = *swapchain_image;
                 image_views_uninit[0] = unsafe { device.create_image_view(&info, None).unwrap() };
 
                 let infos = [
                     depth_image_view_info,
-                    g_buffer_position_info,
                     g_buffer_normal_info,
-                    g_buffer_color_info,
+                    g_buffer_albedo_info,
+                    g_buffer_material_info,
                 ];
 
                 images
@@ -511,17 +1279,12 @@ impl Render {
                     .zip(image_views_uninit[1..].iter_mut())
                     .zip(requirements.iter().zip(infos.iter()))
                     .fold(offset, |offset, ((image, image_view), (requirements, info))| {
-                        // Adjust alignment.
-                        let offset = if offset % requirements.alignment != 0 {
-                            (offset / requirements.alignment + 1) * requirements.alignment
-                        } else {
-                            offset
-                        };
+                        let offset = align(offset, requirements.alignment);
                         unsafe { device.bind_image_memory(*image, memory, offset).unwrap() };
 
                         // Create vk::ImageView.
                         // For now, the image field of the info is null, so make it a valid handle.
-                        let mut info = info.clone();
+                        let mut info = *info;
                         info.image = *image;
                         *image_view = unsafe { device.create_image_view(&info, None).unwrap() };
 
@@ -535,7 +1298,7 @@ impl Render {
             .map(|views| {
                 let framebuffer_info = vk::FramebufferCreateInfo::builder()
                     .flags(vk::FramebufferCreateFlags::empty())
-                    .render_pass(unimplemented!())
+                    .render_pass(render_pass)
                     .width(swapchain.extent.width)
                     .height(swapchain.extent.height)
                     .layers(1)
@@ -545,58 +1308,299 @@ impl Render {
             })
             .collect::<Vec<_>>();
 
-        Framebuffers { handles, memory, images, views }
+        Framebuffers { handles, memory, images, views, normal_format }
+    }
+
+    /// Name the swapchain images, the framebuffers and their attachments, numbered by framebuffer.
+    fn name_framebuffers(vulkan: &Vulkan, framebuffers: &Framebuffers, swapchain: &SwapchainKHR) {
+        // In the order of attachments.
+        let names = ["swapchain", "depth", "normal g-buffer", "albedo g-buffer", "material g-buffer"];
+
+        swapchain.images
+            .iter()
+            .enumerate()
+            .for_each(|(index, image)| vulkan.set_object_name(*image, &format!("swapchain image {}", index)));
+
+        framebuffers.handles
+            .iter()
+            .zip(framebuffers.images.iter())
+            .zip(framebuffers.views.iter())
+            .enumerate()
+            .for_each(|(index, ((handle, images), views))| {
+                vulkan.set_object_name(*handle, &format!("framebuffer {}", index));
+                images
+                    .iter()
+                    .zip(names[1..].iter())
+                    .for_each(|(image, name)| vulkan.set_object_name(*image, &format!("{} {}", name, index)));
+                views
+                    .iter()
+                    .zip(names.iter())
+                    .for_each(|(view, name)| vulkan.set_object_name(*view, &format!("{} view {}", name, index)));
+            });
     }
 
+    /// Load the pipeline cache saved by the last run if it was made by the same device.
     fn create_pipeline_cache(vulkan: &Vulkan) -> vk::PipelineCache {
-        let info = vk::PipelineCacheCreateInfo::builder().initial_data(&[]).build();
+        let data = pipeline_cache_path()
+            .and_then(|path| std::fs::read(path).ok())
+            .filter(|data| is_compatible_pipeline_cache(data, &vulkan.physical_device.properties))
+            .unwrap_or_default();
+
+        let info = vk::PipelineCacheCreateInfo::builder().initial_data(&data[..]).build();
         unsafe { vulkan.device.create_pipeline_cache(&info, None).unwrap() }
     }
 
-    unsafe fn shader_module(&self, bytes: &[u8]) -> vk::ShaderModule {
+    /// Errors are only printed, since the cache is just for speed.
+    unsafe fn save_pipeline_cache(&self, vulkan: &Vulkan) {
+        let path = match pipeline_cache_path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result = vulkan.device
+            .get_pipeline_cache_data(self.pipeline_cache)
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|data| {
+                std::fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| std::fs::write(&path, data))
+                    .map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            eprintln!("failed to save the pipeline cache into {}: {}", path.display(), err);
+        }
+    }
+
+    /// Threads recording secondary command buffers of each frame, besides the frame loop.
+    fn default_worker_count() -> usize {
+        std::thread::available_parallelism()
+            .map(|count| count.get().saturating_sub(1).max(1))
+            .unwrap_or(1)
+    }
+
+    fn inheritance(&self, framebuffer_index: usize, subpass: u32) -> Inheritance {
+        Inheritance {
+            render_pass: self.render_pass,
+            subpass,
+            framebuffer: self.framebuffers.handles[framebuffer_index],
+        }
+    }
+
+    fn set_viewport(command_buffer: &mut CommandBuffer<Recording>, extent: vk::Extent2D) {
+        let viewport = vk::Viewport::builder()
+            .x(0.0)
+            .y(0.0)
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .max_depth(1.0)
+            .min_depth(0.0)
+            .build();
+        let scissor = vk::Rect2D::builder()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(extent)
+            .build();
+
+        command_buffer
+            .set_viewport(viewport)
+            .set_scissor(scissor);
+    }
+
+    unsafe fn shader_module(vulkan: &Vulkan, bytes: &[u8]) -> vk::ShaderModule {
         debug_assert_eq!(bytes.len() % 4, 0);
-        let code = std::slice::from_raw_parts(bytes[0] as *const u8 as *const u32, bytes.len() / 4);
+        // Bytes of include_bytes! or a file are not aligned to u32.
+        let code = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>();
 
         let info = vk::ShaderModuleCreateInfo::builder()
             .flags(vk::ShaderModuleCreateFlags::empty())
-            .code(code);
+            .code(&code[..]);
 
-        self.vulkan.borrow().device.create_shader_module(&info, None).unwrap()
+        vulkan.device.create_shader_module(&info, None).unwrap()
     }
 
-    unsafe fn destroy(self, vulkan: &Vulkan) {
+    /// Save the pipeline cache and destroy everything of the render. Call this on exit.
+    /// # Safety
+    /// This waits until the device becomes idle. Shaders and descriptor sets made with the
+    /// render must not be used after this.
+    pub unsafe fn destroy(self, vulkan: &Vulkan) {
         let device = &vulkan.device;
+        device.device_wait_idle().unwrap();
+        // save pipeline cache before fields are moved out
+        self.save_pipeline_cache(vulkan);
+
+        // destroy frames and resources retired by them
+        self.frames.destroy(vulkan);
+
         // destroy Framebuffers
-        self.framebuffers.handles
+        self.framebuffers.destroy(device);
+
+        // destroy camera, joint and light buffers and shadow map
+        self.cameras.destroy(vulkan);
+        self.joints.destroy(vulkan);
+        self.lights.destroy(vulkan);
+        self.shadow_map.destroy(vulkan);
+        self.descriptors.destroy(vulkan);
+        self.profiler.destroy(vulkan);
+
+        // destroy pipeline cache, which was saved above
+        device.destroy_pipeline_cache(self.pipeline_cache, None);
+
+        // destroy RenderPass
+        device.destroy_render_pass(self.render_pass, None);
+
+        // destroy SwapchainKHR
+        self.swapchain.loader.destroy_swapchain(self.swapchain.handle, None);
+    }
+}
+
+/// `<user cache directory>/sinsha/pipeline_cache`
+fn pipeline_cache_path() -> Option<PathBuf> {
+    let env_path = |name| std::env::var_os(name).map(PathBuf::from);
+    let cache_dir = if cfg!(windows) {
+        env_path("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        env_path("XDG_CACHE_HOME").or_else(|| env_path("HOME").map(|home| home.join(".cache")))
+    };
+
+    cache_dir.map(|dir| dir.join("sinsha").join("pipeline_cache"))
+}
+
+/// Check the header (`VkPipelineCacheHeaderVersionOne`) matches the device.
+/// Drivers should reject incompatible data by themselves, but not all of them do.
+fn is_compatible_pipeline_cache(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    // The header is in the byte order of the host.
+    let word = |index: usize| {
+        let bytes = &data[index * 4..index * 4 + 4];
+        u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    let header_size = word(0);
+    let header_version = word(1);
+    let vendor_id = word(2);
+    let device_id = word(3);
+    let uuid = &data[16..HEADER_SIZE];
+
+    header_size as usize >= HEADER_SIZE
+        && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && vendor_id == properties.vendor_id
+        && device_id == properties.device_id
+        && uuid == &properties.pipeline_cache_uuid[..]
+}
+
+impl Framebuffers {
+    /// # Safety
+    /// The device must have finished using the framebuffers.
+    unsafe fn destroy(self, device: &Device) {
+        self.handles
             .iter()
             .for_each(|handle| device.destroy_framebuffer(*handle, None));
-        self.framebuffers.views
+        self.views
             .iter()
             .for_each(|views| {
                 views.iter().for_each(|view| device.destroy_image_view(*view, None));
             });
-        self.framebuffers.images
+        self.images
             .iter()
             .for_each(|images| {
                 images.iter().for_each(|image| device.destroy_image(*image, None));
             });
-        device.free_memory(self.framebuffers.memory, None);
+        device.free_memory(self.memory, None);
+    }
+}
 
-        // destroy pipeline cache
-        device.destroy_pipeline_cache(self.pipeline_cache, None);
+impl HostBuffer {
+    /// The regions are aligned to this.
+    /// 256 is the maximum value of `min*BufferOffsetAlignment` allowed by the spec.
+    const REGION_ALIGNMENT: u64 = 256;
+
+    unsafe fn new(
+        vulkan: &Vulkan,
+        usage: vk::BufferUsageFlags,
+        size: u64,
+        region_count: usize,
+    ) -> Self {
+        let device = &vulkan.device;
+        let region_size = size.div_ceil(Self::REGION_ALIGNMENT) * Self::REGION_ALIGNMENT;
 
-        // destroy RenderPass
-        device.destroy_render_pass(self.render_pass, None);
+        let info = vk::BufferCreateInfo::builder()
+            .size(region_size * region_count as u64)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .queue_family_indices(&[]);
+        let handle = device.create_buffer(&info, None).unwrap();
+
+        let requirements = device.get_buffer_memory_requirements(handle);
+        let memory_type_index = vulkan.physical_device
+            .memory_type_index(
+                requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+            .unwrap();
 
-        // destroy SwapchainKHR
-        self.swapchain.loader.destroy_swapchain(self.swapchain.handle, None);
+        let info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_type_index)
+            .allocation_size(requirements.size);
+        let memory = device.allocate_memory(&info, None).unwrap();
+        device.bind_buffer_memory(handle, memory, 0).unwrap();
+
+        let mapped = device
+            .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            .unwrap() as *mut u8;
+
+        Self { handle, memory, mapped, region_size }
     }
+
+    #[inline]
+    unsafe fn region(&self, index: usize) -> *mut u8 {
+        self.mapped.add(self.region_size as usize * index)
+    }
+
+    fn set_name(&self, vulkan: &Vulkan, name: &str) {
+        vulkan.set_object_name(self.handle, name);
+        vulkan.set_object_name(self.memory, &format!("{} memory", name));
+    }
+
+    fn descriptor_info(&self, index: usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::builder()
+            .buffer(self.handle)
+            .offset(self.region_size * index as u64)
+            .range(self.region_size)
+            .build()
+    }
+
+    unsafe fn destroy(self, vulkan: &Vulkan) {
+        vulkan.device.unmap_memory(self.memory);
+        vulkan.device.destroy_buffer(self.handle, None);
+        vulkan.device.free_memory(self.memory, None);
+    }
+}
+
+impl Destroy for HostBuffer {
+    #[inline]
+    unsafe fn destroy(self, vulkan: &Vulkan) { HostBuffer::destroy(self, vulkan) }
 }
 
 impl Shader {
+    /// # Safety
+    /// The device must have finished using the pipeline.
     pub unsafe fn destroy(self, vulkan: &Vulkan) {
-        vulkan.device.destroy_pipeline(self.pipeline);
-        vulkan.device.destroy_pipeline_layout(self.pipeline_layout);
-        vulkan.device.destroy_descriptor_set_layout(self.descriptor_set_layout);
+        vulkan.device.destroy_pipeline(self.pipeline, None);
+        vulkan.device.destroy_pipeline_layout(self.pipeline_layout, None);
+        self.descriptor_set_layouts
+            .iter()
+            .for_each(|layout| vulkan.device.destroy_descriptor_set_layout(*layout, None));
     }
 }
+
+impl Destroy for Shader {
+    #[inline]
+    unsafe fn destroy(self, vulkan: &Vulkan) { Shader::destroy(self, vulkan) }
+}
@@ -0,0 +1,227 @@
+//! Compilation of GLSL into SPIR-V.
+//!
+//! This module is shared by `build.rs` (through `#[path]`), so it must depend only on std and
+//! the `shaderc` crate.
+//!
+//! Each shader is a directory under `src/vulkan/render/` which has `glsl.<stage>` sources,
+//! e.g. `dim3/glsl.vert` and `dim3/glsl.frag`. Sources can `#include "file"` relative to
+//! their directory or to `src/vulkan/render/include/`. `glslangValidator` needs
+//! `#extension GL_GOOGLE_include_directive : require` in such sources.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+/// Extensions of sources and the names of compiled SPIR-V.
+pub const STAGES: [(&str, &str); 6] = [
+    ("vert", "vert.spv"),
+    ("tesc", "tesc.spv"),
+    ("tese", "tese.spv"),
+    ("geom", "geom.spv"),
+    ("frag", "frag.spv"),
+    ("comp", "comp.spv"),
+];
+
+/// Directory of shared sources for `#include`, relative to the shader root.
+pub const INCLUDE_DIR: &str = "include";
+
+#[derive(Debug)]
+pub enum CompileErr {
+    /// Neither `shaderc` nor `glslangValidator` is available.
+    NoCompiler,
+    Io(std::io::Error),
+    /// Error messages of the compiler.
+    Failed(String),
+}
+
+/// A source of one stage of a shader.
+#[derive(Clone, Debug)]
+pub struct Source {
+    /// Name of the directory, e.g. "dim3".
+    pub shader: String,
+    pub path: PathBuf,
+    /// Extension of the source, e.g. "vert".
+    pub stage: &'static str,
+    /// e.g. "vert.spv"
+    pub spv_name: &'static str,
+}
+
+impl Source {
+    /// SPIR-V checked in next to the source, which is compiled without defines and used when
+    /// no compiler is available.
+    pub fn checked_in(&self) -> PathBuf { self.path.with_file_name(self.spv_name) }
+
+    /// File which has the `source_hash` of the sources `checked_in` was compiled from,
+    /// e.g. "vert.spv.hash".
+    pub fn stamp(&self) -> PathBuf { self.path.with_file_name(format!("{}.hash", self.spv_name)) }
+}
+
+/// Find all sources of all shaders under the root, sorted by paths.
+pub fn discover(shader_root: &Path) -> Result<Vec<Source>, CompileErr> {
+    let mut sources = Vec::new();
+
+    for entry in fs::read_dir(shader_root).map_err(CompileErr::Io)? {
+        let dir = entry.map_err(CompileErr::Io)?.path();
+        if !dir.is_dir() || dir.file_name().is_some_and(|name| name == INCLUDE_DIR) {
+            continue;
+        }
+        let shader = dir.file_name().unwrap().to_string_lossy().into_owned();
+
+        for &(stage, spv_name) in STAGES.iter() {
+            let path = dir.join(format!("glsl.{}", stage));
+            if path.is_file() {
+                sources.push(Source { shader: shader.clone(), path, stage, spv_name });
+            }
+        }
+    }
+
+    sources.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(sources)
+}
+
+/// Files which the source includes, recursively. Used to know what to watch.
+/// Includes which cannot be found are ignored here; the compiler reports them.
+pub fn dependencies(source: &Path, shader_root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![source.to_path_buf()];
+
+    while let Some(path) = pending.pop() {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        text.lines()
+            .filter_map(|line| {
+                let line = line.trim_start();
+                if !line.starts_with("#include") {
+                    return None;
+                }
+                let name = line.split_once('"')?.1.split_once('"')?.0;
+                resolve_include(name, dir, shader_root)
+            })
+            .for_each(|include| {
+                if !found.contains(&include) {
+                    found.push(include.clone());
+                    pending.push(include);
+                }
+            });
+    }
+
+    found
+}
+
+/// FNV-1a hash of the source and files it includes. Carriage returns are skipped, so that
+/// checkouts with CRLF line endings have the same hash.
+pub fn source_hash(source: &Source, shader_root: &Path) -> Result<u64, CompileErr> {
+    let mut paths = dependencies(&source.path, shader_root);
+    paths.sort();
+    paths.insert(0, source.path.clone());
+
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for path in paths.iter() {
+        let bytes = fs::read(path).map_err(CompileErr::Io)?;
+        hash = bytes
+            .into_iter()
+            .filter(|&byte| byte != b'\r')
+            .fold(hash, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+    }
+    Ok(hash)
+}
+
+fn resolve_include(name: &str, dir: &Path, shader_root: &Path) -> Option<PathBuf> {
+    [dir.join(name), shader_root.join(INCLUDE_DIR).join(name)]
+        .iter()
+        .find(|path| path.is_file())
+        .cloned()
+}
+
+/// Compile a source into SPIR-V. `defines` are `(name, value)` pairs of macros.
+#[cfg(feature = "shaderc")]
+pub fn compile(source: &Source, shader_root: &Path, defines: &[(String, String)]) -> Result<Vec<u8>, CompileErr> {
+    let text = fs::read_to_string(&source.path).map_err(CompileErr::Io)?;
+    let kind = match source.stage {
+        "vert" => shaderc::ShaderKind::Vertex,
+        "tesc" => shaderc::ShaderKind::TessControl,
+        "tese" => shaderc::ShaderKind::TessEvaluation,
+        "geom" => shaderc::ShaderKind::Geometry,
+        "frag" => shaderc::ShaderKind::Fragment,
+        _ => shaderc::ShaderKind::Compute,
+    };
+
+    let mut compiler = shaderc::Compiler::new().ok_or(CompileErr::NoCompiler)?;
+    let mut options = shaderc::CompileOptions::new().ok_or(CompileErr::NoCompiler)?;
+    defines.iter().for_each(|(name, value)| options.add_macro_definition(name, Some(value)));
+
+    let dir = source.path.parent().unwrap().to_path_buf();
+    let shader_root = shader_root.to_path_buf();
+    options.set_include_callback(move |name, _, includer, _| {
+        let includer_dir = Path::new(includer).parent().map_or(dir.clone(), Path::to_path_buf);
+        let path = resolve_include(name, &includer_dir, &shader_root)
+            .ok_or_else(|| format!("{}: not found", name))?;
+        let content = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: path.to_string_lossy().into_owned(),
+            content,
+        })
+    });
+
+    compiler
+        .compile_into_spirv(&text, kind, &source.path.to_string_lossy(), "main", Some(&options))
+        .map(|artifact| artifact.as_binary_u8().to_vec())
+        .map_err(|err| CompileErr::Failed(err.to_string()))
+}
+
+/// Compile a source into SPIR-V with `glslangValidator`. `defines` are `(name, value)` pairs
+/// of macros.
+#[cfg(not(feature = "shaderc"))]
+pub fn compile(source: &Source, shader_root: &Path, defines: &[(String, String)]) -> Result<Vec<u8>, CompileErr> {
+    use std::process::Command;
+
+    let output_path = std::env::temp_dir().join(format!(
+        "sinsha-{}-{}-{}.spv",
+        std::process::id(),
+        source.shader,
+        source.stage,
+    ));
+
+    let mut command = Command::new("glslangValidator");
+    command
+        .arg("-V")
+        .arg(format!("-I{}", shader_root.join(INCLUDE_DIR).display()));
+    defines.iter().for_each(|(name, value)| { command.arg(format!("-D{}={}", name, value)); });
+    command.arg(&source.path).arg("-o").arg(&output_path);
+
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Err(CompileErr::NoCompiler),
+        Err(err) => return Err(CompileErr::Io(err)),
+    };
+
+    if !output.status.success() {
+        // glslangValidator writes errors into stdout.
+        let _ = fs::remove_file(&output_path);
+        return Err(CompileErr::Failed(String::from_utf8_lossy(&output.stdout).into_owned()));
+    }
+
+    let spirv = fs::read(&output_path).map_err(CompileErr::Io)?;
+    let _ = fs::remove_file(&output_path);
+    Ok(spirv)
+}
+
+/// Parse `NAME=VALUE,NAME2` into macros. A name without value is defined as 1.
+pub fn parse_defines(defines: &str) -> Vec<(String, String)> {
+    defines
+        .split(',')
+        .map(str::trim)
+        .filter(|define| !define.is_empty())
+        .map(|define| {
+            let mut pair = define.splitn(2, '=');
+            let name = pair.next().unwrap().to_string();
+            let value = pair.next().unwrap_or("1").to_string();
+            (name, value)
+        })
+        .collect()
+}
@@ -0,0 +1,332 @@
+//! Allocation and writing of descriptor sets.
+//!
+//! `DescriptorAllocator` creates pools on demand for each descriptor set layout. Persistent
+//! sets live until they are freed or the allocator is destroyed, and transient sets live
+//! until their frame is reset.
+//!
+//! `DescriptorWriter` collects resources bound to a set and writes them at once.
+
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use super::Vulkan;
+use super::Shader;
+use super::Destroy;
+
+use std::collections::HashMap;
+
+/// Resources which can be bound as buffer descriptors.
+pub trait DescriptorBuffer {
+    fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo;
+}
+
+/// Resources which can be bound as image descriptors.
+pub trait DescriptorImage {
+    fn descriptor_image_view(&self) -> vk::ImageView;
+}
+
+pub struct DescriptorAllocator {
+    persistent: Pools,
+    /// Pools of persistent sets, which `free` gives them back into.
+    owners: HashMap<vk::DescriptorSet, vk::DescriptorPool>,
+    /// One for each frame in flight.
+    transient: Vec<Pools>,
+}
+
+/// Pools of each layout.
+struct Pools {
+    flags: vk::DescriptorPoolCreateFlags,
+    layouts: HashMap<vk::DescriptorSetLayout, LayoutPools>,
+}
+
+/// A persistent set given back by `DescriptorAllocator::free`. Destroying it frees the set
+/// into its pool, which can allocate it again.
+pub struct FreedDescriptorSet {
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+}
+
+struct LayoutPools {
+    /// Sizes of a pool, which holds `SETS_PER_POOL` sets.
+    sizes: Vec<vk::DescriptorPoolSize>,
+    pools: Vec<vk::DescriptorPool>,
+    /// Index of the pool to allocate from. Pools before this are full.
+    current: usize,
+}
+
+pub struct DescriptorWriter {
+    set: vk::DescriptorSet,
+    buffers: Vec<(u32, vk::DescriptorType, vk::DescriptorBufferInfo)>,
+    images: Vec<(u32, vk::DescriptorType, vk::DescriptorImageInfo)>,
+}
+
+impl DescriptorAllocator {
+    const SETS_PER_POOL: u32 = 64;
+
+    pub fn new(frame_count: usize) -> Self {
+        Self {
+            persistent: Pools::new(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET),
+            owners: HashMap::new(),
+            transient: (0..frame_count).map(|_| Pools::new(vk::DescriptorPoolCreateFlags::empty())).collect(),
+        }
+    }
+
+    /// Allocate a set of the `set` number of the shader which lives until it is freed or this
+    /// allocator is destroyed.
+    /// # Safety
+    /// The shader must be alive, and `set` must be one of its sets.
+    pub unsafe fn allocate(&mut self, vulkan: &Vulkan, shader: &Shader, set: u32) -> vk::DescriptorSet {
+        let (set, pool) = self.persistent.allocate(vulkan, shader, set);
+        self.owners.insert(set, pool);
+        set
+    }
+
+    /// Give back a set of `allocate`, which is freed when the returned one is destroyed,
+    /// e.g. by `Render::retire` after frames using it complete.
+    /// Panics if the set is not a persistent one of this allocator.
+    pub fn free(&mut self, set: vk::DescriptorSet) -> FreedDescriptorSet {
+        let pool = self.owners
+            .remove(&set)
+            .expect("the descriptor set is not a persistent one of the allocator");
+        FreedDescriptorSet { pool, set }
+    }
+
+    /// Allocate a set of the `set` number of the shader which lives until `reset_frame` of
+    /// the frame.
+    /// # Safety
+    /// The shader must be alive, and `set` must be one of its sets.
+    pub unsafe fn allocate_transient(
+        &mut self,
+        vulkan: &Vulkan,
+        frame_index: usize,
+        shader: &Shader,
+        set: u32,
+    ) -> vk::DescriptorSet {
+        self.transient[frame_index].allocate(vulkan, shader, set).0
+    }
+
+    /// Free all transient sets of the frame at once. Pools are kept for the next frame.
+    /// # Safety
+    /// The device must have finished the frame.
+    pub unsafe fn reset_frame(&mut self, vulkan: &Vulkan, frame_index: usize) {
+        self.transient[frame_index].layouts
+            .values_mut()
+            .for_each(|layout_pools| {
+                layout_pools.pools
+                    .iter()
+                    .for_each(|pool| {
+                        vulkan.device
+                            .reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())
+                            .unwrap();
+                    });
+                layout_pools.current = 0;
+            });
+    }
+
+    /// # Safety
+    /// The device must have finished using all sets.
+    pub unsafe fn destroy(self, vulkan: &Vulkan) {
+        self.persistent.destroy(vulkan);
+        self.transient.into_iter().for_each(|pools| pools.destroy(vulkan));
+    }
+}
+
+impl Pools {
+    fn new(flags: vk::DescriptorPoolCreateFlags) -> Self {
+        Self { flags, layouts: HashMap::new() }
+    }
+
+    /// Returns the set with the pool it is allocated from.
+    unsafe fn allocate(
+        &mut self,
+        vulkan: &Vulkan,
+        shader: &Shader,
+        set: u32,
+    ) -> (vk::DescriptorSet, vk::DescriptorPool) {
+        let layout = shader.descriptor_set_layouts[set as usize];
+        let layout_pools = self.layouts
+            .entry(layout)
+            .or_insert_with(|| LayoutPools::new(&shader.descriptor_pool_sizes[set as usize]));
+
+        let set_layouts = [layout];
+        let try_allocate = |pool: vk::DescriptorPool| {
+            let info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&set_layouts[..]);
+
+            match vulkan.device.allocate_descriptor_sets(&info) {
+                Ok(sets) => Some(sets[0]),
+                // This pool is full.
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                    | Err(vk::Result::ERROR_FRAGMENTED_POOL) => None,
+                Err(err) => panic!("failed to allocate a descriptor set: {:?}", err),
+            }
+        };
+
+        loop {
+            if layout_pools.current == layout_pools.pools.len() {
+                // Sets freed into the pools before make room again.
+                if self.flags.contains(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET) {
+                    let reused = layout_pools.pools
+                        .iter()
+                        .enumerate()
+                        .find_map(|(index, pool)| try_allocate(*pool).map(|set| (index, set, *pool)));
+                    if let Some((index, set, pool)) = reused {
+                        layout_pools.current = index;
+                        return (set, pool);
+                    }
+                }
+                layout_pools.pools.push(layout_pools.create_pool(vulkan, self.flags));
+            }
+
+            let pool = layout_pools.pools[layout_pools.current];
+            match try_allocate(pool) {
+                Some(set) => return (set, pool),
+                // Go to the next one.
+                None => layout_pools.current += 1,
+            }
+        }
+    }
+
+    unsafe fn destroy(self, vulkan: &Vulkan) {
+        self.layouts
+            .values()
+            .flat_map(|layout_pools| layout_pools.pools.iter())
+            .for_each(|pool| vulkan.device.destroy_descriptor_pool(*pool, None));
+    }
+}
+
+impl LayoutPools {
+    fn new(sizes_of_one_set: &[vk::DescriptorPoolSize]) -> Self {
+        let sizes = sizes_of_one_set
+            .iter()
+            .map(|size| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(size.ty)
+                    .descriptor_count(size.descriptor_count * DescriptorAllocator::SETS_PER_POOL)
+                    .build()
+            })
+            .collect();
+
+        Self { sizes, pools: Vec::new(), current: 0 }
+    }
+
+    unsafe fn create_pool(&self, vulkan: &Vulkan, flags: vk::DescriptorPoolCreateFlags) -> vk::DescriptorPool {
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(flags)
+            .max_sets(DescriptorAllocator::SETS_PER_POOL)
+            .pool_sizes(&self.sizes[..]);
+        vulkan.device.create_descriptor_pool(&info, None).unwrap()
+    }
+}
+
+impl Destroy for FreedDescriptorSet {
+    unsafe fn destroy(self, vulkan: &Vulkan) {
+        vulkan.device.free_descriptor_sets(self.pool, &[self.set]);
+    }
+}
+
+impl DescriptorWriter {
+    pub fn new(set: vk::DescriptorSet) -> Self {
+        Self { set, buffers: Vec::new(), images: Vec::new() }
+    }
+
+    pub fn uniform_buffer<B: DescriptorBuffer>(self, binding: u32, buffer: &B) -> Self {
+        self.buffer(binding, vk::DescriptorType::UNIFORM_BUFFER, buffer)
+    }
+
+    pub fn storage_buffer<B: DescriptorBuffer>(self, binding: u32, buffer: &B) -> Self {
+        self.buffer(binding, vk::DescriptorType::STORAGE_BUFFER, buffer)
+    }
+
+    pub fn buffer<B: DescriptorBuffer>(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: &B,
+    ) -> Self {
+        self.buffers.push((binding, descriptor_type, buffer.descriptor_buffer_info()));
+        self
+    }
+
+    /// The image must be in `SHADER_READ_ONLY_OPTIMAL` when it is sampled.
+    pub fn combined_image_sampler<I: DescriptorImage>(
+        self,
+        binding: u32,
+        image: &I,
+        sampler: vk::Sampler,
+    ) -> Self {
+        self.image(
+            binding,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            image,
+            sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+    }
+
+    pub fn input_attachment<I: DescriptorImage>(
+        self,
+        binding: u32,
+        image: &I,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        self.image(binding, vk::DescriptorType::INPUT_ATTACHMENT, image, vk::Sampler::null(), layout)
+    }
+
+    /// `sampler` is ignored unless the type uses samplers.
+    pub fn image<I: DescriptorImage>(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        image: &I,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        let info = vk::DescriptorImageInfo::builder()
+            .sampler(sampler)
+            .image_view(image.descriptor_image_view())
+            .image_layout(layout)
+            .build();
+        self.images.push((binding, descriptor_type, info));
+        self
+    }
+
+    /// # Safety
+    /// The set must not be in use by the device.
+    pub unsafe fn write(&self, vulkan: &Vulkan) {
+        let buffer_writes = self.buffers
+            .iter()
+            .map(|(binding, descriptor_type, info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.set)
+                    .dst_binding(*binding)
+                    .descriptor_type(*descriptor_type)
+                    .buffer_info(std::slice::from_ref(info))
+                    .build()
+            });
+        let image_writes = self.images
+            .iter()
+            .map(|(binding, descriptor_type, info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.set)
+                    .dst_binding(*binding)
+                    .descriptor_type(*descriptor_type)
+                    .image_info(std::slice::from_ref(info))
+                    .build()
+            });
+        let writes = buffer_writes.chain(image_writes).collect::<Vec<_>>();
+
+        vulkan.device.update_descriptor_sets(&writes[..], &[]);
+    }
+}
+
+impl DescriptorBuffer for vk::DescriptorBufferInfo {
+    #[inline]
+    fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo { *self }
+}
+
+impl DescriptorImage for vk::ImageView {
+    #[inline]
+    fn descriptor_image_view(&self) -> vk::ImageView { *self }
+}
@@ -0,0 +1,403 @@
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use crate::linear_algebra::{ XYZ, XYZW, Quat, Mat4, Transform, Camera, Lens };
+
+use super::Vulkan;
+use super::Render;
+use super::{ CommandBuffer, Recording };
+use super::Shader;
+use super::ReflectErr;
+use super::HostBuffer;
+use super::MeshBuffer;
+use super::Matrix;
+use super::ViewMatrices;
+use super::GraphicsPipelineBuilder;
+use super::DescriptorWriter;
+
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+
+/// Maximum number of joint matrices of all skinned objects in a frame.
+pub const MAX_JOINTS: usize = 4096;
+
+/// The value of the specialization constant `PROJECTION` in `dim3/glsl.vert`.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Projection {
+    Perspective = 0,
+    Orthographic = 1,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Vertex {
+    pub position: XYZ<f32>,
+    pub normal: XYZ<f32>,
+    pub color: XYZW<f32>,
+}
+
+vertex_input!(Vertex { position, normal, color });
+
+/// Vertex of `dim3_skinned/glsl.vert`. `joints` index the matrices of the object, and
+/// `weights` sum to 1. Unused joints have zero weights.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SkinnedVertex {
+    pub position: XYZ<f32>,
+    pub normal: XYZ<f32>,
+    pub color: XYZW<f32>,
+    pub joints: XYZW<u32>,
+    pub weights: XYZW<f32>,
+}
+
+vertex_input!(SkinnedVertex { position, normal, color, joints, weights });
+
+/// Layout of `Camera` in `dim3/glsl.vert` (std140).
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CameraUniform {
+    view: Matrix,
+    perspective: Matrix,
+    orthographic: Matrix,
+}
+
+/// Layout of `Obj` in `dim3/glsl.vert`. Pushed as push constants for each object.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Object {
+    pub position: XYZW<f32>,
+    pub rotation: Quat,
+    pub scale: XYZW<f32>,
+}
+
+/// Layout of `Obj` in `dim3_skinned/glsl.vert`: `Object` followed by the index of the first
+/// joint matrix, which `Render::upload_joints` returns.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SkinnedObject {
+    pub object: Object,
+    pub joint_offset: u32,
+}
+
+/// Records draws of skinned meshes into a command buffer in the G-Buffer subpass, like
+/// `Recorder` without materials.
+pub struct SkinnedRecorder<'a, 'v> {
+    command_buffer: &'a mut CommandBuffer<Recording<'v>>,
+    shader: &'a Shader,
+}
+
+/// Camera uniform buffers and their descriptor sets, one for each framebuffer.
+pub struct Cameras {
+    buffer: HostBuffer,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+/// Storage buffers of joint matrices and their descriptor sets, one for each framebuffer.
+/// Matrices of each frame are appended from the start of the region of its framebuffer.
+pub struct Joints {
+    buffer: HostBuffer,
+    /// Matrices written into the region of each framebuffer.
+    counts: Vec<usize>,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl CameraUniform {
+    pub fn new(camera: &Camera, lens: &Lens, aspect: f32) -> Self {
+        Self {
+            view: camera.view_matrix(),
+            perspective: lens.perspective(aspect),
+            orthographic: lens.orthographic(aspect),
+        }
+    }
+
+    /// Matrices for the lighting subpass to reconstruct positions of the projection.
+    pub fn view_matrices(
+        camera: &Camera,
+        lens: &Lens,
+        aspect: f32,
+        projection: Projection,
+    ) -> ViewMatrices {
+        let inverse_projection = match projection {
+            Projection::Perspective => lens.inverse_perspective(aspect),
+            Projection::Orthographic => lens.inverse_orthographic(aspect),
+        };
+
+        ViewMatrices {
+            view: camera.view_matrix(),
+            inverse_view: camera.inverse_view_matrix(),
+            inverse_projection,
+        }
+    }
+}
+
+impl Object {
+    pub fn new(position: XYZ<f32>, rotation: Quat, scale: XYZ<f32>) -> Self {
+        Self {
+            position: position.extend(1.0),
+            rotation,
+            scale: scale.extend(1.0),
+        }
+    }
+
+    /// Record pushing this transform for following draw commands.
+    pub fn push(&self, command_buffer: &mut CommandBuffer<Recording>, shader: &Shader) {
+        command_buffer.push_constants(shader.pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, self);
+    }
+}
+
+impl SkinnedObject {
+    pub fn new(object: Object, joint_offset: u32) -> Self {
+        Self { object, joint_offset }
+    }
+
+    /// Record pushing this transform and joint offset for following draw commands.
+    pub fn push(&self, command_buffer: &mut CommandBuffer<Recording>, shader: &Shader) {
+        command_buffer.push_constants(shader.pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, self);
+    }
+}
+
+impl<'a, 'v> SkinnedRecorder<'a, 'v> {
+    /// Record binding the pipeline of `Render::load_dim3_skinned`, the viewport covering
+    /// `extent`, and the camera and joint matrices of the framebuffer.
+    pub(super) fn begin(
+        command_buffer: &'a mut CommandBuffer<Recording<'v>>,
+        shader: &'a Shader,
+        camera: vk::DescriptorSet,
+        joints: vk::DescriptorSet,
+        extent: vk::Extent2D,
+    ) -> Self {
+        command_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, shader.pipeline);
+        Render::set_viewport(command_buffer, extent);
+        command_buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            shader.pipeline_layout,
+            0,
+            &[camera, joints],
+            &[],
+        );
+
+        Self { command_buffer, shader }
+    }
+
+    #[inline]
+    pub fn command_buffer(&mut self) -> &mut CommandBuffer<Recording<'v>> { self.command_buffer }
+
+    /// Record drawing the mesh of `SkinnedVertex` with the transform and the joint offset.
+    pub fn draw(&mut self, mesh: &Arc<MeshBuffer>, object: &SkinnedObject) {
+        object.push(self.command_buffer, self.shader);
+        mesh.bind(self.command_buffer);
+        mesh.draw(self.command_buffer);
+    }
+}
+
+impl From<&Transform> for Object {
+    fn from(transform: &Transform) -> Self {
+        Self::new(transform.translation, transform.rotation, transform.scale)
+    }
+}
+
+impl Cameras {
+    pub(super) unsafe fn new(vulkan: &Vulkan, framebuffer_count: usize) -> Self {
+        let buffer = HostBuffer::new(
+            vulkan,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            mem::size_of::<CameraUniform>() as u64,
+            framebuffer_count,
+        );
+        buffer.set_name(vulkan, "camera buffer");
+
+        Self {
+            buffer,
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::new(),
+        }
+    }
+
+    /// Allocate a descriptor set for each framebuffer and write the camera uniform buffer.
+    pub(super) unsafe fn write_descriptor_sets(
+        &mut self,
+        vulkan: &Vulkan,
+        framebuffer_count: usize,
+        set_layout: vk::DescriptorSetLayout,
+    ) {
+        let device = &vulkan.device;
+        let count = framebuffer_count as u32;
+
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(count)
+                .build(),
+        ];
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count)
+            .pool_sizes(&pool_sizes[..]);
+        self.descriptor_pool = device.create_descriptor_pool(&info, None).unwrap();
+
+        let set_layouts = vec![set_layout; framebuffer_count];
+        let info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts[..]);
+        self.descriptor_sets = device.allocate_descriptor_sets(&info).unwrap();
+
+        self.descriptor_sets
+            .iter()
+            .enumerate()
+            .for_each(|(index, set)| {
+                DescriptorWriter::new(*set)
+                    .uniform_buffer(0, &self.buffer.descriptor_info(index))
+                    .write(vulkan);
+            });
+    }
+
+    /// The region must not be in use by the device.
+    pub(super) unsafe fn upload(&self, framebuffer_index: usize, camera: &CameraUniform) {
+        ptr::write(self.buffer.region(framebuffer_index) as *mut CameraUniform, *camera);
+    }
+
+    #[inline]
+    pub(super) fn descriptor_set(&self, framebuffer_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[framebuffer_index]
+    }
+
+    pub(super) unsafe fn destroy(self, vulkan: &Vulkan) {
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            vulkan.device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+        self.buffer.destroy(vulkan);
+    }
+}
+
+impl Joints {
+    pub(super) unsafe fn new(vulkan: &Vulkan, framebuffer_count: usize) -> Self {
+        let buffer = HostBuffer::new(
+            vulkan,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            (mem::size_of::<Mat4>() * MAX_JOINTS) as u64,
+            framebuffer_count,
+        );
+        buffer.set_name(vulkan, "joint buffer");
+
+        Self {
+            buffer,
+            counts: vec![0; framebuffer_count],
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::new(),
+        }
+    }
+
+    /// Allocate a descriptor set for each framebuffer and write the joint storage buffer.
+    pub(super) unsafe fn write_descriptor_sets(
+        &mut self,
+        vulkan: &Vulkan,
+        framebuffer_count: usize,
+        set_layout: vk::DescriptorSetLayout,
+    ) {
+        let device = &vulkan.device;
+        let count = framebuffer_count as u32;
+
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(count)
+                .build(),
+        ];
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count)
+            .pool_sizes(&pool_sizes[..]);
+        self.descriptor_pool = device.create_descriptor_pool(&info, None).unwrap();
+
+        let set_layouts = vec![set_layout; framebuffer_count];
+        let info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts[..]);
+        self.descriptor_sets = device.allocate_descriptor_sets(&info).unwrap();
+
+        self.descriptor_sets
+            .iter()
+            .enumerate()
+            .for_each(|(index, set)| {
+                DescriptorWriter::new(*set)
+                    .storage_buffer(0, &self.buffer.descriptor_info(index))
+                    .write(vulkan);
+            });
+    }
+
+    /// Append the matrices and return the index of the first one, or `None` if they don't fit
+    /// in `MAX_JOINTS`. The region must not be in use by the device.
+    pub(super) unsafe fn upload(&mut self, framebuffer_index: usize, matrices: &[Mat4]) -> Option<u32> {
+        let offset = self.counts[framebuffer_index];
+        if offset + matrices.len() > MAX_JOINTS {
+            return None;
+        }
+
+        let region = self.buffer.region(framebuffer_index) as *mut Mat4;
+        ptr::copy_nonoverlapping(matrices.as_ptr(), region.add(offset), matrices.len());
+        self.counts[framebuffer_index] += matrices.len();
+        Some(offset as u32)
+    }
+
+    #[inline]
+    pub(super) fn clear(&mut self, framebuffer_index: usize) {
+        self.counts[framebuffer_index] = 0;
+    }
+
+    #[inline]
+    pub(super) fn descriptor_set(&self, framebuffer_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[framebuffer_index]
+    }
+
+    pub(super) unsafe fn destroy(self, vulkan: &Vulkan) {
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            vulkan.device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+        self.buffer.destroy(vulkan);
+    }
+}
+
+pub unsafe fn load(vulkan: &Vulkan, render: &Render, subpass: u32, projection: Projection) -> Result<Shader, ReflectErr> {
+    // The projection flips y, so front faces are clockwise in framebuffer coordinates.
+    // Color attachments are normal, albedo and material G-Buffers.
+    GraphicsPipelineBuilder::new()
+        .name("dim3")
+        .vertex_shader(spirv!(render, "dim3", "vert"))
+        .fragment_shader(spirv!(render, "dim3", "frag"))
+        .specialization(vk::ShaderStageFlags::VERTEX, 0, projection as i32)
+        .vertex::<Vertex>()
+        .cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::CLOCKWISE)
+        .depth_test(true, vk::CompareOp::LESS)
+        .opaque_color_attachments(3)
+        .build(vulkan, render.render_pass, subpass, render.pipeline_cache)
+}
+
+/// dim3 with `SkinnedVertex`. Joint matrices are set 1.
+pub unsafe fn load_skinned(
+    vulkan: &Vulkan,
+    render: &Render,
+    subpass: u32,
+    projection: Projection,
+) -> Result<Shader, ReflectErr> {
+    // The same fragment shader, culling and attachments as dim3.
+    GraphicsPipelineBuilder::new()
+        .name("dim3 skinned")
+        .vertex_shader(spirv!(render, "dim3_skinned", "vert"))
+        .fragment_shader(spirv!(render, "dim3", "frag"))
+        .specialization(vk::ShaderStageFlags::VERTEX, 0, projection as i32)
+        .vertex::<SkinnedVertex>()
+        .cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::CLOCKWISE)
+        .depth_test(true, vk::CompareOp::LESS)
+        .opaque_color_attachments(3)
+        .build(vulkan, render.render_pass, subpass, render.pipeline_cache)
+}
@@ -0,0 +1,72 @@
+# version 450
+
+// The skinned path of dim3/glsl.vert, which shares its fragment shader.
+
+// input
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec4 in_color;
+// Up to four joints, relative to OBJ.joint_offset, and their weights which sum to 1.
+layout(location = 3) in uvec4 in_joints;
+layout(location = 4) in vec4 in_weights;
+
+// output
+// Normal vectors are in view coordinates.
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec4 out_color;
+
+
+// Camera uniform, the same set as dim3.
+layout(binding = 0, set = 0) uniform Camera {
+    mat4 view;
+    mat4 perspective;
+    mat4 orthographic;
+} CAMERA;
+
+// Joint matrices of all skinned objects in the frame, which move vertices from the bind
+// pose into the current pose in object coordinates.
+layout(binding = 0, set = 1) readonly buffer Joints {
+    mat4 joints[];
+} JOINTS;
+
+// Transform of each object in push constants.
+layout(push_constant) uniform Obj {
+    // xyz: position in world coordinates.
+    vec4 position;
+    // Unit quaternion (x, y, z, w).
+    vec4 rotation;
+    // xyz: scale.
+    vec4 scale;
+    // Index of the first joint matrix of the object.
+    uint joint_offset;
+} OBJ;
+
+// Specialization Constants:
+// 0: perspective, 1: orthographic
+layout(constant_id = 0) const int PROJECTION = 0;
+
+vec3 rotate(vec4 q, vec3 v) {
+    return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
+
+void main() {
+    // Blend the joint matrices. Joints are assumed to have no non-uniform scale, so the
+    // same matrix transforms normals.
+    uvec4 joints = in_joints + OBJ.joint_offset;
+    mat4 skin = in_weights.x * JOINTS.joints[joints.x]
+        + in_weights.y * JOINTS.joints[joints.y]
+        + in_weights.z * JOINTS.joints[joints.z]
+        + in_weights.w * JOINTS.joints[joints.w];
+    vec3 position = (skin * vec4(in_position, 1.0)).xyz;
+    vec3 normal = mat3(skin) * in_normal;
+
+    // Put the object in world coordinates.
+    vec3 world_position = rotate(OBJ.rotation, position * OBJ.scale.xyz) + OBJ.position.xyz;
+    vec3 world_normal = rotate(OBJ.rotation, normal / OBJ.scale.xyz);
+
+    mat4 projection = PROJECTION == 0 ? CAMERA.perspective : CAMERA.orthographic;
+    gl_Position = projection * CAMERA.view * vec4(world_position, 1.0);
+
+    out_normal = mat3(CAMERA.view) * world_normal;
+    out_color = in_color;
+}
@@ -0,0 +1,247 @@
+//! Frames in flight, one for each framebuffer, and destruction of resources deferred until
+//! the frames using them complete.
+//!
+//! Every frame has a serial number. A resource retired while a frame is recorded is destroyed
+//! once that frame has completed, which is known when a later frame waits for the fence of the
+//! same framebuffer. All frames are submitted to one queue, so they complete in order.
+
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use super::Vulkan;
+use super::SwapchainKHR;
+use super::{ CommandPool, CommandBuffer, Recording, Pending, WorkerPools, Workers };
+
+use std::sync::Arc;
+
+/// Resources which `Render::retire` destroys after the device finishes using them.
+pub trait Destroy {
+    /// # Safety
+    /// The device must have finished using the resource.
+    unsafe fn destroy(self, vulkan: &Vulkan);
+}
+
+pub(super) struct Frames {
+    /// Primary command buffers of all frames, recorded by the thread of the frame loop.
+    pool: CommandPool,
+    frames: Vec<Frame>,
+    /// Signaled by the next acquire, and swapped with the semaphore of the acquired frame.
+    spare_acquired: vk::Semaphore,
+    /// Serial of the frame which is recorded next or now.
+    serial: u64,
+    /// Resources with the serial of the frame when they were retired.
+    retired: Vec<(u64, Box<dyn Retired>)>,
+}
+
+struct Frame {
+    /// `None` while recording or pending.
+    command_buffer: Option<CommandBuffer>,
+    pending: Option<Pending>,
+    /// Record secondary command buffers which the command buffer of the frame executes.
+    workers: WorkerPools,
+    /// Signaled when the frame completes.
+    fence: vk::Fence,
+    /// Signaled when the swapchain image is acquired.
+    acquired: vk::Semaphore,
+    /// Signaled when the frame is rendered, and waited by the presentation.
+    rendered: vk::Semaphore,
+    /// Serial of the frame last submitted into this framebuffer.
+    serial: u64,
+}
+
+/// `Arc` of a resource whose type is erased.
+trait Retired {
+    /// Destroy the resource unless it is still shared, e.g. kept by a command buffer which is
+    /// not reset yet. Otherwise it is given back.
+    unsafe fn try_destroy(self: Box<Self>, vulkan: &Vulkan) -> Option<Box<dyn Retired>>;
+}
+
+impl Frames {
+    pub(super) unsafe fn new(vulkan: &Vulkan, framebuffer_count: usize, worker_count: usize) -> Self {
+        let device = &vulkan.device;
+        let mut pool = CommandPool::new(vulkan, vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+        pool.set_name(vulkan, "frame command pool");
+
+        // Worker threads are shared by the frames, and each frame has command pools for them.
+        let workers = Arc::new(Workers::new(worker_count));
+
+        let semaphore = || device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None).unwrap();
+        // Signaled, so the first wait of each frame returns immediately.
+        let info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+
+        let frames = pool
+            .allocate_primary(vulkan, framebuffer_count as u32)
+            .into_iter()
+            .enumerate()
+            .map(|(index, command_buffer)| {
+                let frame = Frame {
+                    command_buffer: Some(command_buffer),
+                    pending: None,
+                    workers: WorkerPools::new(vulkan, &workers),
+                    fence: device.create_fence(&info, None).unwrap(),
+                    acquired: semaphore(),
+                    rendered: semaphore(),
+                    serial: 0,
+                };
+                frame.set_name(vulkan, index);
+                frame
+            })
+            .collect();
+
+        Self {
+            pool,
+            frames,
+            spare_acquired: semaphore(),
+            serial: 1,
+            retired: Vec::new(),
+        }
+    }
+
+    /// Acquire the next swapchain image and wait until the previous frame of its framebuffer
+    /// completes, then destroy the resources retired up to that frame and begin the command
+    /// buffer of the frame. Returns `None` if the swapchain is out of date, and then it must
+    /// be recreated. A suboptimal swapchain is used until `end` reports it.
+    pub(super) unsafe fn begin<'a>(
+        &mut self,
+        vulkan: &'a Vulkan,
+        swapchain: &SwapchainKHR,
+    ) -> Option<(usize, CommandBuffer<Recording<'a>>)> {
+        let acquired = swapchain.loader
+            .acquire_next_image(swapchain.handle, !0, self.spare_acquired, vk::Fence::null());
+        let image_index = match acquired {
+            Ok((image_index, _suboptimal)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return None,
+            Err(result) => panic!("failed to acquire a swapchain image: {}", result),
+        };
+        let index = image_index as usize;
+        let frame = &mut self.frames[index];
+
+        vulkan.device.wait_for_fences(&[frame.fence], true, !0).unwrap();
+        vulkan.device.reset_fences(&[frame.fence]).unwrap();
+        // The semaphore of the previous frame has been waited for, so it can be acquired again.
+        std::mem::swap(&mut frame.acquired, &mut self.spare_acquired);
+
+        // Release resources kept by the command buffer before collecting them.
+        let command_buffer = match frame.pending.take() {
+            Some(pending) => pending.wait(vulkan).reset(vulkan),
+            None => frame.command_buffer.take().unwrap(),
+        };
+        // The command buffer above dropped the secondary command buffers executed by it.
+        frame.workers.reset(vulkan);
+        let completed = frame.serial;
+        self.collect(vulkan, completed);
+
+        Some((index, command_buffer.begin(vulkan, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)))
+    }
+
+    /// Submit the frame and present its swapchain image. Returns `true` if the swapchain is
+    /// out of date or suboptimal, and then it should be recreated.
+    pub(super) unsafe fn end(
+        &mut self,
+        vulkan: &Vulkan,
+        swapchain: &SwapchainKHR,
+        index: usize,
+        command_buffer: CommandBuffer<Recording>,
+    ) -> bool {
+        let frame = &mut self.frames[index];
+        let pending = command_buffer.end().submit(
+            vulkan,
+            &[(frame.acquired, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)],
+            &[frame.rendered],
+            frame.fence,
+        );
+        frame.pending = Some(pending);
+        frame.serial = self.serial;
+        self.serial += 1;
+
+        let swapchains = [swapchain.handle];
+        let image_indices = [index as u32];
+        let wait_semaphores = [frame.rendered];
+        let info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&wait_semaphores[..])
+            .swapchains(&swapchains[..])
+            .image_indices(&image_indices[..]);
+
+        match swapchain.loader.queue_present(vulkan.queue.handle, &info) {
+            Ok(suboptimal) => suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
+            Err(result) => panic!("failed to present a swapchain image: {}", result),
+        }
+    }
+
+    /// Worker threads of the frame being recorded into the framebuffer.
+    #[inline]
+    pub(super) fn workers(&mut self, index: usize) -> &mut WorkerPools { &mut self.frames[index].workers }
+
+    /// Destroy `resource` after the frame being recorded completes, and after command buffers
+    /// and others sharing it drop it.
+    pub(super) fn retire<T: Destroy + 'static>(&mut self, resource: Arc<T>) {
+        self.retired.push((self.serial, Box::new(resource)));
+    }
+
+    /// Destroy retired resources of frames up to `completed`.
+    unsafe fn collect(&mut self, vulkan: &Vulkan, completed: u64) {
+        let retired = std::mem::take(&mut self.retired);
+        self.retired = retired
+            .into_iter()
+            .filter_map(|(serial, resource)| {
+                if serial <= completed {
+                    resource.try_destroy(vulkan).map(|resource| (serial, resource))
+                } else {
+                    Some((serial, resource))
+                }
+            })
+            .collect();
+    }
+
+    /// Wait for all frames and destroy everything, including retired resources which are no
+    /// longer shared.
+    pub(super) unsafe fn destroy(mut self, vulkan: &Vulkan) {
+        let device = &vulkan.device;
+        let fences = self.frames.iter().map(|frame| frame.fence).collect::<Vec<_>>();
+        device.wait_for_fences(&fences[..], true, !0).unwrap();
+
+        // Drop command buffers with the resources they keep.
+        self.frames.iter_mut().for_each(|frame| {
+            frame.pending.take();
+            frame.command_buffer.take();
+        });
+        self.collect(vulkan, !0);
+        if !self.retired.is_empty() {
+            eprintln!("{} retired resources are still shared and leaked", self.retired.len());
+        }
+
+        self.frames.into_iter().for_each(|frame| {
+            frame.workers.destroy(vulkan);
+            device.destroy_fence(frame.fence, None);
+            device.destroy_semaphore(frame.acquired, None);
+            device.destroy_semaphore(frame.rendered, None);
+        });
+        device.destroy_semaphore(self.spare_acquired, None);
+        self.pool.destroy(vulkan);
+    }
+}
+
+impl Frame {
+    fn set_name(&self, vulkan: &Vulkan, index: usize) {
+        if let Some(command_buffer) = &self.command_buffer {
+            command_buffer.set_name(vulkan, &format!("frame command buffer {}", index));
+        }
+        vulkan.set_object_name(self.fence, &format!("frame fence {}", index));
+        // Acquired semaphores move between frames.
+        vulkan.set_object_name(self.acquired, "image acquired semaphore");
+        vulkan.set_object_name(self.rendered, &format!("frame rendered semaphore {}", index));
+    }
+}
+
+impl<T: Destroy + 'static> Retired for Arc<T> {
+    unsafe fn try_destroy(self: Box<Self>, vulkan: &Vulkan) -> Option<Box<dyn Retired>> {
+        match Arc::try_unwrap(*self) {
+            Ok(resource) => {
+                resource.destroy(vulkan);
+                None
+            }
+            Err(shared) => Some(Box::new(shared)),
+        }
+    }
+}
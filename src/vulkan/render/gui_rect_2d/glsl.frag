@@ -2,13 +2,14 @@
 
 // input
 layout(location = 0) in vec2 tex_xy;
+layout(location = 1) in vec4 color;
 
 // output
 layout(location = 0) out vec4 out_color;
 
 // Descriptor Set
-layout(binding = 0, set = 1) uniform sampler2D texture_image;
+layout(set = 0, binding = 0) uniform sampler2D texture_image;
 
 void main() {
-    out_color = texture(texture_image, tex_xy);
-}
\ No newline at end of file
+    out_color = texture(texture_image, tex_xy) * color;
+}
@@ -1,26 +1,28 @@
 # version 450
 
-// vertices
-// TRIANGLE FAN
-vec2 VERTICES[4] = vec2[] (
-    vec2 (-1.0, -1.0),
-    vec2 (-1.0,  1.0),
-    vec2 ( 1.0,  1.0),
-    vec2 ( 1.0, -1.0)
+// Corners of the rectangle as a TRIANGLE STRIP, from the top left (0, 0) to the bottom right (1, 1).
+const vec2 CORNERS[4] = vec2[] (
+    vec2(0.0, 0.0),
+    vec2(0.0, 1.0),
+    vec2(1.0, 0.0),
+    vec2(1.0, 1.0)
 );
 
-// input
-layout(location = 0) in vec2 in_tex;
-
 // output
 layout(location = 0) out vec2 tex_xy;
+layout(location = 1) out vec4 color;
 
-layout(set = 0, binding = 0) uniform Something {
-    vec2 delta;
-    vec2 scale;
-} A;
+layout(push_constant) uniform Rect {
+    // xy: top left, zw: bottom right in normalized device coordinates.
+    vec4 position;
+    // xy: top left, zw: bottom right of the region of the texture.
+    vec4 tex;
+    vec4 color;
+} rect;
 
 void main() {
-    VERTICES[gl_VertexIndex] * A.scale + A.delta;
-    tex_xy = in_tex;
-}
\ No newline at end of file
+    vec2 corner = CORNERS[gl_VertexIndex];
+    gl_Position = vec4(mix(rect.position.xy, rect.position.zw, corner), 0.0, 1.0);
+    tex_xy = mix(rect.tex.xy, rect.tex.zw, corner);
+    color = rect.color;
+}
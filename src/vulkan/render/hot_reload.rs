@@ -0,0 +1,105 @@
+//! Reloading of shaders while running, enabled by the `hot_reload` feature.
+//!
+//! SPIR-V is read from `OUT_DIR` instead of being embedded, and sources under
+//! `src/vulkan/render/` are polled by their modified times and recompiled.
+
+use super::compile::{ self, Source };
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+
+const SHADER_ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/vulkan/render");
+const OUT_DIR: &str = env!("OUT_DIR");
+/// Same as the one of `build.rs`. Setting it while running overrides `BUILD_DEFINES`.
+const DEFINES_VAR: &str = "SHADER_DEFINES";
+/// The value of `DEFINES_VAR` which `build.rs` compiled the SPIR-V in `OUT_DIR` with.
+const BUILD_DEFINES: Option<&str> = option_env!("SHADER_DEFINES");
+
+pub struct HotReload {
+    stages: Vec<Stage>,
+}
+
+struct Stage {
+    source: Source,
+    /// Modified times of the source and files it includes.
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+    spirv: Vec<u8>,
+}
+
+impl HotReload {
+    pub fn new() -> Self {
+        let shader_root = Path::new(SHADER_ROOT);
+        let stages = compile::discover(shader_root)
+            .unwrap()
+            .into_iter()
+            .map(|source| {
+                let output = Path::new(OUT_DIR).join(&source.shader).join(source.spv_name);
+                let spirv = fs::read(output).unwrap_or_default();
+                let modified = modified_times(&source, shader_root);
+                Stage { source, modified, spirv }
+            })
+            .collect();
+
+        Self { stages }
+    }
+
+    /// The latest SPIR-V which compiled successfully.
+    pub fn spirv(&self, shader: &str, stage: &str) -> Option<&[u8]> {
+        self.stages
+            .iter()
+            .find(|s| s.source.shader == shader && s.source.stage == stage)
+            .filter(|s| !s.spirv.is_empty())
+            .map(|s| &s.spirv[..])
+    }
+
+    /// Recompile sources modified since the last poll and return names of shaders which
+    /// have new SPIR-V. Errors of the compiler are printed and the previous SPIR-V is kept.
+    pub fn poll(&mut self) -> Vec<String> {
+        let shader_root = Path::new(SHADER_ROOT);
+        let defines = std::env::var(DEFINES_VAR)
+            .ok()
+            .or_else(|| BUILD_DEFINES.map(String::from))
+            .unwrap_or_default();
+        let defines = compile::parse_defines(&defines);
+        let mut changed: Vec<String> = Vec::new();
+
+        self.stages
+            .iter_mut()
+            .for_each(|stage| {
+                let modified = modified_times(&stage.source, shader_root);
+                if modified == stage.modified {
+                    return;
+                }
+                stage.modified = modified;
+
+                match compile::compile(&stage.source, shader_root, &defines[..]) {
+                    Ok(spirv) => {
+                        stage.spirv = spirv;
+                        if !changed.contains(&stage.source.shader) {
+                            changed.push(stage.source.shader.clone());
+                        }
+                    },
+                    Err(err) => eprintln!(
+                        "\u{001b}[31mShader Compile Error:\u{001b}[m {}\n{:?}",
+                        stage.source.path.display(),
+                        err,
+                    ),
+                }
+            });
+
+        changed
+    }
+}
+
+fn modified_times(source: &Source, shader_root: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut paths = compile::dependencies(&source.path, shader_root);
+    paths.insert(0, source.path.clone());
+    paths
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
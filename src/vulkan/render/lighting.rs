@@ -0,0 +1,394 @@
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use crate::linear_algebra::{ XYZ, XYZW };
+
+use super::Vulkan;
+use super::Render;
+use super::Shader;
+use super::ReflectErr;
+use super::GraphicsPipelineBuilder;
+use super::DescriptorWriter;
+use super::Framebuffers;
+use super::HostBuffer;
+use super::Matrix;
+use super::shadow::ShadowMap;
+
+use std::mem;
+use std::ptr;
+
+/// Maximum number of lights evaluated in the lighting subpass.
+pub const MAX_LIGHTS: usize = 256;
+
+/// Kinds of light. These values must match the defines in `lighting/glsl.frag`.
+const DIRECTIONAL: f32 = 0.0;
+const POINT: f32 = 1.0;
+const SPOT: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Light {
+    Directional {
+        direction: XYZ<f32>,
+        color: XYZ<f32>,
+        intensity: f32,
+    },
+    Point {
+        position: XYZ<f32>,
+        color: XYZ<f32>,
+        intensity: f32,
+        range: f32,
+    },
+    /// Angles are half angles of the cones in radians.
+    Spot {
+        position: XYZ<f32>,
+        direction: XYZ<f32>,
+        color: XYZ<f32>,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// Index of the slot and its generation. The generation of a slot increases when its light is
+/// removed, so ids of removed lights never refer to a light added later into the same slot.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct LightId {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    generation: u32,
+    light: Option<Light>,
+}
+
+/// Matrices of the camera used to reconstruct positions from the depth attachment.
+#[derive(Copy, Clone, Debug)]
+pub struct ViewMatrices {
+    pub view: Matrix,
+    pub inverse_view: Matrix,
+    pub inverse_projection: Matrix,
+}
+
+/// Lights and the storage buffer read by the lighting subpass.
+pub struct Lights {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    ambient: XYZ<f32>,
+    /// The directional light which casts shadows.
+    sun: Option<LightId>,
+    buffer: HostBuffer,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+/// Layout of `Lights` in `lighting/glsl.frag` (std430).
+#[repr(C)]
+struct Header {
+    view: Matrix,
+    inverse_view: Matrix,
+    inverse_projection: Matrix,
+    ambient: XYZW<f32>,
+    /// (width, height, 1 / width, 1 / height)
+    viewport: XYZW<f32>,
+    count: u32,
+    _padding: [u32; 3],
+}
+
+/// Layout of `Light` in `lighting/glsl.frag` (std430).
+/// `attenuation.z` is 1.0 if the light casts shadows.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct RawLight {
+    position: XYZW<f32>,
+    direction: XYZW<f32>,
+    color: XYZW<f32>,
+    attenuation: XYZW<f32>,
+}
+
+impl Lights {
+    /// Create a light buffer which has one region for each framebuffer.
+    pub(super) unsafe fn new(vulkan: &Vulkan, framebuffer_count: usize) -> Self {
+        let size = mem::size_of::<Header>() + mem::size_of::<RawLight>() * MAX_LIGHTS;
+        let buffer = HostBuffer::new(
+            vulkan,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            size as u64,
+            framebuffer_count,
+        );
+        buffer.set_name(vulkan, "light buffer");
+
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            ambient: XYZ::new(0.03, 0.03, 0.03),
+            sun: None,
+            buffer,
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::new(),
+        }
+    }
+
+    /// Rewrite depth and G-Buffers of recreated framebuffers into the descriptor sets.
+    /// # Safety
+    /// The device must have finished using the descriptor sets.
+    pub(super) unsafe fn write_input_attachments(&self, vulkan: &Vulkan, framebuffers: &Framebuffers) {
+        self.descriptor_sets
+            .iter()
+            .zip(framebuffers.views.iter())
+            .for_each(|(set, views)| Self::input_attachment_writer(*set, views).write(vulkan));
+    }
+
+    /// Write depth and G-Buffers at bindings 0 to 3.
+    fn input_attachment_writer(set: vk::DescriptorSet, views: &[vk::ImageView; 5]) -> DescriptorWriter {
+        let input_attachments = [
+            (Render::DEPTH_ATTACHMENT_INDEX, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+            (Render::NORMAL_G_BUFFER_ATTACHMENT_INDEX, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (Render::ALBEDO_G_BUFFER_ATTACHMENT_INDEX, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (Render::MATERIAL_G_BUFFER_ATTACHMENT_INDEX, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        ];
+        input_attachments
+            .iter()
+            .enumerate()
+            .fold(DescriptorWriter::new(set), |writer, (binding, (attachment_index, layout))| {
+                writer.input_attachment(binding as u32, &views[*attachment_index as usize], *layout)
+            })
+    }
+
+    /// Allocate a descriptor set for each framebuffer and write depth, G-Buffers,
+    /// the light buffer and the shadow map.
+    pub(super) unsafe fn write_descriptor_sets(
+        &mut self,
+        vulkan: &Vulkan,
+        framebuffers: &Framebuffers,
+        shadow_map: &ShadowMap,
+        set_layout: vk::DescriptorSetLayout,
+    ) {
+        let device = &vulkan.device;
+        let count = framebuffers.views.len() as u32;
+
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::INPUT_ATTACHMENT)
+                .descriptor_count(count * 4)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(count)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(count)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(count)
+                .build(),
+        ];
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count)
+            .pool_sizes(&pool_sizes[..]);
+        self.descriptor_pool = device.create_descriptor_pool(&info, None).unwrap();
+
+        let set_layouts = vec![set_layout; count as usize];
+        let info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts[..]);
+        self.descriptor_sets = device.allocate_descriptor_sets(&info).unwrap();
+
+        self.descriptor_sets
+            .iter()
+            .zip(framebuffers.views.iter())
+            .enumerate()
+            .for_each(|(index, (set, views))| {
+                let writer = Self::input_attachment_writer(*set, views);
+
+                let shadow_map_info = shadow_map.descriptor_image_info();
+                writer
+                    .storage_buffer(4, &self.buffer.descriptor_info(index))
+                    .image(
+                        5,
+                        vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        &shadow_map_info.image_view,
+                        shadow_map_info.sampler,
+                        shadow_map_info.image_layout,
+                    )
+                    .uniform_buffer(6, &shadow_map.descriptor_buffer_info(index))
+                    .write(vulkan);
+            });
+    }
+
+    pub fn add(&mut self, light: Light) -> Option<LightId> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None if self.slots.len() < MAX_LIGHTS => {
+                self.slots.push(Slot { generation: 0, light: None });
+                self.slots.len() - 1
+            },
+            None => return None,
+        };
+        let slot = &mut self.slots[index];
+        slot.light = Some(light);
+        Some(LightId { index, generation: slot.generation })
+    }
+
+    /// Returns `false` if the light has been removed.
+    pub fn update(&mut self, id: LightId, light: Light) -> bool {
+        match self.get_mut(id) {
+            Some(slot) => {
+                *slot = light;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        self.get(id)?;
+        if self.sun == Some(id) { self.sun = None; }
+
+        let slot = &mut self.slots[id.index];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        slot.light.take()
+    }
+
+    /// Set the directional light which casts shadows.
+    /// Returns `false` and keeps the current sun if the light has been removed.
+    pub fn set_sun(&mut self, id: Option<LightId>) -> bool {
+        if let Some(id) = id {
+            match self.get(id) {
+                Some(light) => debug_assert!(
+                    matches!(light, Light::Directional { .. }),
+                    "Only a directional light can be the sun.",
+                ),
+                None => return false,
+            }
+        }
+        self.sun = id;
+        true
+    }
+
+    /// Direction of the sun light if it exists.
+    pub fn sun_direction(&self) -> Option<XYZ<f32>> {
+        match self.sun.and_then(|id| self.get(id)) {
+            Some(Light::Directional { direction, .. }) => Some(*direction),
+            _ => None,
+        }
+    }
+
+    /// Returns `None` if the light has been removed.
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.slots
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.light.as_ref())
+    }
+
+    fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.slots
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.light.as_mut())
+    }
+
+    #[inline]
+    pub fn set_ambient(&mut self, ambient: XYZ<f32>) { self.ambient = ambient; }
+
+    /// Write lights to the region of the framebuffer.
+    /// The region must not be in use by the device.
+    pub(super) unsafe fn upload(
+        &self,
+        framebuffer_index: usize,
+        matrices: &ViewMatrices,
+        extent: vk::Extent2D,
+    ) {
+        let region = self.buffer.region(framebuffer_index);
+        let lights = region.add(mem::size_of::<Header>()) as *mut RawLight;
+
+        let count = self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let id = LightId { index, generation: slot.generation };
+                slot.light.as_ref().map(|light| (id, light))
+            })
+            .fold(0, |count, (id, light)| {
+                let mut raw = RawLight::from(light);
+                if self.sun == Some(id) { raw.attenuation.z = 1.0; }
+                ptr::write(lights.add(count), raw);
+                count + 1
+            });
+
+        let (width, height) = (extent.width as f32, extent.height as f32);
+        let header = Header {
+            view: matrices.view,
+            inverse_view: matrices.inverse_view,
+            inverse_projection: matrices.inverse_projection,
+            ambient: XYZW::new(self.ambient.x, self.ambient.y, self.ambient.z, 1.0),
+            viewport: XYZW::new(width, height, 1.0 / width, 1.0 / height),
+            count: count as u32,
+            _padding: [0; 3],
+        };
+        ptr::write(region as *mut Header, header);
+    }
+
+    #[inline]
+    pub(super) fn descriptor_set(&self, framebuffer_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[framebuffer_index]
+    }
+
+    pub(super) unsafe fn destroy(self, vulkan: &Vulkan) {
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            vulkan.device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+        self.buffer.destroy(vulkan);
+    }
+}
+
+impl From<&Light> for RawLight {
+    fn from(light: &Light) -> Self {
+        let xyzw = |xyz: XYZ<f32>, w: f32| XYZW::new(xyz.x, xyz.y, xyz.z, w);
+        let zero = XYZ::new(0.0, 0.0, 0.0);
+
+        match *light {
+            Light::Directional { direction, color, intensity } => RawLight {
+                position: xyzw(zero, DIRECTIONAL),
+                direction: xyzw(direction, 0.0),
+                color: xyzw(color, intensity),
+                attenuation: XYZW::new(0.0, 0.0, 0.0, 0.0),
+            },
+            Light::Point { position, color, intensity, range } => RawLight {
+                position: xyzw(position, POINT),
+                direction: xyzw(zero, 0.0),
+                color: xyzw(color, intensity),
+                attenuation: XYZW::new(range, 0.0, 0.0, 0.0),
+            },
+            Light::Spot {
+                position, direction, color, intensity, range, inner_angle, outer_angle,
+            } => RawLight {
+                position: xyzw(position, SPOT),
+                direction: xyzw(direction, inner_angle.cos()),
+                color: xyzw(color, intensity),
+                attenuation: XYZW::new(range, outer_angle.cos(), 0.0, 0.0),
+            },
+        }
+    }
+}
+
+pub unsafe fn load(vulkan: &Vulkan, render: &Render, subpass: u32) -> Result<Shader, ReflectErr> {
+    // Full screen quad is generated from gl_VertexIndex.
+    GraphicsPipelineBuilder::new()
+        .name("lighting")
+        .vertex_shader(spirv!(render, "lighting", "vert"))
+        .fragment_shader(spirv!(render, "lighting", "frag"))
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+        .opaque_color_attachments(1)
+        .build(vulkan, render.render_pass, subpass, render.pipeline_cache)
+}
@@ -0,0 +1,183 @@
+#version 450
+
+// Kinds of light. Must match `lighting::Light`.
+#define DIRECTIONAL 0
+#define POINT 1
+#define SPOT 2
+
+// Specular reflectance of dielectrics.
+const vec3 DIELECTRIC_SPECULAR = vec3(0.04);
+
+// Must match `shadow::CASCADE_COUNT`.
+#define CASCADE_COUNT 4
+// Offset along normals applied before sampling the shadow map, against shadow acne.
+const float NORMAL_OFFSET = 0.05;
+
+struct Light {
+    // xyz: position in world coordinates, w: kind of the light.
+    vec4 position;
+    // xyz: direction the light travels, w: cosine of the inner cone angle.
+    vec4 direction;
+    // rgb: color, a: intensity.
+    vec4 color;
+    // x: range, y: cosine of the outer cone angle, z: 1.0 if the light casts shadows.
+    vec4 attenuation;
+};
+
+// output
+layout(location = 0) out vec4 out_color;
+
+// Depth and G-Buffers
+layout(set = 0, binding = 0, input_attachment_index = 0) uniform subpassInput DEPTH;
+layout(set = 0, binding = 1, input_attachment_index = 1) uniform subpassInput NORMAL;
+layout(set = 0, binding = 2, input_attachment_index = 2) uniform subpassInput ALBEDO;
+layout(set = 0, binding = 3, input_attachment_index = 3) uniform subpassInput MATERIAL;
+
+// Light list and the camera
+layout(set = 0, binding = 4) readonly buffer Lights {
+    mat4 view;
+    mat4 inverse_view;
+    mat4 inverse_projection;
+    // rgb: ambient color.
+    vec4 ambient;
+    // (width, height, 1 / width, 1 / height)
+    vec4 viewport;
+    uint count;
+    Light lights[];
+} LIGHTS;
+
+// Cascaded shadow map of the sun.
+layout(set = 0, binding = 5) uniform sampler2DArrayShadow SHADOW_MAP;
+layout(set = 0, binding = 6) uniform Shadow {
+    mat4 light_space[CASCADE_COUNT];
+    // Far distances of the cascades along the view direction.
+    vec4 splits;
+    vec4 view_direction;
+} SHADOW;
+
+// Inverse of the octahedral encoding in `dim3/glsl.frag`.
+vec3 decode_normal(vec2 encoded) {
+    vec3 normal = vec3(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    float t = clamp(-normal.z, 0.0, 1.0);
+    normal.xy += vec2(normal.x >= 0.0 ? -t : t, normal.y >= 0.0 ? -t : t);
+    return normalize(normal);
+}
+
+// Position in view coordinates from the depth of this fragment.
+vec3 reconstruct_position(float depth) {
+    vec2 ndc = gl_FragCoord.xy * LIGHTS.viewport.zw * 2.0 - 1.0;
+    vec4 position = LIGHTS.inverse_projection * vec4(ndc, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// 1.0 if lit, 0.0 if in shadow. Filtered by 3x3 PCF.
+// Position and normal are in world coordinates.
+float shadow(vec3 position, vec3 normal) {
+    vec3 eye = LIGHTS.inverse_view[3].xyz;
+    float depth = dot(position - eye, SHADOW.view_direction.xyz);
+    if (depth > SHADOW.splits[CASCADE_COUNT - 1]) {
+        return 1.0;
+    }
+
+    int cascade = 0;
+    for (int i = 0; i < CASCADE_COUNT - 1; i++) {
+        if (depth > SHADOW.splits[i]) {
+            cascade = i + 1;
+        }
+    }
+
+    vec4 light_space = SHADOW.light_space[cascade] * vec4(position + normal * NORMAL_OFFSET, 1.0);
+    vec3 coord = light_space.xyz / light_space.w;
+    vec2 uv = coord.xy * 0.5 + 0.5;
+    vec2 texel = 1.0 / vec2(textureSize(SHADOW_MAP, 0).xy);
+
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(x, y) * texel;
+            lit += texture(SHADOW_MAP, vec4(uv + offset, float(cascade), coord.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// Smooth falloff that reaches zero at the range of the light.
+float distance_attenuation(float distance, float range) {
+    float ratio = distance / range;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// Blinn-Phong whose shininess and specular color are derived from roughness and metallic.
+vec3 blinn_phong(
+    vec3 to_light,
+    vec3 to_eye,
+    vec3 normal,
+    vec3 albedo,
+    float roughness,
+    float metallic,
+    vec3 radiance
+) {
+    float alpha = roughness * roughness;
+    float shininess = max(2.0 / (alpha * alpha + 0.0001) - 2.0, 1.0);
+    vec3 specular_color = mix(DIELECTRIC_SPECULAR, albedo, metallic);
+    vec3 diffuse_color = albedo * (1.0 - metallic);
+
+    float diffuse = max(dot(normal, to_light), 0.0);
+    vec3 half_vector = normalize(to_light + to_eye);
+    float specular = diffuse > 0.0 ? pow(max(dot(normal, half_vector), 0.0), shininess) : 0.0;
+    // Normalization factor keeps the energy of the highlight while shininess changes.
+    specular *= (shininess + 8.0) / 8.0;
+
+    return (diffuse_color * diffuse + specular_color * specular * diffuse) * radiance;
+}
+
+void main() {
+    // Nothing is drawn where the depth is still cleared, which keeps the clear color there.
+    float depth = subpassLoad(DEPTH).r;
+    if (depth == 1.0) {
+        discard;
+    }
+
+    // Lighting is done in view coordinates.
+    vec3 position = reconstruct_position(depth);
+    vec3 normal = decode_normal(subpassLoad(NORMAL).xy);
+    vec3 albedo = subpassLoad(ALBEDO).rgb;
+    vec4 material = subpassLoad(MATERIAL);
+    float roughness = material.r;
+    float metallic = material.g;
+    vec3 to_eye = normalize(-position);
+
+    vec3 color = LIGHTS.ambient.rgb * albedo;
+    for (uint i = 0; i < LIGHTS.count; i++) {
+        Light light = LIGHTS.lights[i];
+        uint kind = uint(light.position.w);
+        vec3 radiance = light.color.rgb * light.color.a;
+        vec3 direction = normalize(mat3(LIGHTS.view) * light.direction.xyz);
+
+        vec3 to_light;
+        if (kind == DIRECTIONAL) {
+            to_light = -direction;
+            if (light.attenuation.z > 0.0) {
+                vec3 world_position = (LIGHTS.inverse_view * vec4(position, 1.0)).xyz;
+                vec3 world_normal = mat3(LIGHTS.inverse_view) * normal;
+                radiance *= shadow(world_position, world_normal);
+            }
+        } else {
+            vec3 light_position = (LIGHTS.view * vec4(light.position.xyz, 1.0)).xyz;
+            vec3 delta = light_position - position;
+            float distance = length(delta);
+            to_light = delta / distance;
+            radiance *= distance_attenuation(distance, light.attenuation.x);
+
+            if (kind == SPOT) {
+                float cos_angle = dot(-to_light, direction);
+                radiance *= smoothstep(light.attenuation.y, light.direction.w, cos_angle);
+            }
+        }
+
+        color += blinn_phong(to_light, to_eye, normal, albedo, roughness, metallic, radiance);
+    }
+
+    out_color = vec4(color, 1.0);
+}
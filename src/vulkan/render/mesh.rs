@@ -0,0 +1,256 @@
+//! Indexed meshes in device local memory and their textured pipeline of the G-Buffer subpass.
+//!
+//! Uploads are recorded into a command buffer of the caller like `Texture`, and `Recorder`
+//! records draws of meshes with their materials and transforms. Draws keep the meshes, which
+//! are shared by `Arc`, until the command buffer is reset.
+
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use super::Vulkan;
+use super::Render;
+use super::{ CommandBuffer, Recording, BufferResource };
+use super::Destroy;
+use super::Shader;
+use super::ReflectErr;
+use super::HostBuffer;
+use super::Object;
+use super::Projection;
+use super::{ GraphicsPipelineBuilder, VertexInput };
+
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+/// Vertices followed by `u32` indices in one device local buffer.
+pub struct MeshBuffer {
+    handle: vk::Buffer,
+    memory: vk::DeviceMemory,
+    size: u64,
+    index_offset: u64,
+    index_count: u32,
+    /// Until the upload completes.
+    staging: Option<HostBuffer>,
+}
+
+/// Records draws of meshes into a command buffer in the G-Buffer subpass.
+/// Materials are rebound only when they change.
+pub struct Recorder<'a, 'v> {
+    command_buffer: &'a mut CommandBuffer<Recording<'v>>,
+    shader: &'a Shader,
+    material: vk::DescriptorSet,
+}
+
+impl MeshBuffer {
+    /// Create a buffer and record the upload of the mesh into the command buffer.
+    /// It can be read as vertices and indices after the command buffer.
+    /// # Safety
+    /// Call `finish_upload` after the command buffer completes.
+    pub unsafe fn new<V: VertexInput + Copy>(
+        vulkan: &Vulkan,
+        command_buffer: vk::CommandBuffer,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> Self {
+        let device = &vulkan.device;
+        let vertex_size = mem::size_of_val(vertices) as u64;
+        // Offsets of index buffers must be multiples of the index size.
+        let index_offset = vertex_size.div_ceil(4) * 4;
+        let size = index_offset + mem::size_of_val(indices) as u64;
+
+        let staging = HostBuffer::new(vulkan, vk::BufferUsageFlags::TRANSFER_SRC, size, 1);
+        let mapped = staging.region(0);
+        ptr::copy_nonoverlapping(vertices.as_ptr() as *const u8, mapped, vertex_size as usize);
+        let mapped_indices = mapped.add(index_offset as usize) as *mut u32;
+        ptr::copy_nonoverlapping(indices.as_ptr(), mapped_indices, indices.len());
+
+        let info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .queue_family_indices(&[]);
+        let handle = device.create_buffer(&info, None).unwrap();
+
+        let requirements = device.get_buffer_memory_requirements(handle);
+        let memory_type_index = vulkan.physical_device
+            .memory_type_index(requirements.memory_type_bits, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .unwrap();
+        let info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_type_index)
+            .allocation_size(requirements.size);
+        let memory = device.allocate_memory(&info, None).unwrap();
+        device.bind_buffer_memory(handle, memory, 0).unwrap();
+
+        let region = vk::BufferCopy::builder().src_offset(0).dst_offset(0).size(size).build();
+        device.cmd_copy_buffer(command_buffer, staging.handle, handle, &[region]);
+
+        let barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(handle)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[barrier],
+            &[],
+        );
+
+        Self {
+            handle,
+            memory,
+            size,
+            index_offset,
+            index_count: indices.len() as u32,
+            staging: Some(staging),
+        }
+    }
+
+    /// Retire the staging buffer, which is destroyed after the frame being recorded completes.
+    /// # Safety
+    /// The command buffer passed to `new` must be the one of the frame being recorded, or
+    /// have completed.
+    pub unsafe fn finish_upload(&mut self, render: &mut Render) {
+        if let Some(staging) = self.staging.take() {
+            render.retire(Arc::new(staging));
+        }
+    }
+
+    pub fn set_name(&self, vulkan: &Vulkan, name: &str) {
+        vulkan.set_object_name(self.handle, name);
+        vulkan.set_object_name(self.memory, &format!("{} memory", name));
+    }
+
+    #[inline]
+    pub fn index_count(&self) -> u32 { self.index_count }
+
+    /// Record binding the vertices to binding 0 and the indices, e.g. for the shadow pass.
+    pub fn bind(self: &Arc<Self>, command_buffer: &mut CommandBuffer<Recording>) {
+        command_buffer
+            .bind_vertex_buffers(0, slice::from_ref(self))
+            .bind_index_buffer(self, self.index_offset, vk::IndexType::UINT32);
+    }
+
+    /// Record drawing all triangles. Bind it first.
+    pub fn draw(&self, command_buffer: &mut CommandBuffer<Recording>) {
+        command_buffer.draw_indexed(self.index_count, 1, 0, 0, 0);
+    }
+
+    /// # Safety
+    /// The device must have finished using the buffer.
+    pub unsafe fn destroy(self, vulkan: &Vulkan) {
+        if let Some(staging) = self.staging {
+            staging.destroy(vulkan);
+        }
+        vulkan.device.destroy_buffer(self.handle, None);
+        vulkan.device.free_memory(self.memory, None);
+    }
+}
+
+impl Destroy for MeshBuffer {
+    #[inline]
+    unsafe fn destroy(self, vulkan: &Vulkan) { MeshBuffer::destroy(self, vulkan) }
+}
+
+impl<'a, 'v> Recorder<'a, 'v> {
+    /// Record binding the pipeline of `Render::load_mesh`, the viewport and the camera of
+    /// the framebuffer. The command buffer must be in the G-Buffer subpass.
+    pub fn begin(
+        render: &Render,
+        command_buffer: &'a mut CommandBuffer<Recording<'v>>,
+        shader: &'a Shader,
+        framebuffer_index: usize,
+    ) -> Self {
+        let camera = render.dim3_descriptor_set(framebuffer_index);
+        Self::with_camera(command_buffer, shader, camera, render.swapchain.extent)
+    }
+
+    /// `begin` without `Render`, which worker threads do not share.
+    pub(super) fn with_camera(
+        command_buffer: &'a mut CommandBuffer<Recording<'v>>,
+        shader: &'a Shader,
+        camera: vk::DescriptorSet,
+        extent: vk::Extent2D,
+    ) -> Self {
+        command_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, shader.pipeline);
+        Render::set_viewport(command_buffer, extent);
+        command_buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            shader.pipeline_layout,
+            0,
+            &[camera],
+            &[],
+        );
+
+        Self { command_buffer, shader, material: vk::DescriptorSet::null() }
+    }
+
+    #[inline]
+    pub fn command_buffer(&mut self) -> &mut CommandBuffer<Recording<'v>> { self.command_buffer }
+
+    /// Record binding a set 1 of the shader, allocated by `Render::allocate_descriptor_set`,
+    /// unless it is already bound.
+    pub fn bind_material(&mut self, material: vk::DescriptorSet) {
+        if material == self.material {
+            return;
+        }
+
+        self.command_buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            self.shader.pipeline_layout,
+            1,
+            &[material],
+            &[],
+        );
+        self.material = material;
+    }
+
+    /// Record drawing the mesh with the transform and the bound material.
+    pub fn draw(&mut self, mesh: &Arc<MeshBuffer>, object: &Object) {
+        object.push(self.command_buffer, self.shader);
+        mesh.bind(self.command_buffer);
+        mesh.draw(self.command_buffer);
+    }
+}
+
+impl BufferResource for MeshBuffer {
+    #[inline]
+    fn buffer_handle(&self) -> vk::Buffer { self.handle }
+    #[inline]
+    fn buffer_offset(&self) -> u64 { 0 }
+    #[inline]
+    fn buffer_size(&self) -> u64 { self.size }
+}
+
+/// `V` begins with a position of `XYZ<f32>`, a normal of `XYZ<f32>`, a tangent of
+/// `XYZW<f32>` and texture coordinates of `XY<f32>`.
+pub unsafe fn load<V: VertexInput>(
+    vulkan: &Vulkan,
+    render: &Render,
+    subpass: u32,
+    projection: Projection,
+) -> Result<Shader, ReflectErr> {
+    // Same transforms, culling and attachments as dim3.
+    GraphicsPipelineBuilder::new()
+        .name("mesh")
+        .vertex_shader(spirv!(render, "mesh", "vert"))
+        .fragment_shader(spirv!(render, "mesh", "frag"))
+        .specialization(vk::ShaderStageFlags::VERTEX, 0, projection as i32)
+        .vertex_attributes_of(V::stride(), &V::attributes()[..4])
+        .cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::CLOCKWISE)
+        .depth_test(true, vk::CompareOp::LESS)
+        .opaque_color_attachments(3)
+        .build(vulkan, render.render_pass, subpass, render.pipeline_cache)
+}
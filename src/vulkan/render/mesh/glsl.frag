@@ -0,0 +1,34 @@
+# version 450
+
+layout(location = 0) in vec3 in_normal;
+layout(location = 1) in vec2 in_uv;
+
+// Material
+layout(binding = 0, set = 1) uniform sampler2D ALBEDO;
+
+// G-Buffers. Positions are reconstructed from depth.
+layout(location = 0) out vec2 out_normal;
+layout(location = 1) out vec4 out_albedo;
+layout(location = 2) out vec4 out_material;
+
+// Specialization Constants:
+layout(constant_id = 1) const float ROUGHNESS = 0.5;
+layout(constant_id = 2) const float METALLIC = 0.0;
+layout(constant_id = 3) const float MATERIAL_ID = 0.0;
+
+// Octahedral encoding of a unit vector into [-1, 1]^2.
+vec2 encode_normal(vec3 normal) {
+    normal /= abs(normal.x) + abs(normal.y) + abs(normal.z);
+    vec2 encoded = normal.xy;
+    if (normal.z < 0.0) {
+        vec2 sign_not_zero = vec2(encoded.x >= 0.0 ? 1.0 : -1.0, encoded.y >= 0.0 ? 1.0 : -1.0);
+        encoded = (1.0 - abs(encoded.yx)) * sign_not_zero;
+    }
+    return encoded;
+}
+
+void main() {
+    out_normal = encode_normal(normalize(in_normal));
+    out_albedo = texture(ALBEDO, in_uv);
+    out_material = vec4(ROUGHNESS, METALLIC, MATERIAL_ID / 255.0, 0.0);
+}
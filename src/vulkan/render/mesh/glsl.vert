@@ -0,0 +1,51 @@
+# version 450
+
+// input
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+// w: sign of the bitangent. Unused until normal maps.
+layout(location = 2) in vec4 in_tangent;
+layout(location = 3) in vec2 in_uv;
+
+// output
+// Normal vectors are in view coordinates.
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec2 out_uv;
+
+
+// Camera uniform, the same set as dim3.
+layout(binding = 0, set = 0) uniform Camera {
+    mat4 view;
+    mat4 perspective;
+    mat4 orthographic;
+} CAMERA;
+
+// Transform of each object in push constants.
+layout(push_constant) uniform Obj {
+    // xyz: position in world coordinates.
+    vec4 position;
+    // Unit quaternion (x, y, z, w).
+    vec4 rotation;
+    // xyz: scale.
+    vec4 scale;
+} OBJ;
+
+// Specialization Constants:
+// 0: perspective, 1: orthographic
+layout(constant_id = 0) const int PROJECTION = 0;
+
+vec3 rotate(vec4 q, vec3 v) {
+    return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
+
+void main() {
+    // Put the object in world coordinates.
+    vec3 world_position = rotate(OBJ.rotation, in_position * OBJ.scale.xyz) + OBJ.position.xyz;
+    vec3 world_normal = rotate(OBJ.rotation, in_normal / OBJ.scale.xyz);
+
+    mat4 projection = PROJECTION == 0 ? CAMERA.perspective : CAMERA.orthographic;
+    gl_Position = projection * CAMERA.view * vec4(world_position, 1.0);
+
+    out_normal = mat3(CAMERA.view) * world_normal;
+    out_uv = in_uv;
+}
@@ -0,0 +1,479 @@
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use crate::linear_algebra::{ XY, XYZ, XYZW };
+
+use super::Vulkan;
+use super::Render;
+use super::Shader;
+use super::reflect::{ self, Layout, ReflectErr };
+
+use std::ffi::CString;
+use std::mem;
+
+/// Types which can be a vertex attribute.
+pub trait VertexFormat {
+    const FORMAT: vk::Format;
+}
+
+/// Vertex types which describe their own layout in a vertex buffer.
+/// Implement this by `vertex_input!`.
+pub trait VertexInput: Sized {
+    /// Attributes in order of shader locations.
+    fn attributes() -> Vec<VertexAttribute>;
+
+    #[inline]
+    fn stride() -> u32 { mem::size_of::<Self>() as u32 }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct VertexAttribute {
+    pub offset: u32,
+    pub format: vk::Format,
+}
+
+/// Implement `VertexInput` for a `#[repr(C)]` struct. Fields are bound to locations from 0 in the
+/// order they are listed, and formats are derived from the types of fields.
+/// ```ignore
+/// vertex_input!(Vertex { position, normal, color });
+/// ```
+macro_rules! vertex_input {
+    ($vertex:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::vulkan::render::VertexInput for $vertex {
+            fn attributes() -> Vec<$crate::vulkan::render::VertexAttribute> {
+                let vertex = std::mem::MaybeUninit::<$vertex>::uninit();
+                let base = vertex.as_ptr();
+                vec![
+                    $(
+                        unsafe {
+                            let field = std::ptr::addr_of!((*base).$field);
+                            $crate::vulkan::render::VertexAttribute {
+                                offset: (field as *const u8).offset_from(base as *const u8) as u32,
+                                format: $crate::vulkan::render::vertex_format_of(field),
+                            }
+                        },
+                    )*
+                ]
+            }
+        }
+    };
+}
+
+/// Used by `vertex_input!` to infer formats from fields.
+#[inline]
+pub fn vertex_format_of<T: VertexFormat>(_field: *const T) -> vk::Format { T::FORMAT }
+
+impl VertexFormat for f32 { const FORMAT: vk::Format = vk::Format::R32_SFLOAT; }
+impl VertexFormat for u32 { const FORMAT: vk::Format = vk::Format::R32_UINT; }
+impl VertexFormat for i32 { const FORMAT: vk::Format = vk::Format::R32_SINT; }
+impl VertexFormat for XY<f32> { const FORMAT: vk::Format = vk::Format::R32G32_SFLOAT; }
+impl VertexFormat for XYZ<f32> { const FORMAT: vk::Format = vk::Format::R32G32B32_SFLOAT; }
+impl VertexFormat for XYZW<f32> { const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT; }
+impl VertexFormat for XYZW<u32> { const FORMAT: vk::Format = vk::Format::R32G32B32A32_UINT; }
+impl VertexFormat for [u8; 4] { const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM; }
+
+/// Builder of `Shader`. Defaults are:
+/// - triangle list without primitive restart,
+/// - filled polygons without culling, counter clockwise front faces and no depth bias,
+/// - `Render::SAMPLE_COUNT` samples,
+/// - no depth test, no color attachments and no vertex input.
+///
+/// Descriptor set layouts and push constant ranges are reflected from the SPIR-V of the stages.
+///
+/// Viewport and scissor are always dynamic, so pipelines survive resizing of the swapchain.
+/// Set them by `Render::set_full_viewport` after binding the pipeline.
+pub struct GraphicsPipelineBuilder<'a> {
+    stages: Vec<(vk::ShaderStageFlags, &'a [u8])>,
+    specialization: Vec<(vk::ShaderStageFlags, u32, Vec<u8>)>,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    depth_bias: Option<(f32, f32)>,
+    samples: vk::SampleCountFlags,
+    depth: Option<(bool, vk::CompareOp)>,
+    color_blend_attachments: Vec<vk::PipelineColorBlendAttachmentState>,
+    name: Option<&'a str>,
+}
+
+impl Default for GraphicsPipelineBuilder<'_> {
+    #[inline]
+    fn default() -> Self { Self::new() }
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            stages: Vec::new(),
+            specialization: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_bias: None,
+            samples: Render::SAMPLE_COUNT,
+            depth: None,
+            color_blend_attachments: Vec::new(),
+            name: None,
+        }
+    }
+
+    /// Name of the pipeline and its layouts in validation messages and capture tools.
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// SPIR-V of a stage. The entry point must be `main`.
+    pub fn stage(mut self, stage: vk::ShaderStageFlags, spirv: &'a [u8]) -> Self {
+        self.stages.push((stage, spirv));
+        self
+    }
+
+    #[inline]
+    pub fn vertex_shader(self, spirv: &'a [u8]) -> Self {
+        self.stage(vk::ShaderStageFlags::VERTEX, spirv)
+    }
+
+    #[inline]
+    pub fn fragment_shader(self, spirv: &'a [u8]) -> Self {
+        self.stage(vk::ShaderStageFlags::FRAGMENT, spirv)
+    }
+
+    /// Value of a specialization constant of a stage.
+    pub fn specialization<T: Copy>(
+        mut self,
+        stage: vk::ShaderStageFlags,
+        constant_id: u32,
+        value: T,
+    ) -> Self {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>())
+        };
+        self.specialization.push((stage, constant_id, bytes.to_vec()));
+        self
+    }
+
+    /// Bind the vertex type `V` to the next binding.
+    /// Its attributes take the locations following the ones already added.
+    pub fn vertex<V: VertexInput>(self) -> Self {
+        self.vertex_attributes_of(V::stride(), &V::attributes()[..])
+    }
+
+    /// Bind vertices of `stride` bytes with the given attributes to the next binding,
+    /// e.g. only positions for depth only passes.
+    pub fn vertex_attributes_of(mut self, stride: u32, attributes: &[VertexAttribute]) -> Self {
+        let binding = self.vertex_bindings.len() as u32;
+        let first_location = self.vertex_attributes.len() as u32;
+
+        self.vertex_bindings.push(
+            vk::VertexInputBindingDescription::builder()
+                .binding(binding)
+                .input_rate(vk::VertexInputRate::VERTEX)
+                .stride(stride)
+                .build()
+        );
+        attributes
+            .iter()
+            .enumerate()
+            .for_each(|(i, attribute)| {
+                self.vertex_attributes.push(
+                    vk::VertexInputAttributeDescription::builder()
+                        .binding(binding)
+                        .offset(attribute.offset)
+                        .format(attribute.format)
+                        .location(first_location + i as u32)
+                        .build()
+                );
+            });
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self {
+        self.depth_bias = Some((constant_factor, slope_factor));
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn depth_test(mut self, write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth = Some((write, compare_op));
+        self
+    }
+
+    /// Color attachments of the subpass which are overwritten.
+    pub fn opaque_color_attachments(mut self, count: usize) -> Self {
+        let attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .blend_enable(false)
+            .build();
+        self.color_blend_attachments.extend(std::iter::repeat_n(attachment, count));
+        self
+    }
+
+    /// A color attachment of the subpass which is blended by alpha.
+    pub fn alpha_blended_color_attachment(mut self) -> Self {
+        self.color_blend_attachments.push(
+            vk::PipelineColorBlendAttachmentState::builder()
+                .color_write_mask(vk::ColorComponentFlags::all())
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+                .alpha_blend_op(vk::BlendOp::ADD)
+                .build()
+        );
+        self
+    }
+
+    /// Fails if the stages disagree with each other or with the vertex input and
+    /// specialization constants given to this builder. Nothing is created then.
+    /// # Safety
+    /// `render_pass` and `pipeline_cache` must be alive, and the render pass must have the
+    /// subpass.
+    pub unsafe fn build(
+        &self,
+        vulkan: &Vulkan,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        pipeline_cache: vk::PipelineCache,
+    ) -> Result<Shader, ReflectErr> {
+        let device = &vulkan.device;
+
+        // Reflection of stages.
+        let reflections = self.stages
+            .iter()
+            .map(|(_, spirv)| reflect::reflect(spirv))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        reflections
+            .iter()
+            .filter(|reflection| reflection.stage == vk::ShaderStageFlags::VERTEX)
+            .try_for_each(|reflection| reflection.check_vertex_inputs(&self.vertex_attributes[..]))?;
+
+        self.specialization
+            .iter()
+            .try_for_each(|(stage, constant_id, bytes)| {
+                reflections
+                    .iter()
+                    .find(|reflection| reflection.stage == *stage)
+                    .ok_or(ReflectErr::SpecializationMismatch { constant_id: *constant_id })?
+                    .check_specialization(*constant_id, bytes.len())
+            })?;
+
+        let layout = Layout::new(&reflections[..])?;
+
+
+        // Descriptor Set Layout creation.
+        let descriptor_set_layouts = layout.sets
+            .iter()
+            .map(|bindings| {
+                let info = vk::DescriptorSetLayoutCreateInfo::builder()
+                    .flags(vk::DescriptorSetLayoutCreateFlags::empty())
+                    .bindings(&bindings[..])
+                    .build();
+
+                device.create_descriptor_set_layout(&info, None).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // Descriptors of one set of each layout, for `DescriptorAllocator`.
+        let descriptor_pool_sizes = layout.sets
+            .iter()
+            .map(|bindings| {
+                let mut sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+                bindings
+                    .iter()
+                    .for_each(|binding| {
+                        match sizes.iter_mut().find(|size| size.ty == binding.descriptor_type) {
+                            Some(size) => size.descriptor_count += binding.descriptor_count,
+                            None => sizes.push(vk::DescriptorPoolSize {
+                                ty: binding.descriptor_type,
+                                descriptor_count: binding.descriptor_count,
+                            }),
+                        }
+                    });
+                sizes
+            })
+            .collect::<Vec<_>>();
+
+
+        // Pipeline Layout creation.
+        let info = vk::PipelineLayoutCreateInfo::builder()
+            .flags(vk::PipelineLayoutCreateFlags::empty())
+            .set_layouts(&descriptor_set_layouts[..])
+            .push_constant_ranges(&layout.push_constant_ranges[..])
+            .build();
+
+        let pipeline_layout = device.create_pipeline_layout(&info, None).unwrap();
+
+
+        // Graphics Pipeline creation.
+        let modules = self.stages
+            .iter()
+            .map(|(_, spirv)| Render::shader_module(vulkan, spirv))
+            .collect::<Vec<_>>();
+
+        // Specialization constants of each stage are packed into one buffer.
+        let specializations = self.stages
+            .iter()
+            .map(|(stage, _)| {
+                let mut data = Vec::new();
+                let entries = self.specialization
+                    .iter()
+                    .filter(|(constant_stage, _, _)| constant_stage == stage)
+                    .map(|(_, constant_id, bytes)| {
+                        let entry = vk::SpecializationMapEntry::builder()
+                            .constant_id(*constant_id)
+                            .offset(data.len() as u32)
+                            .size(bytes.len())
+                            .build();
+                        data.extend_from_slice(&bytes[..]);
+                        entry
+                    })
+                    .collect::<Vec<_>>();
+                (entries, data)
+            })
+            .collect::<Vec<_>>();
+        let specialization_infos = specializations
+            .iter()
+            .map(|(entries, data)| {
+                vk::SpecializationInfo::builder()
+                    .map_entries(&entries[..])
+                    .data(&data[..])
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let fn_name = CString::new("main").unwrap();
+        let stages = self.stages
+            .iter()
+            .zip(modules.iter())
+            .zip(specialization_infos.iter())
+            .map(|(((stage, _), module), specialization)| {
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .flags(vk::PipelineShaderStageCreateFlags::empty())
+                    .module(*module)
+                    .stage(*stage)
+                    .name(&fn_name)
+                    .specialization_info(specialization)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&self.vertex_bindings[..])
+            .vertex_attribute_descriptions(&self.vertex_attributes[..])
+            .build();
+
+        let assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .flags(vk::PipelineInputAssemblyStateCreateFlags::empty())
+            .primitive_restart_enable(false)
+            .topology(self.topology)
+            .build();
+
+        // Viewport and scissor are dynamic, only their counts matter.
+        let viewport = vk::PipelineViewportStateCreateInfo::builder()
+            .flags(vk::PipelineViewportStateCreateFlags::empty())
+            .viewport_count(1)
+            .scissor_count(1)
+            .build();
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states[..])
+            .build();
+
+        let (depth_bias_constant_factor, depth_bias_slope_factor) =
+            self.depth_bias.unwrap_or((0.0, 0.0));
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+            .flags(vk::PipelineRasterizationStateCreateFlags::empty())
+            .rasterizer_discard_enable(false)
+            .line_width(1.0)
+            .front_face(self.front_face)
+            .cull_mode(self.cull_mode)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .depth_bias_enable(self.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias_constant_factor)
+            .depth_bias_slope_factor(depth_bias_slope_factor)
+            .depth_clamp_enable(false)
+            .build();
+
+        let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(self.samples)
+            .sample_shading_enable(false)
+            .build();
+
+        let (depth_write, depth_compare_op) = self.depth.unwrap_or((false, vk::CompareOp::ALWAYS));
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth.is_some())
+            .depth_write_enable(depth_write)
+            .depth_compare_op(depth_compare_op)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false)
+            .build();
+
+        let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+            .flags(vk::PipelineColorBlendStateCreateFlags::empty())
+            .logic_op_enable(false)
+            .attachments(&self.color_blend_attachments[..])
+            .build();
+
+        let info = vk::GraphicsPipelineCreateInfo::builder()
+            .flags(vk::PipelineCreateFlags::empty())
+            .render_pass(render_pass)
+            .subpass(subpass)
+            .layout(pipeline_layout)
+            .stages(&stages[..])
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic)
+            .build();
+
+        let pipeline = device
+            .create_graphics_pipelines(pipeline_cache, &[info], None)
+            .map_err(|(_pipelines, err)| err)
+            .unwrap()[0];
+
+        modules.iter().for_each(|module| device.destroy_shader_module(*module, None));
+
+        if let Some(name) = self.name {
+            vulkan.set_object_name(pipeline, name);
+            vulkan.set_object_name(pipeline_layout, &format!("{} layout", name));
+            descriptor_set_layouts
+                .iter()
+                .enumerate()
+                .for_each(|(set, layout)| vulkan.set_object_name(*layout, &format!("{} set {}", name, set)));
+        }
+
+        Ok(Shader {
+            descriptor_set_layouts,
+            descriptor_pool_sizes,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+}
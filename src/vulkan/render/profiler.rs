@@ -0,0 +1,236 @@
+//! GPU timing of each pass with timestamp queries, and CPU timing of frames.
+//!
+//! Each framebuffer has a region of the query pool: a timestamp at the start of the frame
+//! and one at the end of each pass. The region is read back when the framebuffer is used
+//! again, that is after its previous frame has completed, so reading never stalls.
+
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use crate::utility::Timer;
+
+use super::Vulkan;
+use super::{ CommandBuffer, Recording };
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, Write };
+use std::path::Path;
+
+pub const PASS_COUNT: usize = 4;
+
+/// Frames kept for the CSV dump.
+const HISTORY_LENGTH: usize = 1000;
+/// Frames of rolling averages.
+const AVERAGE_LENGTH: usize = 60;
+
+/// Passes which are timed. A pass lasts from the end of the previous recorded one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Shadow = 0,
+    GBuffer = 1,
+    Lighting = 2,
+    Gui = 3,
+}
+
+/// Times of a frame in milliseconds.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameTimes {
+    /// Time between the starts of this frame and the next one on the host.
+    pub cpu: f32,
+    /// Indexed by `Pass`. Zero if the pass was not recorded.
+    pub passes: [f32; PASS_COUNT],
+}
+
+pub(super) struct Profiler {
+    query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    /// Whether the region of each framebuffer has timestamps to read.
+    written: Vec<bool>,
+    /// Measures the CPU time of the frame being recorded into the framebuffer `current`.
+    timer: Timer,
+    /// `None` before the first frame.
+    current: Option<usize>,
+    /// CPU time of the last frame of each framebuffer, waiting for its GPU times.
+    cpu_times: Vec<Option<f32>>,
+    history: VecDeque<FrameTimes>,
+}
+
+impl Pass {
+    const ALL: [Pass; PASS_COUNT] = [Pass::Shadow, Pass::GBuffer, Pass::Lighting, Pass::Gui];
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Pass::Shadow => "shadow",
+            Pass::GBuffer => "g_buffer",
+            Pass::Lighting => "lighting",
+            Pass::Gui => "gui",
+        }
+    }
+}
+
+impl Profiler {
+    /// A timestamp at the start of the frame and one for each pass.
+    const QUERIES_PER_FRAME: u32 = 1 + PASS_COUNT as u32;
+
+    pub(super) unsafe fn new(vulkan: &Vulkan, framebuffer_count: usize) -> Self {
+        let info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(Self::QUERIES_PER_FRAME * framebuffer_count as u32);
+        let query_pool = vulkan.device.create_query_pool(&info, None).unwrap();
+
+        Self {
+            query_pool,
+            timestamp_period: vulkan.physical_device.properties.limits.timestamp_period,
+            written: vec![false; framebuffer_count],
+            timer: Timer::new(),
+            current: None,
+            cpu_times: vec![None; framebuffer_count],
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+        }
+    }
+
+    /// Read the previous frame of the framebuffer, then reset its queries and write the
+    /// start timestamp. Must be recorded outside of render passes.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub(super) unsafe fn begin_frame(
+        &mut self,
+        vulkan: &Vulkan,
+        command_buffer: &mut CommandBuffer<Recording>,
+        framebuffer_index: usize,
+    ) {
+        // The previous frame lasts until this one begins.
+        if let Some(previous) = self.current.replace(framebuffer_index) {
+            self.cpu_times[previous] = Some(self.timer.lap().as_secs_f32() * 1000.0);
+        }
+        self.timer.start();
+
+        if self.written[framebuffer_index] {
+            let passes = self.read(vulkan, framebuffer_index);
+            let cpu = self.cpu_times[framebuffer_index].take().unwrap_or(0.0);
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            self.history.push_back(FrameTimes { cpu, passes });
+        }
+
+        let first_query = Self::QUERIES_PER_FRAME * framebuffer_index as u32;
+        command_buffer
+            .reset_query_pool(self.query_pool, first_query, Self::QUERIES_PER_FRAME)
+            .write_timestamp(vk::PipelineStageFlags::TOP_OF_PIPE, self.query_pool, first_query);
+        self.written[framebuffer_index] = true;
+    }
+
+    /// Write the timestamp at the end of the pass into the frame being recorded. It can be
+    /// recorded in a render pass, e.g. before `next_subpass`, unless the subpass is executed
+    /// by secondary command buffers.
+    pub(super) fn end_pass(&self, command_buffer: &mut CommandBuffer<Recording>, pass: Pass) {
+        if let Some(framebuffer_index) = self.current {
+            command_buffer.write_timestamp(
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.query_pool,
+                Self::QUERIES_PER_FRAME * framebuffer_index as u32 + 1 + pass as u32,
+            );
+        }
+    }
+
+    /// Durations of passes in milliseconds. Queries of passes which were not recorded are
+    /// unavailable and count as zero.
+    unsafe fn read(&self, vulkan: &Vulkan, framebuffer_index: usize) -> [f32; PASS_COUNT] {
+        // Pairs of a timestamp and its availability.
+        let mut results = [[0_u64; 2]; Self::QUERIES_PER_FRAME as usize];
+        // NOT_READY is returned when some queries are unavailable, and results are still written.
+        let _ = vulkan.device.get_query_pool_results(
+            self.query_pool,
+            Self::QUERIES_PER_FRAME * framebuffer_index as u32,
+            Self::QUERIES_PER_FRAME,
+            &mut results[..],
+            vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+        );
+
+        let mut passes = [0.0; PASS_COUNT];
+        let mut previous = match results[0] {
+            [start, available] if available != 0 => start,
+            _ => return passes,
+        };
+        Pass::ALL
+            .iter()
+            .for_each(|pass| {
+                let [timestamp, available] = results[1 + *pass as usize];
+                if available != 0 {
+                    let ticks = timestamp.saturating_sub(previous);
+                    passes[*pass as usize] = ticks as f32 * self.timestamp_period / 1_000_000.0;
+                    previous = timestamp;
+                }
+            });
+
+        passes
+    }
+
+    /// Rolling averages over the last frames.
+    pub(super) fn averages(&self) -> FrameTimes {
+        let count = self.history.len().min(AVERAGE_LENGTH);
+        if count == 0 {
+            return FrameTimes::default();
+        }
+
+        let mut sum = self.history
+            .iter()
+            .rev()
+            .take(count)
+            .fold(FrameTimes::default(), |mut sum, times| {
+                sum.cpu += times.cpu;
+                sum.passes.iter_mut().zip(times.passes.iter()).for_each(|(s, t)| *s += *t);
+                sum
+            });
+
+        sum.cpu /= count as f32;
+        sum.passes.iter_mut().for_each(|s| *s /= count as f32);
+        sum
+    }
+
+    /// Write the kept frames as CSV, a row for each frame in milliseconds.
+    pub(super) fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+
+        write!(file, "frame,cpu")?;
+        for pass in Pass::ALL.iter() {
+            write!(file, ",{}", pass.name())?;
+        }
+        writeln!(file)?;
+
+        for (frame, times) in self.history.iter().enumerate() {
+            write!(file, "{},{}", frame, times.cpu)?;
+            for time in times.passes.iter() {
+                write!(file, ",{}", time)?;
+            }
+            writeln!(file)?;
+        }
+
+        file.flush()
+    }
+
+    pub(super) unsafe fn destroy(self, vulkan: &Vulkan) {
+        vulkan.device.destroy_query_pool(self.query_pool, None);
+    }
+}
+
+impl FrameTimes {
+    /// Sum of the GPU times of the passes.
+    pub fn gpu(&self) -> f32 { self.passes.iter().sum() }
+}
+
+/// One line for an overlay, e.g. `cpu 16.67 ms | gpu 3.20 ms (shadow 0.80, ...)`.
+impl fmt::Display for FrameTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cpu {:.2} ms | gpu {:.2} ms (", self.cpu, self.gpu())?;
+        for (i, pass) in Pass::ALL.iter().enumerate() {
+            if i != 0 { write!(f, ", ")?; }
+            write!(f, "{} {:.2}", pass.name(), self.passes[*pass as usize])?;
+        }
+        write!(f, ")")
+    }
+}
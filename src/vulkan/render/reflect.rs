@@ -0,0 +1,676 @@
+//! Reflection of SPIR-V modules.
+//!
+//! Only the subset of SPIR-V which glslang emits for our shaders is understood:
+//! descriptors, push constant blocks, inputs of vertex shaders and specialization constants.
+
+use ash::vk;
+
+use std::collections::HashMap;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_LEN: usize = 5;
+
+// Opcodes.
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations.
+const SPEC_ID: u32 = 1;
+const BLOCK: u32 = 2;
+const BUFFER_BLOCK: u32 = 3;
+const ARRAY_STRIDE: u32 = 6;
+const MATRIX_STRIDE: u32 = 7;
+const BUILT_IN: u32 = 11;
+const LOCATION: u32 = 30;
+const BINDING: u32 = 33;
+const DESCRIPTOR_SET: u32 = 34;
+const OFFSET: u32 = 35;
+
+// Storage classes.
+const UNIFORM_CONSTANT: u32 = 0;
+const INPUT: u32 = 1;
+const UNIFORM: u32 = 2;
+const PUSH_CONSTANT: u32 = 9;
+const STORAGE_BUFFER: u32 = 12;
+
+// Dimensions of images.
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug)]
+pub enum ReflectErr {
+    /// The bytes are not a SPIR-V module.
+    InvalidModule,
+    /// There is no entry point or the execution model is not supported.
+    UnknownStage,
+    /// A type of a resource which is not supported, e.g. runtime arrays of descriptors.
+    UnsupportedType { id: u32 },
+    /// Stages declare different descriptors at the same binding.
+    DescriptorMismatch { set: u32, binding: u32 },
+    /// Vertex input which is not given or whose type is different from the shader.
+    VertexInputMismatch { location: u32 },
+    /// Specialization constant which is not declared or whose size is different from the shader.
+    SpecializationMismatch { constant_id: u32 },
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+}
+
+/// Numeric types of components of vertex inputs.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NumericType {
+    Float,
+    Sint,
+    Uint,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub numeric_type: NumericType,
+    pub components: u32,
+}
+
+/// Interface of one stage.
+#[derive(Clone, Debug)]
+pub struct Reflection {
+    pub stage: vk::ShaderStageFlags,
+    pub descriptors: Vec<DescriptorBinding>,
+    /// (offset, size) of the push constant block.
+    pub push_constants: Option<(u32, u32)>,
+    /// Empty except for vertex shaders.
+    pub vertex_inputs: Vec<VertexInput>,
+    /// (constant_id, size in bytes)
+    pub specialization_constants: Vec<(u32, usize)>,
+}
+
+/// Interface of all stages of a pipeline.
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    /// Bindings of each set. Sets which no stage uses are empty.
+    pub sets: Vec<Vec<vk::DescriptorSetLayoutBinding>>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Module {
+    execution_model: Option<u32>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    spec_constants: Vec<(u32, u32)>,
+    variables: Vec<(u32, u32, u32)>,
+    decorations: HashMap<(u32, u32), u32>,
+    flags: Vec<(u32, u32)>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+pub fn reflect(spirv: &[u8]) -> Result<Reflection, ReflectErr> {
+    if !spirv.len().is_multiple_of(4) || spirv.len() < HEADER_LEN * 4 {
+        return Err(ReflectErr::InvalidModule);
+    }
+    let words = spirv
+        .chunks(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect::<Vec<_>>();
+    if words[0] != MAGIC_NUMBER {
+        return Err(ReflectErr::InvalidModule);
+    }
+
+    let module = Module::parse(&words[HEADER_LEN..])?;
+    module.reflect()
+}
+
+impl Module {
+    fn parse(mut words: &[u32]) -> Result<Self, ReflectErr> {
+        let mut module = Self::default();
+
+        while !words.is_empty() {
+            let word_count = (words[0] >> 16) as usize;
+            let opcode = words[0] & 0xffff;
+            if word_count == 0 || word_count > words.len() {
+                return Err(ReflectErr::InvalidModule);
+            }
+            let operands = &words[1..word_count];
+            words = &words[word_count..];
+            if operands.len() < min_operands(opcode) {
+                return Err(ReflectErr::InvalidModule);
+            }
+
+            // Result ids of types are operands[0].
+            let ty = match opcode {
+                OP_TYPE_BOOL => Some(Type::Bool),
+                OP_TYPE_INT => Some(Type::Int { width: operands[1], signed: operands[2] != 0 }),
+                OP_TYPE_FLOAT => Some(Type::Float { width: operands[1] }),
+                OP_TYPE_VECTOR => Some(Type::Vector { component: operands[1], count: operands[2] }),
+                OP_TYPE_MATRIX => Some(Type::Matrix { column: operands[1], count: operands[2] }),
+                OP_TYPE_IMAGE => Some(Type::Image { dim: operands[2], sampled: operands[6] }),
+                OP_TYPE_SAMPLER => Some(Type::Sampler),
+                OP_TYPE_SAMPLED_IMAGE => Some(Type::SampledImage),
+                OP_TYPE_ARRAY => Some(Type::Array { element: operands[1], length: operands[2] }),
+                OP_TYPE_RUNTIME_ARRAY => Some(Type::RuntimeArray),
+                OP_TYPE_STRUCT => Some(Type::Struct { members: operands[1..].to_vec() }),
+                OP_TYPE_POINTER => Some(Type::Pointer { pointee: operands[2] }),
+                _ => None,
+            };
+            if let Some(ty) = ty {
+                module.types.insert(operands[0], ty);
+                continue;
+            }
+
+            match opcode {
+                OP_ENTRY_POINT => {
+                    module.execution_model.get_or_insert(operands[0]);
+                },
+                OP_CONSTANT => {
+                    module.constants.insert(operands[1], operands[2]);
+                },
+                OP_SPEC_CONSTANT | OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => {
+                    module.spec_constants.push((operands[0], operands[1]));
+                },
+                OP_VARIABLE => {
+                    module.variables.push((operands[0], operands[1], operands[2]));
+                },
+                OP_DECORATE => match operands.get(2) {
+                    Some(&literal) => { module.decorations.insert((operands[0], operands[1]), literal); },
+                    None => module.flags.push((operands[0], operands[1])),
+                },
+                OP_MEMBER_DECORATE => {
+                    if let Some(&literal) = operands.get(3) {
+                        module.member_decorations
+                            .insert((operands[0], operands[1], operands[2]), literal);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn reflect(&self) -> Result<Reflection, ReflectErr> {
+        let stage = match self.execution_model {
+            Some(0) => vk::ShaderStageFlags::VERTEX,
+            Some(1) => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            Some(2) => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            Some(3) => vk::ShaderStageFlags::GEOMETRY,
+            Some(4) => vk::ShaderStageFlags::FRAGMENT,
+            Some(5) => vk::ShaderStageFlags::COMPUTE,
+            _ => return Err(ReflectErr::UnknownStage),
+        };
+
+        let mut reflection = Reflection {
+            stage,
+            descriptors: Vec::new(),
+            push_constants: None,
+            vertex_inputs: Vec::new(),
+            specialization_constants: Vec::new(),
+        };
+
+        for &(pointer, variable, storage_class) in self.variables.iter() {
+            let pointee = match self.types.get(&pointer) {
+                Some(Type::Pointer { pointee }) => *pointee,
+                _ => return Err(ReflectErr::UnsupportedType { id: pointer }),
+            };
+
+            match storage_class {
+                UNIFORM_CONSTANT | UNIFORM | STORAGE_BUFFER => {
+                    let (set, binding) = match (
+                        self.decorations.get(&(variable, DESCRIPTOR_SET)),
+                        self.decorations.get(&(variable, BINDING)),
+                    ) {
+                        (Some(&set), Some(&binding)) => (set, binding),
+                        _ => continue,
+                    };
+                    let (descriptor_type, count) = self.descriptor_type(pointee, storage_class)?;
+                    reflection.descriptors.push(DescriptorBinding { set, binding, descriptor_type, count });
+                },
+                PUSH_CONSTANT => {
+                    let offset = self.member_offsets(pointee).min().unwrap_or(0);
+                    let size = self.size_of(pointee)?;
+                    reflection.push_constants = Some((offset, size - offset));
+                },
+                INPUT if stage == vk::ShaderStageFlags::VERTEX => {
+                    if self.decorations.contains_key(&(variable, BUILT_IN)) {
+                        continue;
+                    }
+                    if let Some(&location) = self.decorations.get(&(variable, LOCATION)) {
+                        let (numeric_type, components) = self.numeric_type(pointee)?;
+                        reflection.vertex_inputs.push(VertexInput { location, numeric_type, components });
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        for &(ty, constant) in self.spec_constants.iter() {
+            if let Some(&constant_id) = self.decorations.get(&(constant, SPEC_ID)) {
+                let size = self.size_of(ty)? as usize;
+                reflection.specialization_constants.push((constant_id, size));
+            }
+        }
+
+        reflection.descriptors.sort_by_key(|d| (d.set, d.binding));
+        reflection.vertex_inputs.sort_by_key(|input| input.location);
+        Ok(reflection)
+    }
+
+    fn descriptor_type(&self, ty: u32, storage_class: u32) -> Result<(vk::DescriptorType, u32), ReflectErr> {
+        let unsupported = ReflectErr::UnsupportedType { id: ty };
+        let descriptor_type = match self.types.get(&ty) {
+            Some(Type::Array { element, length }) => {
+                let (descriptor_type, count) = self.descriptor_type(*element, storage_class)?;
+                let length = *self.constants.get(length).ok_or(unsupported)?;
+                return Ok((descriptor_type, count * length));
+            },
+            Some(Type::Struct { .. }) => {
+                if storage_class == STORAGE_BUFFER || self.flags.contains(&(ty, BUFFER_BLOCK)) {
+                    vk::DescriptorType::STORAGE_BUFFER
+                } else if self.flags.contains(&(ty, BLOCK)) {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                } else {
+                    return Err(unsupported);
+                }
+            },
+            Some(Type::Sampler) => vk::DescriptorType::SAMPLER,
+            Some(Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            Some(Type::Image { dim, sampled }) => match (*dim, *sampled) {
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                (_, _) => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            _ => return Err(unsupported),
+        };
+        Ok((descriptor_type, 1))
+    }
+
+    fn numeric_type(&self, ty: u32) -> Result<(NumericType, u32), ReflectErr> {
+        match self.types.get(&ty) {
+            Some(Type::Float { .. }) => Ok((NumericType::Float, 1)),
+            Some(Type::Int { signed: true, .. }) => Ok((NumericType::Sint, 1)),
+            Some(Type::Int { signed: false, .. }) => Ok((NumericType::Uint, 1)),
+            Some(Type::Vector { component, count }) => {
+                let (numeric_type, _) = self.numeric_type(*component)?;
+                Ok((numeric_type, *count))
+            },
+            _ => Err(ReflectErr::UnsupportedType { id: ty }),
+        }
+    }
+
+    fn member_offsets<'a>(&'a self, ty: u32) -> impl Iterator<Item = u32> + 'a {
+        let count = match self.types.get(&ty) {
+            Some(Type::Struct { members }) => members.len() as u32,
+            _ => 0,
+        };
+        (0..count).filter_map(move |member| {
+            self.member_decorations.get(&(ty, member, OFFSET)).cloned()
+        })
+    }
+
+    /// Size in bytes by offsets and strides decorated by glslang.
+    fn size_of(&self, ty: u32) -> Result<u32, ReflectErr> {
+        let unsupported = ReflectErr::UnsupportedType { id: ty };
+        match self.types.get(&ty) {
+            // Booleans are VkBool32.
+            Some(Type::Bool) => Ok(4),
+            Some(Type::Int { width, .. }) | Some(Type::Float { width }) => Ok(width / 8),
+            Some(Type::Vector { component, count }) => Ok(self.size_of(*component)? * count),
+            Some(Type::Matrix { column, count }) => Ok(self.size_of(*column)? * count),
+            Some(Type::Array { element, length }) => {
+                let length = *self.constants.get(length).ok_or(unsupported)?;
+                let stride = match self.decorations.get(&(ty, ARRAY_STRIDE)) {
+                    Some(&stride) => stride,
+                    None => self.size_of(*element)?,
+                };
+                Ok(stride * length)
+            },
+            Some(Type::Struct { members }) => {
+                members
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &member)| {
+                        let index = index as u32;
+                        let offset = self.member_decorations
+                            .get(&(ty, index, OFFSET))
+                            .cloned()
+                            .unwrap_or(0);
+                        // Matrices in blocks are laid out by their strides.
+                        let size = match (
+                            self.types.get(&member),
+                            self.member_decorations.get(&(ty, index, MATRIX_STRIDE)),
+                        ) {
+                            (Some(Type::Matrix { count, .. }), Some(&stride)) => stride * count,
+                            _ => self.size_of(member)?,
+                        };
+                        Ok(offset + size)
+                    })
+                    .try_fold(0, |size, end: Result<u32, ReflectErr>| Ok(size.max(end?)))
+            },
+            _ => Err(unsupported),
+        }
+    }
+}
+
+/// Operands which the instructions read by `Module::parse` have at least.
+fn min_operands(opcode: u32) -> usize {
+    match opcode {
+        OP_TYPE_BOOL | OP_TYPE_SAMPLER | OP_TYPE_STRUCT => 1,
+        OP_TYPE_FLOAT | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_RUNTIME_ARRAY => 2,
+        OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE | OP_DECORATE => 2,
+        OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY | OP_TYPE_POINTER => 3,
+        OP_ENTRY_POINT | OP_CONSTANT | OP_SPEC_CONSTANT | OP_VARIABLE | OP_MEMBER_DECORATE => 3,
+        OP_TYPE_IMAGE => 8,
+        _ => 0,
+    }
+}
+
+impl Layout {
+    /// Merge interfaces of stages. Bindings declared by several stages must agree.
+    pub fn new(reflections: &[Reflection]) -> Result<Self, ReflectErr> {
+        let mut layout = Self::default();
+
+        for reflection in reflections.iter() {
+            for descriptor in reflection.descriptors.iter() {
+                let set = descriptor.set as usize;
+                if layout.sets.len() <= set {
+                    layout.sets.resize(set + 1, Vec::new());
+                }
+
+                let bindings = &mut layout.sets[set];
+                match bindings.iter_mut().find(|b| b.binding == descriptor.binding) {
+                    Some(binding) => {
+                        if binding.descriptor_type != descriptor.descriptor_type
+                            || binding.descriptor_count != descriptor.count
+                        {
+                            return Err(ReflectErr::DescriptorMismatch {
+                                set: descriptor.set,
+                                binding: descriptor.binding,
+                            });
+                        }
+                        binding.stage_flags |= reflection.stage;
+                    },
+                    None => bindings.push(
+                        vk::DescriptorSetLayoutBinding::builder()
+                            .binding(descriptor.binding)
+                            .stage_flags(reflection.stage)
+                            .descriptor_type(descriptor.descriptor_type)
+                            .descriptor_count(descriptor.count)
+                            .build()
+                    ),
+                }
+            }
+
+            if let Some((offset, size)) = reflection.push_constants {
+                let same_range = layout.push_constant_ranges
+                    .iter_mut()
+                    .find(|range| range.offset == offset && range.size == size);
+                match same_range {
+                    Some(range) => range.stage_flags |= reflection.stage,
+                    None => layout.push_constant_ranges.push(
+                        vk::PushConstantRange::builder()
+                            .stage_flags(reflection.stage)
+                            .offset(offset)
+                            .size(size)
+                            .build()
+                    ),
+                }
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+impl Reflection {
+    /// Check the attributes given to the pipeline cover the inputs of this vertex shader.
+    pub fn check_vertex_inputs(
+        &self,
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Result<(), ReflectErr> {
+        self.vertex_inputs
+            .iter()
+            .try_for_each(|input| {
+                let mismatch = ReflectErr::VertexInputMismatch { location: input.location };
+                let attribute = attributes
+                    .iter()
+                    .find(|attribute| attribute.location == input.location)
+                    .ok_or(mismatch)?;
+                if numeric_type_of(attribute.format) == Some(input.numeric_type) {
+                    Ok(())
+                } else {
+                    Err(ReflectErr::VertexInputMismatch { location: input.location })
+                }
+            })
+    }
+
+    /// Check a specialization constant given to the pipeline is declared by this stage.
+    pub fn check_specialization(&self, constant_id: u32, size: usize) -> Result<(), ReflectErr> {
+        let declared = self.specialization_constants.contains(&(constant_id, size));
+        if declared {
+            Ok(())
+        } else {
+            Err(ReflectErr::SpecializationMismatch { constant_id })
+        }
+    }
+}
+
+/// Numeric types seen by shaders of the formats which `VertexFormat` uses.
+fn numeric_type_of(format: vk::Format) -> Option<NumericType> {
+    match format {
+        vk::Format::R32_SFLOAT
+            | vk::Format::R32G32_SFLOAT
+            | vk::Format::R32G32B32_SFLOAT
+            | vk::Format::R32G32B32A32_SFLOAT
+            | vk::Format::R8G8B8A8_UNORM => Some(NumericType::Float),
+        vk::Format::R32_SINT
+            | vk::Format::R32G32_SINT
+            | vk::Format::R32G32B32_SINT
+            | vk::Format::R32G32B32A32_SINT => Some(NumericType::Sint),
+        vk::Format::R32_UINT
+            | vk::Format::R32G32_UINT
+            | vk::Format::R32G32B32_UINT
+            | vk::Format::R32G32B32A32_UINT => Some(NumericType::Uint),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+    }
+
+    /// Header of a module followed by `instructions`.
+    fn module(instructions: &[u32]) -> Vec<u8> {
+        let mut words = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        words.extend_from_slice(instructions);
+        words_to_bytes(&words)
+    }
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn binding(set: u32, binding: u32, descriptor_type: vk::DescriptorType) -> DescriptorBinding {
+        DescriptorBinding { set, binding, descriptor_type, count: 1 }
+    }
+
+    #[test]
+    fn lighting_fragment() {
+        let reflection = reflect(include_bytes!("lighting/frag.spv")).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(reflection.descriptors, vec![
+            binding(0, 0, vk::DescriptorType::INPUT_ATTACHMENT),
+            binding(0, 1, vk::DescriptorType::INPUT_ATTACHMENT),
+            binding(0, 2, vk::DescriptorType::INPUT_ATTACHMENT),
+            binding(0, 3, vk::DescriptorType::INPUT_ATTACHMENT),
+            binding(0, 4, vk::DescriptorType::STORAGE_BUFFER),
+            binding(0, 5, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(0, 6, vk::DescriptorType::UNIFORM_BUFFER),
+        ]);
+        assert_eq!(reflection.push_constants, None);
+        assert!(reflection.vertex_inputs.is_empty());
+    }
+
+    #[test]
+    fn mesh_vertex() {
+        let reflection = reflect(include_bytes!("mesh/vert.spv")).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(reflection.descriptors, vec![binding(0, 0, vk::DescriptorType::UNIFORM_BUFFER)]);
+        // Position, rotation and scale.
+        assert_eq!(reflection.push_constants, Some((0, 48)));
+        let float = |location, components| VertexInput { location, numeric_type: NumericType::Float, components };
+        assert_eq!(reflection.vertex_inputs, vec![float(0, 3), float(1, 3), float(2, 4), float(3, 2)]);
+        assert_eq!(reflection.specialization_constants, vec![(0, 4)]);
+    }
+
+    #[test]
+    fn mesh_fragment() {
+        let reflection = reflect(include_bytes!("mesh/frag.spv")).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(reflection.descriptors, vec![binding(1, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)]);
+        let mut constants = reflection.specialization_constants.clone();
+        constants.sort();
+        assert_eq!(constants, vec![(1, 4), (2, 4), (3, 4)]);
+        assert!(reflection.check_specialization(1, 4).is_ok());
+        assert!(reflection.check_specialization(1, 8).is_err());
+        assert!(reflection.check_specialization(4, 4).is_err());
+    }
+
+    #[test]
+    fn layout_merges_stages() {
+        let vertex = reflect(include_bytes!("mesh/vert.spv")).unwrap();
+        let fragment = reflect(include_bytes!("mesh/frag.spv")).unwrap();
+        let layout = Layout::new(&[vertex, fragment]).unwrap();
+        assert_eq!(layout.sets.len(), 2);
+        assert_eq!(layout.sets[0][0].stage_flags, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(layout.sets[1][0].stage_flags, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(layout.push_constant_ranges.len(), 1);
+        assert_eq!(layout.push_constant_ranges[0].size, 48);
+    }
+
+    #[test]
+    fn vertex_input_mismatch() {
+        let reflection = reflect(include_bytes!("shadow/vert.spv")).unwrap();
+        let attribute = |format| vk::VertexInputAttributeDescription { location: 0, binding: 0, format, offset: 0 };
+        assert!(reflection.check_vertex_inputs(&[attribute(vk::Format::R32G32B32_SFLOAT)]).is_ok());
+        match reflection.check_vertex_inputs(&[attribute(vk::Format::R32G32B32_SINT)]) {
+            Err(ReflectErr::VertexInputMismatch { location: 0 }) => (),
+            result => panic!("{:?}", result),
+        }
+        assert!(reflection.check_vertex_inputs(&[]).is_err());
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(matches!(reflect(&[]), Err(ReflectErr::InvalidModule)));
+        // Not a multiple of words.
+        assert!(matches!(reflect(&module(&[0])[..21]), Err(ReflectErr::InvalidModule)));
+        let mut bytes = module(&[]);
+        bytes[0] = 0;
+        assert!(matches!(reflect(&bytes), Err(ReflectErr::InvalidModule)));
+    }
+
+    #[test]
+    fn no_entry_point() {
+        assert!(matches!(reflect(&module(&[])), Err(ReflectErr::UnknownStage)));
+    }
+
+    #[test]
+    fn word_count_out_of_module() {
+        // OpTypeInt claiming 4 words with only 2 left.
+        let bytes = module(&[(4 << 16) | OP_TYPE_INT, 1]);
+        assert!(matches!(reflect(&bytes), Err(ReflectErr::InvalidModule)));
+        // A word count of 0 would never advance.
+        let bytes = module(&[OP_TYPE_BOOL]);
+        assert!(matches!(reflect(&bytes), Err(ReflectErr::InvalidModule)));
+    }
+
+    #[test]
+    fn truncated_instructions() {
+        let truncated = [
+            instruction(OP_ENTRY_POINT, &[4, 1]),
+            instruction(OP_TYPE_INT, &[1, 32]),
+            instruction(OP_TYPE_FLOAT, &[1]),
+            instruction(OP_TYPE_VECTOR, &[2, 1]),
+            instruction(OP_TYPE_MATRIX, &[3, 2]),
+            instruction(OP_TYPE_IMAGE, &[1, 2, 6, 0, 0, 0, 2]),
+            instruction(OP_TYPE_ARRAY, &[1, 2]),
+            instruction(OP_TYPE_POINTER, &[1, 0]),
+            instruction(OP_TYPE_STRUCT, &[]),
+            instruction(OP_CONSTANT, &[1, 2]),
+            instruction(OP_SPEC_CONSTANT, &[1, 2]),
+            instruction(OP_SPEC_CONSTANT_TRUE, &[1]),
+            instruction(OP_VARIABLE, &[1, 2]),
+            instruction(OP_DECORATE, &[1]),
+            instruction(OP_MEMBER_DECORATE, &[1, 0]),
+        ];
+        truncated.iter().for_each(|instruction| {
+            let result = reflect(&module(instruction));
+            assert!(matches!(result, Err(ReflectErr::InvalidModule)), "{:?}: {:?}", instruction, result);
+        });
+    }
+
+    #[test]
+    fn unknown_opcodes_are_skipped() {
+        let mut instructions = instruction(0xffff, &[]);
+        instructions.extend(instruction(OP_ENTRY_POINT, &[4, 1, 0]));
+        let reflection = reflect(&module(&instructions)).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert!(reflection.descriptors.is_empty());
+    }
+
+    #[test]
+    fn variable_without_pointer_type() {
+        let mut instructions = instruction(OP_ENTRY_POINT, &[4, 1, 0]);
+        instructions.extend(instruction(OP_VARIABLE, &[7, 8, UNIFORM]));
+        match reflect(&module(&instructions)) {
+            Err(ReflectErr::UnsupportedType { id: 7 }) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+}
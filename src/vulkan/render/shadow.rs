@@ -0,0 +1,446 @@
+//! Cascaded shadow maps of the sun.
+//!
+//! The view frustum of the camera is split into `CASCADE_COUNT` ranges of depth and
+//! each range is rendered from the sun into a layer of one depth image array.
+//! The lighting subpass picks the cascade by the depth of the fragment and filters
+//! the shadow map with PCF.
+
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use crate::linear_algebra::{ XYZ, XYZW, Mat4 };
+
+use super::Vulkan;
+use super::Render;
+use super::{ CommandBuffer, Recording };
+use super::Shader;
+use super::ReflectErr;
+use super::{ GraphicsPipelineBuilder, VertexInput };
+use super::HostBuffer;
+use super::Matrix;
+
+use std::mem;
+use std::ptr;
+
+pub const CASCADE_COUNT: usize = 4;
+
+/// Width and height of each cascade.
+const SHADOW_MAP_SIZE: u32 = 2048;
+const SHADOW_MAP_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Blend factor between the logarithmic split (1.0) and the uniform split (0.0).
+const SPLIT_LAMBDA: f32 = 0.75;
+
+/// Casters in front of the bounding sphere of a cascade within this distance along the
+/// sun direction are still rendered into the shadow map.
+const CASTER_MARGIN: f32 = 50.0;
+
+/// The part of the camera needed to fit cascades to its view frustum.
+#[derive(Copy, Clone, Debug)]
+pub struct ViewFrustum {
+    pub position: XYZ<f32>,
+    pub direction: XYZ<f32>,
+    pub up: XYZ<f32>,
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    /// Width / height.
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+/// Layout of `Object` in `shadow/glsl.vert`. Pushed for each caster in each cascade.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowObject {
+    pub light_space: Matrix,
+}
+
+pub struct ShadowMap {
+    render_pass: vk::RenderPass,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    /// A view for each cascade to render into, and the last one is an array view to sample.
+    views: [vk::ImageView; CASCADE_COUNT + 1],
+    framebuffers: [vk::Framebuffer; CASCADE_COUNT],
+    sampler: vk::Sampler,
+    uniform: HostBuffer,
+    cascades: Cascades,
+}
+
+/// Layout of `Shadow` in `lighting/glsl.frag` (std140).
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Cascades {
+    light_space: [Matrix; CASCADE_COUNT],
+    /// Far distances of the cascades along the view direction.
+    splits: [f32; CASCADE_COUNT],
+    view_direction: XYZW<f32>,
+}
+
+impl ShadowObject {
+    /// `light_space` is the matrix of the cascade given to the draw callback of `record`.
+    pub fn new(light_space: &Matrix, model: &Matrix) -> Self {
+        Self { light_space: *light_space * *model }
+    }
+
+    /// Record pushing this transform for following draw commands.
+    pub fn push(&self, command_buffer: &mut CommandBuffer<Recording>, shader: &Shader) {
+        command_buffer.push_constants(shader.pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, self);
+    }
+}
+
+impl ShadowMap {
+    pub(super) unsafe fn new(vulkan: &Vulkan, framebuffer_count: usize) -> Self {
+        let device = &vulkan.device;
+        let render_pass = Self::create_render_pass(vulkan);
+
+        // Create the depth image which has a layer for each cascade.
+        let info = vk::ImageCreateInfo::builder()
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+            .format(SHADOW_MAP_FORMAT)
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D { width: SHADOW_MAP_SIZE, height: SHADOW_MAP_SIZE, depth: 1 })
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .mip_levels(1)
+            .array_layers(CASCADE_COUNT as u32)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .queue_family_indices(&[]);
+        let image = device.create_image(&info, None).unwrap();
+
+        let requirements = device.get_image_memory_requirements(image);
+        let memory_type_index = vulkan.physical_device
+            .memory_type_index(requirements.memory_type_bits, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .unwrap();
+        let info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_type_index)
+            .allocation_size(requirements.size);
+        let memory = device.allocate_memory(&info, None).unwrap();
+        device.bind_image_memory(image, memory, 0).unwrap();
+
+        // Create vk::ImageViews.
+        let mut views = [vk::ImageView::null(); CASCADE_COUNT + 1];
+        views.iter_mut()
+            .enumerate()
+            .for_each(|(index, view)| {
+                let (view_type, base_array_layer, layer_count) = if index < CASCADE_COUNT {
+                    (vk::ImageViewType::TYPE_2D, index as u32, 1)
+                } else {
+                    (vk::ImageViewType::TYPE_2D_ARRAY, 0, CASCADE_COUNT as u32)
+                };
+
+                let info = vk::ImageViewCreateInfo::builder()
+                    .image(image)
+                    .format(SHADOW_MAP_FORMAT)
+                    .view_type(view_type)
+                    .subresource_range(
+                        vk::ImageSubresourceRange::builder()
+                            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                            .base_mip_level(0)
+                            .level_count(1)
+                            .base_array_layer(base_array_layer)
+                            .layer_count(layer_count)
+                            .build()
+                    );
+                *view = device.create_image_view(&info, None).unwrap();
+            });
+
+        // Create vk::Framebuffers.
+        let mut framebuffers = [vk::Framebuffer::null(); CASCADE_COUNT];
+        framebuffers.iter_mut()
+            .zip(views.iter())
+            .for_each(|(framebuffer, view)| {
+                let info = vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .width(SHADOW_MAP_SIZE)
+                    .height(SHADOW_MAP_SIZE)
+                    .layers(1)
+                    .attachments(std::slice::from_ref(view));
+                *framebuffer = device.create_framebuffer(&info, None).unwrap();
+            });
+
+        // Linear filter with compare op does 2x2 PCF in hardware.
+        let info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .min_lod(0.0)
+            .max_lod(0.0);
+        let sampler = device.create_sampler(&info, None).unwrap();
+
+        vulkan.set_object_name(render_pass, "shadow render pass");
+        vulkan.set_object_name(image, "shadow map");
+        vulkan.set_object_name(sampler, "shadow map sampler");
+        views
+            .iter()
+            .enumerate()
+            .for_each(|(index, view)| vulkan.set_object_name(*view, &format!("shadow map view {}", index)));
+        framebuffers
+            .iter()
+            .enumerate()
+            .for_each(|(index, framebuffer)| {
+                vulkan.set_object_name(*framebuffer, &format!("shadow framebuffer {}", index));
+            });
+
+        let uniform = HostBuffer::new(
+            vulkan,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            mem::size_of::<Cascades>() as u64,
+            framebuffer_count,
+        );
+        uniform.set_name(vulkan, "shadow cascade buffer");
+
+        Self {
+            render_pass,
+            image,
+            memory,
+            views,
+            framebuffers,
+            sampler,
+            uniform,
+            cascades: Cascades {
+                light_space: [Mat4::IDENTITY; CASCADE_COUNT],
+                splits: [0.0; CASCADE_COUNT],
+                view_direction: XYZW::new(0.0, 0.0, 1.0, 0.0),
+            },
+        }
+    }
+
+    unsafe fn create_render_pass(vulkan: &Vulkan) -> vk::RenderPass {
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(SHADOW_MAP_FORMAT)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .build(),
+        ];
+
+        let depth_attachment = vk::AttachmentReference::builder()
+            .attachment(0)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .build();
+
+        let subpasses = [
+            vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .depth_stencil_attachment(&depth_attachment)
+                .build(),
+        ];
+
+        let dependencies = [
+            // The lighting subpass of the previous frame must finish reading the shadow map.
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+                .build(),
+            // The shadow map is sampled in the lighting subpass.
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+                .build(),
+        ];
+
+        let info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments[..])
+            .subpasses(&subpasses[..])
+            .dependencies(&dependencies[..]);
+
+        vulkan.device.create_render_pass(&info, None).unwrap()
+    }
+
+    /// Fit cascades to the view frustum and write them for the frame rendered into the
+    /// framebuffer.
+    /// # Safety
+    /// The previous frame using the framebuffer must have been finished by the device.
+    pub(super) unsafe fn update(
+        &mut self,
+        framebuffer_index: usize,
+        frustum: &ViewFrustum,
+        sun_direction: XYZ<f32>,
+    ) {
+        self.cascades = Cascades::fit(frustum, sun_direction);
+        ptr::write(self.uniform.region(framebuffer_index) as *mut Cascades, self.cascades);
+    }
+
+    /// Record rendering of all cascades.
+    /// `draw` is called once for each cascade with the light space matrix of the cascade
+    /// after the pipeline is bound, and records draw commands of shadow casters.
+    pub fn record<F>(
+        &self,
+        command_buffer: &mut CommandBuffer<Recording>,
+        shader: &Shader,
+        mut draw: F,
+    ) where F: FnMut(&mut CommandBuffer<Recording>, usize, &Matrix) {
+        let extent = vk::Extent2D { width: SHADOW_MAP_SIZE, height: SHADOW_MAP_SIZE };
+        let clear_values = [
+            vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+        ];
+
+        self.framebuffers
+            .iter()
+            .zip(self.cascades.light_space.iter())
+            .enumerate()
+            .for_each(|(cascade, (framebuffer, light_space))| {
+                command_buffer
+                    .begin_label(&format!("shadow cascade {}", cascade))
+                    .begin_render_pass(
+                        self.render_pass,
+                        *framebuffer,
+                        vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent },
+                        &clear_values[..],
+                        vk::SubpassContents::INLINE,
+                    )
+                    .bind_pipeline(vk::PipelineBindPoint::GRAPHICS, shader.pipeline);
+                Render::set_viewport(command_buffer, extent);
+                draw(command_buffer, cascade, light_space);
+                command_buffer
+                    .end_render_pass()
+                    .end_label();
+            });
+    }
+
+    pub(super) fn descriptor_image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler)
+            .image_view(self.views[CASCADE_COUNT])
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+            .build()
+    }
+
+    #[inline]
+    pub(super) fn descriptor_buffer_info(&self, framebuffer_index: usize) -> vk::DescriptorBufferInfo {
+        self.uniform.descriptor_info(framebuffer_index)
+    }
+
+    pub(super) unsafe fn destroy(self, vulkan: &Vulkan) {
+        let device = &vulkan.device;
+        self.uniform.destroy(vulkan);
+        device.destroy_sampler(self.sampler, None);
+        self.framebuffers.iter().for_each(|framebuffer| device.destroy_framebuffer(*framebuffer, None));
+        self.views.iter().for_each(|view| device.destroy_image_view(*view, None));
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+        device.destroy_render_pass(self.render_pass, None);
+    }
+}
+
+impl Cascades {
+    fn fit(frustum: &ViewFrustum, sun_direction: XYZ<f32>) -> Self {
+        let direction = frustum.direction.normalize();
+        let right = direction.cross(frustum.up).normalize();
+        let up = right.cross(direction);
+        let tan_half_fov = (frustum.fov_y * 0.5).tan();
+
+        let mut cascades = Cascades {
+            light_space: [Mat4::IDENTITY; CASCADE_COUNT],
+            splits: [0.0; CASCADE_COUNT],
+            view_direction: XYZW::new(direction.x, direction.y, direction.z, 0.0),
+        };
+
+        (0..CASCADE_COUNT).fold(frustum.near, |near, cascade| {
+            // Practical split scheme.
+            let ratio = (cascade + 1) as f32 / CASCADE_COUNT as f32;
+            let log = frustum.near * (frustum.far / frustum.near).powf(ratio);
+            let uniform = frustum.near + (frustum.far - frustum.near) * ratio;
+            let far = SPLIT_LAMBDA * log + (1.0 - SPLIT_LAMBDA) * uniform;
+
+            // Corners of this range of the view frustum.
+            let mut corners = [XYZ::<f32>::ZERO; 8];
+            [near, far].iter()
+                .flat_map(|depth| {
+                    let half_height = depth * tan_half_fov;
+                    let half_width = half_height * frustum.aspect;
+                    let center = frustum.position + direction * *depth;
+                    [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+                        .iter()
+                        .map(move |(x, y)| {
+                            center + right * (x * half_width) + up * (y * half_height)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .zip(corners.iter_mut())
+                .for_each(|(position, corner)| *corner = position);
+
+            // Bounding sphere keeps the size of the cascade constant while the camera rotates.
+            let center = corners.iter().fold(XYZ::<f32>::ZERO, |sum, corner| sum + *corner) / 8.0;
+            let radius = corners.iter()
+                .map(|corner| corner.distance(center))
+                .fold(0.0_f32, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            cascades.light_space[cascade] = light_space(center, radius, sun_direction);
+            cascades.splits[cascade] = far;
+
+            far
+        });
+
+        cascades
+    }
+}
+
+/// Orthographic projection from the sun which covers the sphere,
+/// snapped to texels of the shadow map to avoid shimmering edges.
+fn light_space(center: XYZ<f32>, radius: f32, sun_direction: XYZ<f32>) -> Matrix {
+    let forward = sun_direction.normalize();
+    let up_hint = if forward.y.abs() < 0.99 { XYZ::new(0.0, 1.0, 0.0) } else { XYZ::new(1.0, 0.0, 0.0) };
+    let right = up_hint.cross(forward).normalize();
+    let up = forward.cross(right);
+    let eye = center - forward * (radius + CASTER_MARGIN);
+    let depth = 2.0 * radius + CASTER_MARGIN;
+
+    // x and y are scaled into [-1, 1], and z into [0, 1].
+    let row = |axis: XYZ<f32>, factor: f32| {
+        [axis.x * factor, axis.y * factor, axis.z * factor, -axis.dot(eye) * factor]
+    };
+    let mut rows = [
+        row(right, 1.0 / radius),
+        row(up, 1.0 / radius),
+        row(forward, 1.0 / depth),
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    // Snap the origin of world coordinates to texels.
+    let texels = SHADOW_MAP_SIZE as f32 * 0.5;
+    rows[0][3] = (rows[0][3] * texels).round() / texels;
+    rows[1][3] = (rows[1][3] * texels).round() / texels;
+
+    Mat4::from(rows).transpose()
+}
+
+/// Only positions, the first attribute of `V`, are read.
+pub unsafe fn load<V: VertexInput>(vulkan: &Vulkan, render: &Render) -> Result<Shader, ReflectErr> {
+    // Shadow casters need no descriptors, only the light space matrix in push constants.
+    // Depth only, so there is no fragment shader.
+    // Depth bias reduces shadow acne.
+    GraphicsPipelineBuilder::new()
+        .name("shadow")
+        .vertex_shader(spirv!(render, "shadow", "vert"))
+        .vertex_attributes_of(V::stride(), &V::attributes()[..1])
+        .depth_bias(1.25, 1.75)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .depth_test(true, vk::CompareOp::LESS_OR_EQUAL)
+        .build(vulkan, render.shadow_map.render_pass, 0, render.pipeline_cache)
+}
@@ -0,0 +1,13 @@
+# version 450
+
+// input
+layout(location = 0) in vec3 in_position;
+
+// Transform from object coordinates into the clip space of a cascade.
+layout(push_constant) uniform Object {
+    mat4 light_space;
+} OBJ;
+
+void main() {
+    gl_Position = OBJ.light_space * vec4(in_position, 1.0);
+}
@@ -0,0 +1,224 @@
+//! Sampled images uploaded from the host, e.g. albedo of materials.
+//!
+//! Uploads are recorded into a command buffer of the caller and go through a staging
+//! buffer, which is retired by `finish_upload` and destroyed after the frame completes.
+
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use super::Vulkan;
+use super::Render;
+use super::HostBuffer;
+use super::Destroy;
+use super::DescriptorImage;
+use super::ImageResource;
+
+use std::ptr;
+use std::sync::Arc;
+
+/// 2D RGBA image with a sampler and without mipmaps.
+pub struct Texture {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+    extent: vk::Extent2D,
+    /// Until the upload completes.
+    staging: Option<HostBuffer>,
+}
+
+impl Texture {
+    /// Color textures are sRGB, and data textures, e.g. normal maps, are linear.
+    pub const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+    pub const DATA_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    /// Create a texture and record the upload of `pixels`, which are rows of RGBA8 from the
+    /// top left, into the command buffer. It is in `SHADER_READ_ONLY_OPTIMAL` for fragment
+    /// shaders after the command buffer.
+    /// # Safety
+    /// Call `finish_upload` after the command buffer completes.
+    pub unsafe fn new(
+        vulkan: &Vulkan,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        pixels: &[u8],
+    ) -> Self {
+        let device = &vulkan.device;
+        let size = extent.width as usize * extent.height as usize * 4;
+        assert_eq!(pixels.len(), size, "The texture must have 4 bytes for each pixel.");
+
+        let staging = HostBuffer::new(vulkan, vk::BufferUsageFlags::TRANSFER_SRC, size as u64, 1);
+        ptr::copy_nonoverlapping(pixels.as_ptr(), staging.region(0), size);
+
+        let info = vk::ImageCreateInfo::builder()
+            .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .format(format)
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .mip_levels(1)
+            .array_layers(1)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .queue_family_indices(&[]);
+        let image = device.create_image(&info, None).unwrap();
+
+        let requirements = device.get_image_memory_requirements(image);
+        let memory_type_index = vulkan.physical_device
+            .memory_type_index(requirements.memory_type_bits, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .unwrap();
+        let info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_type_index)
+            .allocation_size(requirements.size);
+        let memory = device.allocate_memory(&info, None).unwrap();
+        device.bind_image_memory(image, memory, 0).unwrap();
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .format(format)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .subresource_range(subresource_range);
+        let view = device.create_image_view(&info, None).unwrap();
+
+        let info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .min_lod(0.0)
+            .max_lod(0.0);
+        let sampler = device.create_sampler(&info, None).unwrap();
+
+        // UNDEFINED -> TRANSFER_DST_OPTIMAL -> copy -> SHADER_READ_ONLY_OPTIMAL
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)
+                .build()
+        };
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            )],
+        );
+
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build()
+            )
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+            .build();
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            staging.handle,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            )],
+        );
+
+        Self { image, memory, view, sampler, extent, staging: Some(staging) }
+    }
+
+    /// Retire the staging buffer, which is destroyed after the frame being recorded completes.
+    /// # Safety
+    /// The command buffer passed to `new` must be the one of the frame being recorded, or
+    /// have completed.
+    pub unsafe fn finish_upload(&mut self, render: &mut Render) {
+        if let Some(staging) = self.staging.take() {
+            render.retire(Arc::new(staging));
+        }
+    }
+
+    pub fn set_name(&self, vulkan: &Vulkan, name: &str) {
+        vulkan.set_object_name(self.image, name);
+        vulkan.set_object_name(self.memory, &format!("{} memory", name));
+        vulkan.set_object_name(self.view, &format!("{} view", name));
+        vulkan.set_object_name(self.sampler, &format!("{} sampler", name));
+    }
+
+    #[inline]
+    pub fn extent(&self) -> vk::Extent2D { self.extent }
+
+    #[inline]
+    pub fn sampler(&self) -> vk::Sampler { self.sampler }
+
+    /// # Safety
+    /// The device must have finished using the texture.
+    pub unsafe fn destroy(self, vulkan: &Vulkan) {
+        let device = &vulkan.device;
+        if let Some(staging) = self.staging {
+            staging.destroy(vulkan);
+        }
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+impl Destroy for Texture {
+    #[inline]
+    unsafe fn destroy(self, vulkan: &Vulkan) { Texture::destroy(self, vulkan) }
+}
+
+impl ImageResource for Texture {
+    #[inline]
+    fn image_handle(&self) -> vk::Image { self.image }
+}
+
+impl DescriptorImage for Texture {
+    #[inline]
+    fn descriptor_image_view(&self) -> vk::ImageView { self.view }
+}